@@ -0,0 +1,6 @@
+CREATE TABLE IF NOT EXISTS servers (
+            server_id VARCHAR(250) PRIMARY KEY NOT NULL,
+            day_start_hour INTEGER NOT NULL DEFAULT 6,
+            timezone VARCHAR(250) NOT NULL DEFAULT 'UTC',
+            use_24h BOOLEAN NOT NULL DEFAULT TRUE
+);
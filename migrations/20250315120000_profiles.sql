@@ -0,0 +1,4 @@
+CREATE TABLE IF NOT EXISTS profiles (
+            user_id VARCHAR(250) PRIMARY KEY NOT NULL,
+            timezone VARCHAR(250) NOT NULL
+);
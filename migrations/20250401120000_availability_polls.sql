@@ -0,0 +1,19 @@
+CREATE TABLE IF NOT EXISTS polls (
+            poll_id INTEGER PRIMARY KEY AUTOINCREMENT,
+            server_id VARCHAR(250) NOT NULL,
+            title VARCHAR(250) NOT NULL,
+            owner VARCHAR(250) NOT NULL,
+            start_time VARCHAR(250) NOT NULL,
+            end_time VARCHAR(250) NOT NULL
+);
+CREATE INDEX idx_polls_server_id
+ON polls (server_id);
+CREATE TABLE IF NOT EXISTS availability (
+            poll_id INTEGER NOT NULL,
+            user_id VARCHAR(250) NOT NULL,
+            slot_start VARCHAR(250) NOT NULL,
+            PRIMARY KEY (poll_id, user_id, slot_start),
+            FOREIGN KEY (poll_id)
+                REFERENCES polls (poll_id)
+                ON DELETE CASCADE
+);
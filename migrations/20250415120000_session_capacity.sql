@@ -0,0 +1,12 @@
+ALTER TABLE sessions ADD COLUMN max_participants INTEGER;
+ALTER TABLE users ADD COLUMN joined_at VARCHAR(250);
+CREATE TABLE IF NOT EXISTS waitlist (
+            waitlist_id INTEGER PRIMARY KEY AUTOINCREMENT,
+            session_id INTEGER NOT NULL,
+            user_id VARCHAR(250) NOT NULL,
+            user_photo VARCHAR(250) NOT NULL,
+            UNIQUE (session_id, user_id),
+            FOREIGN KEY (session_id)
+                REFERENCES sessions (session_id)
+                ON DELETE CASCADE
+);
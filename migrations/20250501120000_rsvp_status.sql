@@ -0,0 +1,2 @@
+ALTER TABLE users ADD COLUMN status VARCHAR(250) NOT NULL DEFAULT 'going';
+ALTER TABLE users ADD COLUMN eta VARCHAR(250);
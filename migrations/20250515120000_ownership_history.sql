@@ -0,0 +1,11 @@
+CREATE TABLE IF NOT EXISTS ownership_history (
+            history_id INTEGER PRIMARY KEY AUTOINCREMENT,
+            session_id INTEGER NOT NULL,
+            from_owner VARCHAR(250) NOT NULL,
+            to_owner VARCHAR(250) NOT NULL,
+            reason VARCHAR(250) NOT NULL,
+            changed_at VARCHAR(250) NOT NULL,
+            FOREIGN KEY (session_id)
+                REFERENCES sessions (session_id)
+                ON DELETE CASCADE
+);
@@ -0,0 +1,7 @@
+CREATE TABLE IF NOT EXISTS roles (
+            server_id VARCHAR(250) NOT NULL,
+            user_id VARCHAR(250) NOT NULL,
+            role VARCHAR(250) NOT NULL,
+            PRIMARY KEY (server_id, user_id)
+);
+ALTER TABLE sessions ADD COLUMN locked BOOLEAN NOT NULL DEFAULT FALSE;
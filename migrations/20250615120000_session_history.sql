@@ -0,0 +1,23 @@
+CREATE TABLE IF NOT EXISTS session_history (
+            session_id INTEGER PRIMARY KEY,
+            server_id VARCHAR(250) NOT NULL,
+            title VARCHAR(250) NOT NULL,
+            start_time VARCHAR(250) NOT NULL,
+            end_time VARCHAR(250) NOT NULL,
+            owner VARCHAR(250) NOT NULL,
+            game VARCHAR(250),
+            max_participants INTEGER,
+            archived_at VARCHAR(250) NOT NULL
+);
+CREATE INDEX idx_session_history_server_id
+ON session_history (server_id);
+CREATE TABLE IF NOT EXISTS user_history (
+            session_id INTEGER NOT NULL,
+            user_id VARCHAR(250) NOT NULL,
+            status VARCHAR(250) NOT NULL,
+            joined_at VARCHAR(250),
+            PRIMARY KEY (session_id, user_id),
+            FOREIGN KEY (session_id)
+                REFERENCES session_history (session_id)
+                ON DELETE CASCADE
+);
@@ -0,0 +1,2 @@
+ALTER TABLE sessions ADD COLUMN description TEXT;
+ALTER TABLE session_history ADD COLUMN description TEXT;
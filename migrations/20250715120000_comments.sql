@@ -0,0 +1,12 @@
+CREATE TABLE IF NOT EXISTS comments (
+            comment_id INTEGER PRIMARY KEY AUTOINCREMENT,
+            session_id INTEGER NOT NULL,
+            user_id VARCHAR(250) NOT NULL,
+            body TEXT NOT NULL,
+            created_at VARCHAR(250) NOT NULL,
+            FOREIGN KEY (session_id)
+                REFERENCES sessions (session_id)
+                ON DELETE CASCADE
+);
+CREATE INDEX idx_comments_session_id
+ON comments (session_id);
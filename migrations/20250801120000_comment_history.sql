@@ -0,0 +1,12 @@
+CREATE TABLE IF NOT EXISTS comment_history (
+            comment_id INTEGER PRIMARY KEY,
+            session_id INTEGER NOT NULL,
+            user_id VARCHAR(250) NOT NULL,
+            body TEXT NOT NULL,
+            created_at VARCHAR(250) NOT NULL,
+            FOREIGN KEY (session_id)
+                REFERENCES session_history (session_id)
+                ON DELETE CASCADE
+);
+CREATE INDEX idx_comment_history_session_id
+ON comment_history (session_id);
@@ -1,7 +1,15 @@
+use crate::component::agenda::{get_agenda_timezone, Agenda};
 use crate::component::modal::new_event_modal::NewEventModal;
+use crate::component::modal::poll_modal::PollModal;
 use crate::component::navbar::NavBar;
-use crate::component::{calendar::Calendar, model::GamingSession};
-use crate::obf_util::UrlParams;
+use crate::component::{
+    calendar::Calendar,
+    model::{AvailabilityPoll, GamingSession, Role, ServerSettings},
+};
+use crate::error::AppError;
+use crate::obf_util::{UrlParams, UserLink};
+use chrono::{DateTime, Utc};
+use futures::future::join3;
 use leptos::either::Either;
 use leptos::prelude::*;
 use leptos_meta::{provide_meta_context, MetaTags, Stylesheet, Title};
@@ -18,7 +26,15 @@ pub struct GlobalState {
     pub url_params: UrlParams,
     #[store(key: i64 = |s| s.session_id.clone())]
     pub calendar_events: Vec<GamingSession>,
-    pub offset: usize,
+    pub server_settings: ServerSettings,
+    pub user_timezone: Option<String>,
+    /// the user's role on this server
+    pub role: Role,
+    pub polls: Vec<AvailabilityPoll>,
+    /// poll whose heatmap is painted over the calendar
+    pub active_poll: Option<i64>,
+    /// times selected by dragging over the calendar, picked up by the new event modal
+    pub new_event_draft: Option<(DateTime<Utc>, DateTime<Utc>)>,
 }
 
 pub fn shell(options: LeptosOptions) -> impl IntoView {
@@ -58,6 +74,7 @@ pub fn App() -> impl IntoView {
                 <Routes fallback=|| "Page not found.".into_view()>
                     <Route path=StaticSegment("") view=WelcomePage/>
                     <Route path=path!(":id") view=HomePage/>
+                    <Route path=path!("agenda/:id") view=AgendaPage/>
                 </Routes>
             </main>
         </Router>
@@ -67,8 +84,6 @@ pub fn App() -> impl IntoView {
 /// Renders the home page of your application.
 #[component]
 fn HomePage() -> impl IntoView {
-    const OFFSET_SIZE: usize = 6;
-
     // parse params from url
     let params = use_params_map();
     let url_params = UrlParams::decode_url(params.read_untracked().get("id").unwrap_or_default());
@@ -78,21 +93,48 @@ fn HomePage() -> impl IntoView {
         {
             match url_params {
                 Ok(params) => {
-                    provide_context(Store::new(GlobalState {
-                        url_params: params,
-                        calendar_events: vec![],
-                        offset: OFFSET_SIZE,
-                    }));
                     Either::Left(view! {
-                        <div class="relative z-4">
-                            <NavBar />
-                        </div>
-                        <div class="relative z-0">
-                            <Calendar />
-                        </div>
-                        <div class="relative z-4">
-                            <NewEventModal />
-                        </div>
+                        // settings decide the day window, so the calendar waits for them
+                        <Await
+                            future=join3(
+                                get_server_settings(params.encode()),
+                                get_profile_timezone(params.encode()),
+                                get_user_role(params.encode()),
+                            )
+                            let:res
+                        >
+                            {
+                                let (settings, user_timezone, role) = res;
+                                // forged or unsigned links are refused once links are signed
+                                if let Err(ServerFnError::WrappedServerError(AppError::Forbidden(_))) = role {
+                                    return Either::Right(view! { <InvalidUrlPage /> });
+                                }
+                                provide_context(Store::new(GlobalState {
+                                    url_params: params.clone(),
+                                    calendar_events: vec![],
+                                    server_settings: settings.clone().unwrap_or_default(),
+                                    user_timezone: user_timezone.clone().unwrap_or_default(),
+                                    role: role.clone().unwrap_or_default(),
+                                    polls: vec![],
+                                    active_poll: None,
+                                    new_event_draft: None,
+                                }));
+                                Either::Left(view! {
+                                    <div class="relative z-4">
+                                        <NavBar />
+                                    </div>
+                                    <div class="relative z-0">
+                                        <Calendar />
+                                    </div>
+                                    <div class="relative z-4">
+                                        <NewEventModal />
+                                    </div>
+                                    <div class="relative z-4">
+                                        <PollModal />
+                                    </div>
+                                })
+                            }
+                        </Await>
                     })
                 },
                 Err(_) => {
@@ -106,6 +148,25 @@ fn HomePage() -> impl IntoView {
     }
 }
 
+// Agenda page -- the user's sessions on every server, from their cross-server link
+#[component]
+fn AgendaPage() -> impl IntoView {
+    let params = use_params_map();
+    let link = params.read_untracked().get("id").unwrap_or_default();
+
+    match UserLink::decode_url(link.clone()) {
+        Ok(_) => Either::Left(view! {
+            <NavBar />
+            <Await future=get_agenda_timezone(link.clone()) let:user_timezone>
+                <Agenda link=link.clone() user_timezone=user_timezone.clone().unwrap_or_default() />
+            </Await>
+        }),
+        Err(_) => Either::Right(view! {
+            <InvalidUrlPage />
+        }),
+    }
+}
+
 // Welcome page -- for routes without server_id, user_id
 #[component]
 fn WelcomePage() -> impl IntoView {
@@ -123,3 +184,89 @@ fn InvalidUrlPage() -> impl IntoView {
         <p>Invalid Url. Please Try Again.</p>
     }
 }
+
+#[server]
+#[cfg_attr(
+    feature = "ssr",
+    tracing::instrument(
+        name = "get_server_settings",
+        skip_all,
+        fields(
+            server_id = tracing::field::Empty,
+            user_id = tracing::field::Empty
+        ),
+        err
+    )
+)]
+pub async fn get_server_settings(link: String) -> Result<ServerSettings, ServerFnError<AppError>> {
+    use crate::{config::pool, dao::sqlite_util::SqliteClient, obf_util::verify_caller};
+
+    let caller = verify_caller(&link)?;
+
+    let pool = pool()?;
+    let client = SqliteClient::from_pool(pool).await;
+
+    match client.get_server(&caller.get_server_id()).await {
+        Ok(Some(record)) => Ok(ServerSettings::from(&record)),
+        Ok(None) => Ok(crate::config::default_settings()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+#[server]
+#[cfg_attr(
+    feature = "ssr",
+    tracing::instrument(
+        name = "get_profile_timezone",
+        skip_all,
+        fields(
+            server_id = tracing::field::Empty,
+            user_id = tracing::field::Empty
+        ),
+        err
+    )
+)]
+pub async fn get_profile_timezone(link: String) -> Result<Option<String>, ServerFnError<AppError>> {
+    use crate::{config::pool, dao::sqlite_util::SqliteClient, obf_util::verify_caller};
+
+    let caller = verify_caller(&link)?;
+
+    let pool = pool()?;
+    let client = SqliteClient::from_pool(pool).await;
+
+    match client.get_profile(&caller.get_user_id()).await {
+        Ok(profile) => Ok(profile.map(|p| p.timezone)),
+        Err(e) => Err(e.into()),
+    }
+}
+
+#[server]
+#[cfg_attr(
+    feature = "ssr",
+    tracing::instrument(
+        name = "get_user_role",
+        skip_all,
+        fields(
+            server_id = tracing::field::Empty,
+            user_id = tracing::field::Empty
+        ),
+        err
+    )
+)]
+pub async fn get_user_role(link: String) -> Result<Role, ServerFnError<AppError>> {
+    use crate::{config::pool, dao::sqlite_util::SqliteClient, obf_util::verify_caller};
+
+    // also how the calendar page checks its link
+    let caller = verify_caller(&link)?;
+
+    let pool = pool()?;
+    let client = SqliteClient::from_pool(pool).await;
+
+    match client
+        .get_role(&caller.get_server_id(), &caller.get_user_id())
+        .await
+    {
+        Ok(record) => Ok(Role::from(record)),
+        Err(e) => Err(e.into()),
+    }
+}
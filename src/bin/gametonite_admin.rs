@@ -0,0 +1,255 @@
+use std::{fs, path::PathBuf};
+
+use anyhow::{bail, Context, Result};
+use chrono::{Duration, Utc};
+use clap::{Parser, Subcommand};
+use gaming_calendar_website::{
+    dao::{dump::ServerDump, sqlite_util::SqliteClient},
+    obf_util::{UrlParams, UserLink},
+};
+use sqlx::sqlite::SqlitePoolOptions;
+
+/**
+ * Maintenance tool for the GameTonite database
+ */
+#[derive(Parser)]
+#[command(name = "gametonite-admin")]
+struct Cli {
+    /// database to work on
+    #[arg(long, env = "DATABASE_URL", default_value = "sqlite://sessions.db")]
+    database_url: String,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// List every server with data
+    Servers,
+    /// List a server's sessions
+    Sessions { server_id: String },
+    /// Delete a session with its participants
+    DeleteSession { session_id: i64 },
+    /// Remove a user from a session, promoting the waitlist and handing over hosting if needed
+    RemoveUser { session_id: i64, user_id: String },
+    /// Delete sessions that ended more than N days ago
+    Purge {
+        #[arg(long)]
+        days: i64,
+        /// move them into the history tables instead
+        #[arg(long)]
+        archive: bool,
+    },
+    /// Print a signed link for a user
+    Link {
+        server_id: String,
+        user_id: String,
+        #[arg(long, env = "LINK_SECRET", hide_env_values = true)]
+        secret: String,
+        #[arg(long, default_value = "http://127.0.0.1:3000")]
+        base_url: String,
+    },
+    /// Print a signed link to a user's agenda, listing their sessions on every server
+    AgendaLink {
+        user_id: String,
+        #[arg(long, env = "LINK_SECRET", hide_env_values = true)]
+        secret: String,
+        #[arg(long, default_value = "http://127.0.0.1:3000")]
+        base_url: String,
+    },
+    /// Give a user a role on a server (admin, moderator or member)
+    SetRole {
+        server_id: String,
+        user_id: String,
+        role: String,
+    },
+    /// Change a server's calendar settings, options that aren't given are kept
+    ServerSettings {
+        server_id: String,
+        /// hour the calendar day starts at, 0 to 23
+        #[arg(long)]
+        day_start_hour: Option<i64>,
+        /// IANA timezone name, e.g. Europe/Berlin
+        #[arg(long)]
+        timezone: Option<String>,
+        /// show times on a 24h clock
+        #[arg(long)]
+        use_24h: Option<bool>,
+    },
+    /// Write a server's data as JSON, to stdout if no file is given
+    Dump {
+        server_id: String,
+        #[arg(long)]
+        out: Option<PathBuf>,
+    },
+    /// Load a server's data from a JSON dump
+    Restore {
+        file: PathBuf,
+        /// delete the server's existing data first
+        #[arg(long)]
+        replace: bool,
+    },
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let cli = Cli::parse();
+
+    let pool = SqlitePoolOptions::new()
+        .connect(&cli.database_url)
+        .await
+        .with_context(|| format!("could not open {}", cli.database_url))?;
+    sqlx::migrate!()
+        .run(&pool)
+        .await
+        .context("could not run SQLx migrations")?;
+    let client = SqliteClient::from_pool(pool).await;
+
+    match cli.command {
+        Command::Servers => {
+            for server_id in client.get_server_ids().await? {
+                println!("{server_id}");
+            }
+        }
+        Command::Sessions { server_id } => {
+            for s in client.get_sessions(&server_id).await? {
+                let session_id = s.session_id.unwrap_or_default();
+                let players = client.get_session_users(session_id).await?.len();
+                println!(
+                    "{}\t{}\t{} - {}\towner: {}\tplayers: {}{}",
+                    session_id,
+                    s.title,
+                    s.start_time,
+                    s.end_time,
+                    s.owner,
+                    players,
+                    if s.locked { "\tlocked" } else { "" },
+                );
+            }
+        }
+        Command::DeleteSession { session_id } => {
+            if client.get_session(session_id).await?.is_none() {
+                bail!("session {session_id} not found");
+            }
+            client.delete_session(session_id).await?;
+            println!("deleted session {session_id}");
+        }
+        Command::RemoveUser {
+            session_id,
+            user_id,
+        } => {
+            let outcome = client.remove_participant(session_id, &user_id).await?;
+            println!("removed {user_id} from session {session_id}");
+            if let Some(promoted) = outcome.promoted {
+                println!("{promoted} was promoted from the waitlist");
+            }
+            if let Some(new_owner) = outcome.new_owner {
+                println!("{new_owner} is the new owner");
+            }
+        }
+        Command::Purge { days, archive } => {
+            if days < 0 {
+                bail!("days must not be negative");
+            }
+            let cutoff = Utc::now() - Duration::days(days);
+            if archive {
+                let archived = client.archive_sessions_ended_before(cutoff).await?;
+                println!("archived {archived} sessions that ended before {cutoff}");
+            } else {
+                let deleted = client.delete_sessions_ended_before(cutoff).await?;
+                println!("deleted {deleted} sessions that ended before {cutoff}");
+            }
+        }
+        Command::Link {
+            server_id,
+            user_id,
+            secret,
+            base_url,
+        } => {
+            println!(
+                "{}/{}",
+                base_url.trim_end_matches('/'),
+                UrlParams::sign_url(&server_id, &user_id, &secret)
+            );
+        }
+        Command::AgendaLink {
+            user_id,
+            secret,
+            base_url,
+        } => {
+            println!(
+                "{}/agenda/{}",
+                base_url.trim_end_matches('/'),
+                UserLink::sign_url(&user_id, &secret)
+            );
+        }
+        Command::SetRole {
+            server_id,
+            user_id,
+            role,
+        } => match role.as_str() {
+            "member" => {
+                client.delete_role(&server_id, &user_id).await?;
+                println!("{user_id} is now member on {server_id}");
+            }
+            "admin" | "moderator" => {
+                client.upsert_role(&server_id, &user_id, &role).await?;
+                println!("{user_id} is now {role} on {server_id}");
+            }
+            _ => bail!("unknown role: {role}"),
+        },
+        Command::ServerSettings {
+            server_id,
+            day_start_hour,
+            timezone,
+            use_24h,
+        } => {
+            // servers without settings follow the website's configured defaults, which this
+            // tool doesn't know, so new ones need every option
+            let current = client.get_server(&server_id).await?;
+            let (Some(day_start_hour), Some(timezone), Some(use_24h)) = (
+                day_start_hour.or(current.as_ref().map(|r| r.day_start_hour)),
+                timezone.or(current.as_ref().map(|r| r.timezone.clone())),
+                use_24h.or(current.as_ref().map(|r| r.use_24h)),
+            ) else {
+                bail!(
+                    "{server_id} has no settings yet, pass --day-start-hour, --timezone and --use-24h"
+                );
+            };
+            let record = client
+                .upsert_server(&server_id, day_start_hour, &timezone, use_24h)
+                .await?;
+            println!(
+                "{}: day starts at {}:00 in {}, {} clock",
+                server_id,
+                record.day_start_hour,
+                record.timezone,
+                if record.use_24h { "24h" } else { "12h" }
+            );
+        }
+        Command::Dump { server_id, out } => {
+            let dump = client.dump_server(&server_id).await?;
+            let json = serde_json::to_string_pretty(&dump)?;
+            match out {
+                Some(path) => fs::write(&path, json)
+                    .with_context(|| format!("could not write {}", path.display()))?,
+                None => println!("{json}"),
+            }
+        }
+        Command::Restore { file, replace } => {
+            let json = fs::read_to_string(&file)
+                .with_context(|| format!("could not read {}", file.display()))?;
+            let dump: ServerDump = serde_json::from_str(&json).context("invalid dump")?;
+            client.restore_server(&dump, replace).await?;
+            println!(
+                "restored {} sessions and {} polls for {}",
+                dump.sessions.len(),
+                dump.polls.len(),
+                dump.server_id
+            );
+        }
+    }
+
+    Ok(())
+}
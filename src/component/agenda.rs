@@ -0,0 +1,181 @@
+use chrono::Utc;
+use chrono_tz::Tz;
+use leptos::prelude::*;
+
+use crate::{
+    component::{
+        model::AgendaEntry,
+        time_util::{get_browser_timezone, group_by_day, time_format},
+    },
+    error::{error_message, AppError},
+};
+
+/**
+ * Every upcoming session the user attends, on any server, grouped by the day they start.
+ * `link` is the user's cross-server link, see `UserLink`
+ */
+#[component]
+pub fn Agenda(link: String, user_timezone: Option<String>) -> impl IntoView {
+    let profile_tz = user_timezone.and_then(|tz| tz.parse::<Tz>().ok());
+    // the browser's timezone is only known after hydration
+    let (tz, set_tz) = signal(profile_tz.unwrap_or(Tz::UTC));
+    Effect::new(move |_| {
+        if profile_tz.is_none() {
+            if let Some(browser_tz) = get_browser_timezone() {
+                set_tz.set(browser_tz);
+            }
+        }
+    });
+
+    view! {
+        <div class="pt-20 px-4 max-w-3xl mx-auto">
+            <h1 class="text-2xl font-bold mb-4">My sessions</h1>
+            <Await future=get_agenda(link) let:res>
+            {
+                match res.clone() {
+                    Ok(entries) if entries.is_empty() => view! {
+                        <p class="opacity-70">"You haven't joined any upcoming sessions."</p>
+                    }.into_any(),
+                    Ok(entries) => (move || {
+                        let tz = tz.get();
+                        group_by_day(entries.clone(), tz, |e| e.session.start_time)
+                            .into_iter()
+                            .map(|(day, entries)| view! {
+                                <h2 class="text-lg font-semibold mt-6 mb-2">
+                                    { day.format("%A, %B %-d").to_string() }
+                                </h2>
+                                <ul class="list bg-base-100 rounded-box shadow-md">
+                                    { entries.into_iter().map(|e| view! { <AgendaRow entry=e tz=tz /> }).collect_view() }
+                                </ul>
+                            })
+                            .collect_view()
+                    }).into_any(),
+                    Err(e) => view! {
+                        <div role="alert" class="alert alert-error">{ error_message(&e) }</div>
+                    }.into_any(),
+                }
+            }
+            </Await>
+        </div>
+    }
+}
+
+#[component]
+fn AgendaRow(entry: AgendaEntry, tz: Tz) -> impl IntoView {
+    let session = entry.session;
+    let format = time_format(true);
+    let times = format!(
+        "{} - {}",
+        session.start_time.with_timezone(&tz).format(format),
+        session
+            .end_time
+            .with_timezone(&tz)
+            .format(&format!("{format} %Z")),
+    );
+    let players = match session.max_participants {
+        Some(max) => format!("{}/{} players", session.count_attending(), max),
+        None => format!("{} players", session.count_attending()),
+    };
+    let running = session.start_time <= Utc::now();
+
+    view! {
+        <li class="list-row items-center">
+            <div class="tabular-nums text-sm w-36">{ times }</div>
+            <div>
+                <div class="font-semibold">
+                    { session.title.clone() }
+                    { running.then(|| view! { <span class="badge badge-sm badge-success ml-2">Now</span> }) }
+                </div>
+                <div class="text-xs opacity-70">
+                    { session.game.clone().map(|game| format!("{game} · ")) }
+                    { players }
+                    { format!(" · server {}", session.server_id) }
+                </div>
+            </div>
+            <a class="btn btn-sm btn-ghost" href={ format!("/{}", entry.calendar_url) }>Open calendar</a>
+        </li>
+    }
+}
+
+/**
+ * The user a cross-server link was made for, with the secret it's signed with
+ */
+#[cfg(feature = "ssr")]
+fn verify_agenda_link(link: String) -> Result<(String, String), AppError> {
+    use crate::{config::link_secret, obf_util::UserLink};
+
+    let link = UserLink::decode_url(link).map_err(|_| AppError::validation("Invalid link"))?;
+    // the agenda spans every server, so unlike calendars it's never shown for unsigned links
+    let Some(secret) = link_secret() else {
+        return Err(AppError::forbidden(
+            "Agendas need a link secret to be configured",
+        ));
+    };
+    if !link.verify(&secret) {
+        return Err(AppError::forbidden(
+            "This link isn't valid, ask the bot for a new one",
+        ));
+    }
+    let user_id = link.get_user_id();
+    tracing::Span::current().record("user_id", &user_id);
+    Ok((user_id, secret))
+}
+
+/**
+ * The timezone from the user's profile, for their cross-server link
+ */
+#[server]
+#[cfg_attr(
+    feature = "ssr",
+    tracing::instrument(
+        name = "get_agenda_timezone",
+        skip_all,
+        fields(user_id = tracing::field::Empty),
+        err
+    )
+)]
+pub async fn get_agenda_timezone(link: String) -> Result<Option<String>, ServerFnError<AppError>> {
+    use crate::{config::pool, dao::sqlite_util::SqliteClient};
+
+    let (user_id, _) = verify_agenda_link(link)?;
+
+    let pool = pool()?;
+    let client = SqliteClient::from_pool(pool).await;
+
+    Ok(client.get_profile(&user_id).await?.map(|p| p.timezone))
+}
+
+#[server]
+#[cfg_attr(
+    feature = "ssr",
+    tracing::instrument(
+        name = "get_agenda",
+        skip_all,
+        fields(user_id = tracing::field::Empty),
+        err
+    )
+)]
+pub async fn get_agenda(link: String) -> Result<Vec<AgendaEntry>, ServerFnError<AppError>> {
+    use crate::{
+        component::calendar_events::load_session, config::pool, dao::sqlite_util::SqliteClient,
+        obf_util::UrlParams,
+    };
+
+    let (user_id, secret) = verify_agenda_link(link)?;
+
+    let pool = pool()?;
+    let client = SqliteClient::from_pool(pool).await;
+
+    let mut entries = vec![];
+    for s in client
+        .get_user_upcoming_sessions(&user_id, Utc::now())
+        .await?
+    {
+        entries.push(AgendaEntry {
+            session: load_session(&client, &s).await?,
+            calendar_url: UrlParams::sign_url(&s.server_id, &user_id, &secret),
+        });
+    }
+    tracing::debug!(sessions = entries.len(), "loaded agenda");
+    Ok(entries)
+}
@@ -0,0 +1,491 @@
+use chrono::{DateTime, Duration, FixedOffset, Utc};
+use chrono_tz::Tz;
+use leptos::{logging::log, prelude::*};
+use reactive_stores::Store;
+
+use crate::{
+    app::{GlobalState, GlobalStateStoreFields},
+    component::{
+        model::{AvailabilityPoll, GamingSession},
+        time_util::{calculate_time_pct, day_window, poll_slots},
+    },
+    error::AppError,
+};
+
+#[cfg(feature = "ssr")]
+use crate::dao::sqlite_util::{PollRecord, SessionDetails, SqliteClient};
+
+/**
+ * Loads the availability polls for the displayed day. When a poll is active, paints its
+ * heatmap over the hour grid and lets the user click or drag over hours to mark themselves
+ * as available.
+ */
+#[component]
+pub fn PollOverlay(baseline: ReadSignal<Option<DateTime<Tz>>>, offset: usize) -> impl IntoView {
+    // unpack state
+    let state = expect_context::<Store<GlobalState>>();
+    let link = state.url_params().get_untracked().encode();
+    let polls = state.polls();
+    let active_poll = state.active_poll();
+
+    // while dragging: Some(true) marks hours as available, Some(false) clears them
+    let (painting, set_painting) = signal::<Option<bool>>(None);
+
+    // handle availability updates. The server answers with the re-aggregated poll
+    let set_availability = ServerAction::<SetAvailability>::new();
+    let server_res = set_availability.value();
+    Effect::new(move || match server_res() {
+        Some(Ok(poll)) => polls.update(|v| {
+            if let Some(p) = v.iter_mut().find(|p| p.poll_id == poll.poll_id) {
+                *p = poll;
+            }
+        }),
+        Some(Err(e)) => {
+            log!("{:?}", e);
+        }
+        None => {}
+    });
+
+    // optimistic update so painting feels instant
+    let paint = move |poll_id: i64, slot_start: DateTime<Utc>, available: bool| {
+        polls.update(|v| {
+            if let Some(p) = v.iter_mut().find(|p| p.poll_id == poll_id) {
+                p.set_user_slot(slot_start, available);
+            }
+        });
+        set_availability.dispatch(SetAvailability {
+            poll_id,
+            link: state.url_params().get_untracked().encode(),
+            slot_start,
+            available,
+        });
+    };
+
+    view! {
+        <Show
+            when=move || { baseline().is_some() }
+            fallback=|| ()
+        >
+        {
+            let baseline_date = move || baseline().unwrap();
+            let (window_start, window_end) = day_window(baseline_date(), offset);
+            view! {
+                <Await
+                    future=get_polls(link.clone(), window_start.fixed_offset(), window_end.fixed_offset())
+                    let:res
+                >
+                    {
+                        // if successful, update polls signal
+                        if let Ok(v) = res.as_ref() {
+                            polls.set(v.clone());
+                        }
+                        move || {
+                            let tz = baseline_date().timezone();
+                            polls
+                                .get()
+                                .into_iter()
+                                .find(|p| Some(p.poll_id) == active_poll.get())
+                                .map(|poll| {
+                                    let slots = poll_slots(poll.start_time, poll.end_time, window_start.to_utc(), window_end.to_utc());
+                                    view! {
+                                        <div
+                                            class="absolute inset-0 z-3 select-none"
+                                            on:mouseup=move |_| set_painting(None)
+                                            on:mouseleave=move |_| set_painting(None)
+                                        >
+                                        {
+                                            slots.into_iter().map(|slot| {
+                                                let poll_id = poll.poll_id;
+                                                let count = poll.get_count(slot);
+                                                let is_mine = poll.user_slots.contains(&slot);
+                                                let start_pct = calculate_time_pct(slot.with_timezone(&tz), baseline_date(), offset);
+                                                let end_pct = calculate_time_pct((slot + Duration::hours(1)).with_timezone(&tz), baseline_date(), offset);
+                                                let intensity = (100 * count).checked_div(poll.respondents).unwrap_or(0);
+                                                view! {
+                                                    <div
+                                                        class="absolute left-16 right-0 flex justify-end items-start pr-2 cursor-pointer border-b border-base-100"
+                                                        class:outline-2=is_mine
+                                                        class:outline-secondary=is_mine
+                                                        style={ format!("top: {}%; bottom: {}%; background-color: color-mix(in oklch, var(--color-accent) {}%, transparent);", start_pct * 100., (1. - end_pct) * 100., intensity) }
+                                                        on:mousedown=move |ev| {
+                                                            ev.prevent_default();
+                                                            set_painting(Some(!is_mine));
+                                                            paint(poll_id, slot, !is_mine);
+                                                        }
+                                                        on:mouseenter=move |_| {
+                                                            if let Some(available) = painting.get_untracked() {
+                                                                if available != is_mine {
+                                                                    paint(poll_id, slot, available);
+                                                                }
+                                                            }
+                                                        }
+                                                    >
+                                                        <span class="text-xs text-accent-content">{ format!("{}/{}", count, poll.respondents) }</span>
+                                                    </div>
+                                                }
+                                            }).collect_view()
+                                        }
+                                        </div>
+                                    }
+                                })
+                        }
+                    }
+                </Await>
+            }
+        }
+        </Show>
+    }
+    .into_any()
+}
+
+/**
+ * The poll a server function acts on for the caller, which has to be on the caller's server
+ */
+#[cfg(feature = "ssr")]
+async fn caller_poll(
+    client: &SqliteClient,
+    caller: &crate::obf_util::UrlParams,
+    poll_id: i64,
+) -> Result<PollRecord, AppError> {
+    let record = client
+        .get_poll(poll_id)
+        .await?
+        .ok_or(AppError::not_found("poll"))?;
+    if record.server_id != caller.get_server_id() {
+        return Err(AppError::forbidden("This poll is on another server"));
+    }
+    Ok(record)
+}
+
+/**
+ * Converts a stored poll with the aggregated availability and the user's own slots
+ */
+#[cfg(feature = "ssr")]
+async fn load_poll(
+    client: &SqliteClient,
+    record: &PollRecord,
+    user_id: &str,
+) -> Result<AvailabilityPoll, AppError> {
+    use crate::component::model::SlotCount;
+
+    let parse_time = |t: &str| {
+        DateTime::parse_from_rfc3339(t)
+            .map(|t| t.to_utc())
+            .map_err(AppError::storage)
+    };
+    let poll_id = record.poll_id.ok_or(AppError::storage("poll has no id"))?;
+    let mut heatmap = vec![];
+    for slot in client.get_poll_heatmap(poll_id).await? {
+        heatmap.push(SlotCount {
+            slot_start: parse_time(&slot.slot_start)?,
+            count: slot.count as usize,
+        });
+    }
+    let mut user_slots = vec![];
+    for slot in client.get_user_availability(poll_id, user_id).await? {
+        user_slots.push(parse_time(&slot)?);
+    }
+
+    Ok(AvailabilityPoll {
+        poll_id,
+        server_id: record.server_id.clone(),
+        title: record.title.clone(),
+        owner: record.owner.clone(),
+        start_time: parse_time(&record.start_time)?,
+        end_time: parse_time(&record.end_time)?,
+        heatmap,
+        respondents: client.get_poll_respondent_count(poll_id).await? as usize,
+        user_slots,
+    })
+}
+
+#[server]
+#[cfg_attr(
+    feature = "ssr",
+    tracing::instrument(
+        name = "get_polls",
+        skip_all,
+        fields(
+            server_id = tracing::field::Empty,
+            user_id = tracing::field::Empty
+        ),
+        err
+    )
+)]
+async fn get_polls(
+    link: String,
+    start_time: DateTime<FixedOffset>,
+    end_time: DateTime<FixedOffset>,
+) -> Result<Vec<AvailabilityPoll>, ServerFnError<AppError>> {
+    use crate::{config::pool, obf_util::verify_caller};
+
+    let caller = verify_caller(&link)?;
+    let (server_id, user_id) = (caller.get_server_id(), caller.get_user_id());
+
+    let pool = pool()?;
+    let client = SqliteClient::from_pool(pool).await;
+
+    let records = client
+        .get_polls_in_range(&server_id, start_time.to_utc(), end_time.to_utc())
+        .await?;
+
+    let mut polls = vec![];
+    for record in records.iter() {
+        polls.push(load_poll(&client, record, &user_id).await?);
+    }
+    Ok(polls)
+}
+
+/**
+ * Creates a poll covering whole calendar days, from the day start of `start_date` until the
+ * day start after `end_date`, in the user's timezone
+ */
+#[server]
+#[cfg_attr(
+    feature = "ssr",
+    tracing::instrument(
+        name = "create_poll",
+        skip_all,
+        fields(
+            server_id = tracing::field::Empty,
+            user_id = tracing::field::Empty
+        ),
+        err
+    )
+)]
+pub async fn create_poll(
+    link: String,
+    title: String,
+    start_date: String,
+    end_date: String,
+    timezone: String,
+) -> Result<AvailabilityPoll, ServerFnError<AppError>> {
+    use crate::component::{model::ServerSettings, time_util::day_start};
+    use crate::{config::pool, obf_util::verify_caller};
+    use chrono::NaiveDate;
+
+    // polls are painted hour by hour, so keep them to a couple of weeks
+    const MAX_POLL_DAYS: i64 = 14;
+
+    let caller = verify_caller(&link)?;
+    let (server_id, user_id) = (caller.get_server_id(), caller.get_user_id());
+
+    let pool = pool()?;
+    let client = SqliteClient::from_pool(pool).await;
+
+    let title = title.trim().to_string();
+    if title.is_empty() {
+        return Err(AppError::validation("Poll title is required").into());
+    }
+    let parse_date = |date: &str| {
+        NaiveDate::parse_from_str(date, "%Y-%m-%d")
+            .map_err(|_| AppError::validation(format!("Invalid date: {date}")))
+    };
+    let start = parse_date(&start_date)?;
+    let end = parse_date(&end_date)?;
+    if end < start {
+        return Err(AppError::validation("Poll ends before it starts").into());
+    }
+    if (end - start).num_days() >= MAX_POLL_DAYS {
+        return Err(
+            AppError::validation(format!("Polls can cover at most {MAX_POLL_DAYS} days")).into(),
+        );
+    }
+
+    let settings = client
+        .get_server(&server_id)
+        .await?
+        .map(|r| ServerSettings::from(&r))
+        .unwrap_or_else(crate::config::default_settings);
+    let tz = timezone.parse().unwrap_or(settings.get_tz());
+    let start_time = day_start(tz, start, settings.day_start_hour);
+    let end_time = day_start(tz, end + Duration::days(1), settings.day_start_hour);
+
+    let record = client
+        .create_poll(
+            &server_id,
+            &title,
+            &user_id,
+            &start_time.to_utc().to_rfc3339(),
+            &end_time.to_utc().to_rfc3339(),
+        )
+        .await?;
+
+    Ok(load_poll(&client, &record, &user_id).await?)
+}
+
+/**
+ * Marks or clears one hour of the user's availability, returning the updated poll
+ */
+#[server]
+#[cfg_attr(
+    feature = "ssr",
+    tracing::instrument(
+        name = "set_availability",
+        skip_all,
+        fields(poll_id = %poll_id, user_id = tracing::field::Empty),
+        err
+    )
+)]
+pub async fn set_availability(
+    poll_id: i64,
+    link: String,
+    slot_start: DateTime<Utc>,
+    available: bool,
+) -> Result<AvailabilityPoll, ServerFnError<AppError>> {
+    use crate::{config::pool, obf_util::verify_caller};
+
+    let caller = verify_caller(&link)?;
+    let user_id = caller.get_user_id();
+
+    let pool = pool()?;
+    let client = SqliteClient::from_pool(pool).await;
+
+    let record = caller_poll(&client, &caller, poll_id).await?;
+
+    // slots must be one of the poll's hours
+    let poll_start = DateTime::parse_from_rfc3339(&record.start_time)
+        .map_err(AppError::storage)?
+        .to_utc();
+    let poll_end = DateTime::parse_from_rfc3339(&record.end_time)
+        .map_err(AppError::storage)?
+        .to_utc();
+    let offset_secs = (slot_start - poll_start).num_seconds();
+    if slot_start < poll_start || slot_start >= poll_end || offset_secs % 3600 != 0 {
+        return Err(AppError::validation("That hour is not part of the poll").into());
+    }
+
+    let res = if available {
+        client.add_availability(poll_id, &user_id, slot_start).await
+    } else {
+        client
+            .delete_availability(poll_id, &user_id, slot_start)
+            .await
+    };
+    res?;
+
+    Ok(load_poll(&client, &record, &user_id).await?)
+}
+
+/**
+ * Turns the best slot of a poll into a session owned by the organizer and closes the poll
+ */
+#[server]
+#[cfg_attr(
+    feature = "ssr",
+    tracing::instrument(
+        name = "schedule_poll",
+        skip_all,
+        fields(poll_id = %poll_id, user_id = tracing::field::Empty),
+        err
+    )
+)]
+pub async fn schedule_poll(
+    poll_id: i64,
+    link: String,
+) -> Result<GamingSession, ServerFnError<AppError>> {
+    use crate::component::{
+        model::{RsvpStatus, User},
+        time_util::best_slot,
+    };
+    use crate::{config::pool, obf_util::verify_caller};
+
+    let caller = verify_caller(&link)?;
+    let user_id = caller.get_user_id();
+
+    let pool = pool()?;
+    let client = SqliteClient::from_pool(pool).await;
+
+    let record = caller_poll(&client, &caller, poll_id).await?;
+    if record.owner != user_id {
+        return Err(AppError::forbidden("Only the organizer can schedule a poll").into());
+    }
+
+    let poll = load_poll(&client, &record, &user_id).await?;
+    let (start_time, end_time) =
+        best_slot(&poll.heatmap).ok_or(AppError::conflict("Nobody is available yet"))?;
+
+    let session_record = client
+        .create_session(
+            &poll.server_id,
+            &poll.owner,
+            &SessionDetails {
+                title: poll.title.clone(),
+                start_time: start_time.to_rfc3339(),
+                end_time: end_time.to_rfc3339(),
+                ..Default::default()
+            },
+        )
+        .await?;
+    let session_id = session_record
+        .session_id
+        .ok_or(AppError::storage("session was not created"))?;
+    client
+        .create_session_user(
+            &poll.owner,
+            session_id,
+            "placeholder",
+            RsvpStatus::Going.as_str(),
+        )
+        .await?;
+    client.delete_poll(poll_id).await?;
+
+    let profile = client.get_profile(&poll.owner).await.ok().flatten();
+    let owner = User {
+        name: poll.owner.clone(),
+        picture: "placeholder".to_string(),
+        timezone: profile.map(|p| p.timezone),
+        status: RsvpStatus::Going,
+        eta: None,
+        joined_at: Some(chrono::Utc::now()),
+    };
+    Ok(GamingSession {
+        server_id: poll.server_id,
+        session_id,
+        title: poll.title,
+        start_time,
+        end_time,
+        owner: owner.clone(),
+        participants: vec![owner],
+        game: None,
+        max_participants: None,
+        waitlist: vec![],
+        locked: false,
+        description: None,
+        description_html: None,
+        comment_count: 0,
+    })
+}
+
+/**
+ * Deletes a poll without scheduling it. Only the organizer may do this
+ */
+#[server]
+#[cfg_attr(
+    feature = "ssr",
+    tracing::instrument(
+        name = "delete_poll",
+        skip_all,
+        fields(poll_id = %poll_id, user_id = tracing::field::Empty),
+        err
+    )
+)]
+pub async fn delete_poll(poll_id: i64, link: String) -> Result<(), ServerFnError<AppError>> {
+    use crate::{config::pool, obf_util::verify_caller};
+
+    let caller = verify_caller(&link)?;
+    let user_id = caller.get_user_id();
+
+    let pool = pool()?;
+    let client = SqliteClient::from_pool(pool).await;
+
+    let record = caller_poll(&client, &caller, poll_id).await?;
+    if record.owner != user_id {
+        return Err(AppError::forbidden("Only the organizer can delete a poll").into());
+    }
+
+    match client.delete_poll(poll_id).await {
+        Ok(()) => Ok(()),
+        Err(e) => Err(e.into()),
+    }
+}
@@ -1,31 +1,46 @@
-use chrono::{DateTime, FixedOffset};
+use chrono::DateTime;
+use chrono_tz::Tz;
 use leptos::{html::Div, prelude::*};
 use leptos_use::{
     use_element_size, use_interval_fn, use_scroll, use_window_size, UseElementSizeReturn,
     UseScrollReturn, UseWindowSizeReturn,
 };
 
-use crate::component::{
-    calendar_events::CalendarEvents,
-    hour_grid::HourGrid,
-    time_overlay::TimeOverlay,
-    time_util::{calculate_timebar_bottom, create_baseline, get_local_time},
+use reactive_stores::Store;
+
+use crate::{
+    app::{GlobalState, GlobalStateStoreFields},
+    component::{
+        availability_poll::PollOverlay,
+        calendar_events::CalendarEvents,
+        hour_grid::HourGrid,
+        time_overlay::TimeOverlay,
+        time_util::{calculate_timebar_bottom, create_baseline, get_local_time, get_user_timezone},
+    },
 };
 
 #[component]
 pub fn Calendar() -> impl IntoView {
     // some constants to do with displaying calendar
-    const STARTING_HOUR_OFFSET: usize = 6;
     const SCROLL_OFFSET_PCT: f64 = 0.25;
 
+    // server settings decide where the day starts, the browser decides the timezone
+    let state = expect_context::<Store<GlobalState>>();
+    let settings = state.server_settings().get_untracked();
+    let starting_hour_offset = settings.day_start_hour;
+    let tz = get_user_timezone(&settings);
+
     // get client side time
-    let (time, set_time) =
-        signal::<DateTime<FixedOffset>>(DateTime::from_timestamp(0, 0).unwrap().fixed_offset());
+    let (time, set_time) = signal::<DateTime<Tz>>(
+        DateTime::from_timestamp(0, 0)
+            .unwrap()
+            .with_timezone(&Tz::UTC),
+    );
     let (timebar_bottom, set_timebar_bottom) = signal(0.);
     let (has_scrolled, set_has_scrolled) = signal(0);
 
     // client side baseline time. Updated only once
-    let (baseline, set_baseline) = signal::<Option<DateTime<FixedOffset>>>(None);
+    let (baseline, set_baseline) = signal::<Option<DateTime<Tz>>>(None);
 
     // node ref for scrolling
     let e = NodeRef::<Div>::new();
@@ -42,15 +57,15 @@ pub fn Calendar() -> impl IntoView {
     // so this is necessary.
     Effect::new(move || {
         // set time locally
-        let t = get_local_time();
+        let t = get_local_time(tz);
         set_time(t);
 
         // set current time bar locally
-        let tb = calculate_timebar_bottom(t, STARTING_HOUR_OFFSET);
+        let tb = calculate_timebar_bottom(t, starting_hour_offset);
         set_timebar_bottom(tb);
 
         // set baseline
-        set_baseline(create_baseline(t, STARTING_HOUR_OFFSET).ok());
+        set_baseline(create_baseline(t, starting_hour_offset).ok());
     });
 
     // Weird artifact of rendering, heigh begins at 0 and then is set to viewport height
@@ -74,9 +89,9 @@ pub fn Calendar() -> impl IntoView {
     // Update time every 30s
     let _ = use_interval_fn(
         move || {
-            let t = get_local_time();
+            let t = get_local_time(tz);
             set_time(t);
-            set_timebar_bottom(calculate_timebar_bottom(t, STARTING_HOUR_OFFSET));
+            set_timebar_bottom(calculate_timebar_bottom(t, starting_hour_offset));
         },
         30000,
     );
@@ -86,13 +101,16 @@ pub fn Calendar() -> impl IntoView {
             <div node_ref=e2 class="relative flex-shrink-0">
                 // foreground -- calendar events
                 // ** time() without move || is intentional. Only want it once per load
-                <CalendarEvents baseline={baseline} offset={STARTING_HOUR_OFFSET}/>
+                <CalendarEvents baseline={baseline} offset={starting_hour_offset}/>
+
+                // availability poll heatmap, above events while painting
+                <PollOverlay baseline={baseline} offset={starting_hour_offset}/>
 
                 // background -- hour grid
-                <HourGrid offset={STARTING_HOUR_OFFSET}/>
+                <HourGrid baseline={baseline} offset={starting_hour_offset} use_24h={settings.use_24h}/>
 
                 // overlay -- current time indicator
-                <TimeOverlay bottom_pad_pct={timebar_bottom} time={time} />
+                <TimeOverlay bottom_pad_pct={timebar_bottom} time={time} use_24h={settings.use_24h} />
             </div>
         </div>
     }
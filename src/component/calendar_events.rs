@@ -1,7 +1,7 @@
 use std::sync::Arc;
 
-use chrono::{DateTime, Duration, FixedOffset, Utc};
-use futures::future::join_all;
+use chrono::{DateTime, FixedOffset, Utc};
+use chrono_tz::Tz;
 use leptos::{logging::log, prelude::*};
 use reactive_stores::Store;
 
@@ -9,9 +9,9 @@ use crate::{
     app::{GlobalState, GlobalStateStoreFields},
     component::{
         event_card::EventCard,
-        model::{Game, User},
-        time_util::get_events_stacking,
+        time_util::{day_window, get_events_stacking, get_user_clashes},
     },
+    error::{error_message, AppError},
     obf_util::UrlParamsStoreFields,
 };
 
@@ -21,18 +21,74 @@ use super::model::GamingSession;
  * Gets calendar events from sqlite. Creates event cards for each
  */
 #[component]
-pub fn CalendarEvents(
-    baseline: ReadSignal<Option<DateTime<FixedOffset>>>,
-    offset: usize,
-) -> impl IntoView {
+pub fn CalendarEvents(baseline: ReadSignal<Option<DateTime<Tz>>>, offset: usize) -> impl IntoView {
     // unpack state
     let state = expect_context::<Store<GlobalState>>();
-    let server_id = state.url_params().server_id().get_untracked();
+    let link = state.url_params().get_untracked().encode();
     let user_id = move || state.url_params().user_id().get_untracked();
     let calendar_events = state.calendar_events();
 
     // create stacking for display
     let events_stacking = move || get_events_stacking(&calendar_events.get());
+    // sessions the user is double booked in
+    let user_clashes = move || get_user_clashes(&calendar_events.get(), &user_id());
+
+    // cards dragged to new times move right away and are put back if the server refuses.
+    // Cards are rebuilt whenever the events change, so the action lives here
+    let reschedule = ServerAction::<RescheduleEvent>::new();
+    let server_res = reschedule.value();
+    let before_reschedule = StoredValue::new(None::<GamingSession>);
+    let (reschedule_error, set_reschedule_error) = signal(None::<String>);
+    Effect::new(move || match server_res() {
+        Some(Ok(s)) => {
+            before_reschedule.set_value(None);
+            calendar_events.update(|v| {
+                if let Some(session) = v.iter_mut().find(|r| r.session_id == s.session_id) {
+                    *session = s;
+                }
+            });
+        }
+        Some(Err(e)) => {
+            log!("{:?}", e);
+            set_reschedule_error(Some(error_message(&e)));
+            if let Some(previous) = before_reschedule.write_value().take() {
+                calendar_events.update(|v| {
+                    if let Some(session) =
+                        v.iter_mut().find(|r| r.session_id == previous.session_id)
+                    {
+                        *session = previous;
+                    }
+                });
+            }
+        }
+        None => {}
+    });
+    // one at a time, so a failure always rolls back the session it belongs to
+    let on_reschedule = Callback::new(
+        move |(session_id, start, end): (i64, DateTime<Utc>, DateTime<Utc>)| {
+            if reschedule.pending().get_untracked() {
+                return;
+            }
+            let previous = calendar_events
+                .with_untracked(|v| v.iter().find(|r| r.session_id == session_id).cloned());
+            if let Some(previous) = previous {
+                set_reschedule_error(None);
+                before_reschedule.set_value(Some(previous));
+                calendar_events.update(|v| {
+                    if let Some(session) = v.iter_mut().find(|r| r.session_id == session_id) {
+                        session.start_time = start;
+                        session.end_time = end;
+                    }
+                });
+                reschedule.dispatch(RescheduleEvent {
+                    session_id,
+                    link: state.url_params().get_untracked().encode(),
+                    start,
+                    end,
+                });
+            }
+        },
+    );
 
     view! {
         <Show
@@ -41,11 +97,11 @@ pub fn CalendarEvents(
         >
         {
             let baseline_date = move || baseline().unwrap();
-            let window_start = baseline_date() + Duration::hours(offset as i64);
-            let window_end = baseline_date() + Duration::hours(24 + offset as i64);
+            let tz = baseline_date().timezone();
+            let (window_start, window_end) = day_window(baseline_date(), offset);
             view! {
                 <Await
-                    future=get_events(server_id.clone(), window_start, window_end)
+                    future=get_events(link.clone(), window_start.fixed_offset(), window_end.fixed_offset())
                     let:res
                 >
                     {
@@ -53,23 +109,44 @@ pub fn CalendarEvents(
                         if let Ok(v) = res.as_ref() {
                             calendar_events.set(v.clone());
                         }
-                        move || calendar_events.get().iter().map(|r| view! {
+                        // events created elsewhere (e.g. from a poll) may fall on another day
+                        move || calendar_events.get().iter().filter(|r| r.start_time < window_end && r.end_time > window_start).map(|r| view! {
                             <EventCard
                                 title={r.title.clone()}
                                 owner={Arc::new(r.owner.clone())}
                                 participants={r.participants.iter().map(|i| Arc::new(i.clone())).collect()}
-                                start_time={r.start_time.fixed_offset()}
-                                end_time={r.end_time.fixed_offset()}
+                                start_time={r.start_time.with_timezone(&tz)}
+                                end_time={r.end_time.with_timezone(&tz)}
+                                continues_before={r.start_time < window_start}
+                                continues_after={r.end_time > window_end}
                                 baseline={ baseline_date() }
-                                stacking_col={ events_stacking().get(&r.session_id).unwrap().clone() }
+                                layout={ *events_stacking().get(&r.session_id).unwrap() }
                                 session_id={r.session_id.clone()}
                                 game={r.game.clone()}
+                                max_participants={r.max_participants}
+                                waitlist_len={r.waitlist.len()}
+                                locked={r.locked}
+                                description={r.description.clone()}
+                                description_html={r.description_html.clone()}
+                                comment_count={r.comment_count}
+                                clashes_with={user_clashes().remove(&r.session_id).unwrap_or_default()}
                                 user_id={user_id()}
                                 offset={offset}
+                                on_reschedule={on_reschedule}
                             />
                         }).collect_view()
                     }
                 </Await>
+                {
+                    move || reschedule_error().map(|msg| view! {
+                        <div class="toast toast-end toast-bottom z-4 mb-24">
+                            <div role="alert" class="alert alert-error">
+                                <span>{ msg }</span>
+                                <button class="btn btn-sm btn-ghost" on:click=move |_| set_reschedule_error(None)>{"✕"}</button>
+                            </div>
+                        </div>
+                    })
+                }
             }
         }
         </Show>
@@ -77,48 +154,273 @@ pub fn CalendarEvents(
 }
 
 #[server]
+#[cfg_attr(
+    feature = "ssr",
+    tracing::instrument(
+        name = "get_events",
+        skip_all,
+        fields(
+            server_id = tracing::field::Empty,
+            user_id = tracing::field::Empty
+        ),
+        err
+    )
+)]
 async fn get_events(
-    server_id: String,
+    link: String,
     start_time: DateTime<FixedOffset>,
     end_time: DateTime<FixedOffset>,
-) -> Result<Vec<GamingSession>, ServerFnError> {
-    use crate::dao::sqlite_util::SqliteClient;
-    use sqlx::{Pool, Sqlite};
+) -> Result<Vec<GamingSession>, ServerFnError<AppError>> {
+    use crate::{config::pool, dao::sqlite_util::SqliteClient, obf_util::verify_caller};
+    use futures::future::join_all;
 
-    let pool = use_context::<Pool<Sqlite>>().expect("pool not found");
+    let caller = verify_caller(&link)?;
+
+    let pool = pool()?;
     let client = SqliteClient::from_pool(pool).await;
 
     let sessions = client
-        .get_sessions_in_range(&server_id, start_time.to_utc(), end_time.to_utc())
-        .await
-        .unwrap();
-    log!("getting events: {}", Utc::now());
+        .get_sessions_in_range(
+            &caller.get_server_id(),
+            start_time.to_utc(),
+            end_time.to_utc(),
+        )
+        .await?;
+    tracing::debug!(sessions = sessions.len(), "loaded sessions");
 
     // TODO: make this call process faster
-    let a: Vec<_> = sessions
+    let a: Vec<_> = sessions.iter().map(|s| load_session(&client, s)).collect();
+
+    Ok(join_all(a)
+        .await
+        .into_iter()
+        .collect::<Result<Vec<_>, _>>()?)
+}
+
+/**
+ * Moves a session to new times, e.g. after its card was dragged. Everything else is kept
+ */
+#[server]
+#[cfg_attr(
+    feature = "ssr",
+    tracing::instrument(
+        name = "reschedule_event",
+        skip_all,
+        fields(session_id = %session_id, user_id = tracing::field::Empty),
+        err
+    )
+)]
+pub async fn reschedule_event(
+    session_id: i64,
+    link: String,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+) -> Result<GamingSession, ServerFnError<AppError>> {
+    use crate::component::{
+        model::Role,
+        validation::{earliest_start, validate_times},
+    };
+    use crate::{
+        config::pool,
+        dao::sqlite_util::{SessionDetails, SqliteClient},
+        obf_util::verify_caller,
+    };
+
+    let caller = verify_caller(&link)?;
+    let user_id = caller.get_user_id();
+
+    let pool = pool()?;
+    let client = SqliteClient::from_pool(pool).await;
+
+    let session = caller_session(&client, &caller, session_id).await?;
+    let role = Role::from(client.get_role(&session.server_id, &user_id).await?);
+    if !role.can_manage_session(&user_id, &session.owner, session.locked) {
+        return Err(
+            AppError::forbidden("Only the owner or a moderator can edit this session").into(),
+        );
+    }
+
+    // same rules as editing, sessions that already started may keep their start time
+    let current_start = DateTime::parse_from_rfc3339(&session.start_time)
+        .map_err(AppError::storage)?
+        .to_utc();
+    validate_times(start, end, current_start.min(earliest_start(Utc::now())))
+        .map_err(|(_, e)| AppError::validation(e))?;
+
+    let record = client
+        .update_session(
+            session_id,
+            &SessionDetails {
+                start_time: start.to_rfc3339(),
+                end_time: end.to_rfc3339(),
+                ..SessionDetails::from(&session)
+            },
+        )
+        .await?
+        .ok_or(AppError::not_found("session"))?;
+
+    Ok(load_session(&client, &record).await?)
+}
+
+/**
+ * Sessions on the server the user already attends that overlap [start_time, end_time),
+ * other than `session_id` itself
+ */
+#[cfg(feature = "ssr")]
+pub async fn find_clashes(
+    client: &crate::dao::sqlite_util::SqliteClient,
+    server_id: &str,
+    user_id: &str,
+    start_time: chrono::DateTime<chrono::Utc>,
+    end_time: chrono::DateTime<chrono::Utc>,
+    session_id: Option<i64>,
+) -> Result<Vec<crate::component::model::Clash>, AppError> {
+    use crate::component::model::Clash;
+
+    let parse_time = |time: &str| {
+        DateTime::parse_from_rfc3339(time)
+            .map(|t| t.to_utc())
+            .map_err(AppError::storage)
+    };
+    let mut clashes = vec![];
+    for s in client
+        .get_user_sessions_overlapping(server_id, user_id, start_time, end_time)
+        .await?
+    {
+        if s.session_id == session_id {
+            continue;
+        }
+        clashes.push(Clash {
+            session_id: s.session_id.unwrap_or_default(),
+            title: s.title,
+            start_time: parse_time(&s.start_time)?,
+            end_time: parse_time(&s.end_time)?,
+        });
+    }
+    Ok(clashes)
+}
+
+/**
+ * The session a server function acts on for the caller. Links only reach sessions on their own
+ * server, however the session id was found
+ */
+#[cfg(feature = "ssr")]
+pub async fn caller_session(
+    client: &crate::dao::sqlite_util::SqliteClient,
+    caller: &crate::obf_util::UrlParams,
+    session_id: i64,
+) -> Result<crate::dao::sqlite_util::SessionRecord, AppError> {
+    let session = client
+        .get_session(session_id)
+        .await?
+        .ok_or(AppError::not_found("session"))?;
+    if session.server_id != caller.get_server_id() {
+        return Err(AppError::forbidden("This session is on another server"));
+    }
+    Ok(session)
+}
+
+/**
+ * Builds the client side session from its record, loading participants and the waitlist
+ */
+#[cfg(feature = "ssr")]
+pub async fn load_session(
+    client: &crate::dao::sqlite_util::SqliteClient,
+    s: &crate::dao::sqlite_util::SessionRecord,
+) -> Result<GamingSession, AppError> {
+    use crate::{
+        component::model::{RsvpStatus, User},
+        markdown::render_markdown,
+    };
+
+    let session_id = s.session_id.ok_or(AppError::storage("session has no id"))?;
+    let participants = client.get_session_users(session_id).await?;
+    let waitlist = client.get_session_waitlist(session_id).await?;
+    let parse_time = |time: &str| {
+        DateTime::parse_from_rfc3339(time)
+            .map(|t| t.to_utc())
+            .map_err(|e| AppError::storage(format!("session {session_id} has time {time}: {e}")))
+    };
+    // the owner may have been removed from users outside the app
+    let owner = participants
         .iter()
-        .map(|s| async {
-            let session_id = s.session_id.unwrap();
-            let participants = client.get_session_users(session_id).await.unwrap();
-            let owner_record = participants
-                .iter()
-                .find(|r| s.owner == r.user_id)
-                .expect("no owner found for session");
-
-            GamingSession {
-                server_id: s.server_id.clone(),
-                session_id: s.session_id.unwrap(),
-                title: s.title.clone(),
-                start_time: DateTime::parse_from_rfc3339(&s.start_time)
-                    .unwrap()
-                    .to_utc(),
-                end_time: DateTime::parse_from_rfc3339(&s.end_time).unwrap().to_utc(),
-                owner: User::from(owner_record),
-                participants: participants.iter().map(User::from).collect(),
-                game: s.game.clone(),
-            }
-        })
-        .collect();
+        .find(|r| s.owner == r.user_id)
+        .map(User::from)
+        .unwrap_or_else(|| User {
+            name: s.owner.clone(),
+            picture: "placeholder".to_string(),
+            timezone: None,
+            status: RsvpStatus::NotGoing,
+            eta: None,
+            joined_at: None,
+        });
+
+    Ok(GamingSession {
+        server_id: s.server_id.clone(),
+        session_id,
+        title: s.title.clone(),
+        start_time: parse_time(&s.start_time)?,
+        end_time: parse_time(&s.end_time)?,
+        owner,
+        participants: participants.iter().map(User::from).collect(),
+        game: s.game.clone(),
+        max_participants: s.max_participants.map(|m| m as usize),
+        waitlist: waitlist.iter().map(User::from).collect(),
+        locked: s.locked,
+        description: s.description.clone(),
+        description_html: s.description.as_deref().map(render_markdown),
+        comment_count: client.get_comment_count(session_id).await? as usize,
+    })
+}
+
+#[cfg(all(test, feature = "ssr"))]
+mod tests {
+    use crate::{
+        component::calendar_events::caller_session,
+        dao::sqlite_util::{SessionDetails, SqliteClient},
+        error::AppError,
+        obf_util::UrlParams,
+    };
+    use sqlx::sqlite::SqlitePoolOptions;
+
+    async fn setup_client() -> SqliteClient {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        sqlx::migrate!().run(&pool).await.unwrap();
+        SqliteClient::from_pool(pool).await
+    }
+
+    fn caller(server_id: &str) -> UrlParams {
+        UrlParams::decode_url(UrlParams::encode_url(server_id, "user")).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_caller_session_cross_server() {
+        let client = setup_client().await;
+        let session_id = client
+            .create_session(
+                "server_a",
+                "owner",
+                &SessionDetails {
+                    title: "title".to_string(),
+                    start_time: "1996-12-19T16:39:57-08:00".to_string(),
+                    end_time: "1996-12-19T18:39:57-08:00".to_string(),
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap()
+            .session_id
+            .unwrap();
 
-    Ok(join_all(a).await)
+        let session = caller_session(&client, &caller("server_a"), session_id).await;
+        assert_eq!(session.unwrap().session_id, Some(session_id));
+        let session = caller_session(&client, &caller("server_b"), session_id).await;
+        assert!(matches!(session, Err(AppError::Forbidden(_))));
+        let session = caller_session(&client, &caller("server_a"), session_id + 1).await;
+        assert!(matches!(session, Err(AppError::NotFound(_))));
+    }
 }
@@ -1,14 +1,47 @@
 use std::sync::Arc;
 
-use chrono::{DateTime, FixedOffset};
-use leptos::{logging::log, prelude::*};
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+use leptos::{ev, html::Div, prelude::*, web_sys::Element};
+use leptos_use::{use_event_listener, use_window};
 
-use crate::component::{
-    join_leave_session_button::JoinLeaveSessionButton, modal::delete_event_modal::DeleteEventModal,
-    time_util::calculate_time_pct,
+use reactive_stores::Store;
+
+use crate::{
+    app::{GlobalState, GlobalStateStoreFields},
+    component::{
+        host_picker::HostPicker,
+        join_leave_session_button::JoinLeaveSessionButton,
+        modal::{
+            delete_event_modal::DeleteEventModal, event_detail_modal::EventDetailModal,
+            update_event_modal::UpdateEventModal,
+        },
+        model::{RsvpStatus, ServerSettingsStoreFields},
+        time_util::{calculate_time_pct, drag_times, time_format, EventLayout},
+    },
 };
 
-use super::model::{Game, User};
+use super::model::User;
+
+// dragged cards snap to quarter hours
+const SNAP_MINUTES: i64 = 15;
+// pointer movement before a press on the card counts as a drag instead of a click
+const DRAG_THRESHOLD_PX: i32 = 4;
+
+// a press on a card that may turn into a drag
+#[derive(Clone, Copy)]
+struct CardDrag {
+    // pointer position on press
+    origin_y: i32,
+    // height of the whole displayed day, to turn pixels into a percent of it
+    day_height: f64,
+    // the bottom edge was pressed
+    resize: bool,
+    // how far the pointer moved since, as a percent of the day
+    delta_pct: f64,
+    // passed the threshold
+    moved: bool,
+}
 
 /**
  * Display component for an event.
@@ -18,30 +51,217 @@ pub fn EventCard(
     title: String,
     owner: Arc<User>,
     participants: Vec<Arc<User>>,
-    start_time: DateTime<FixedOffset>,
-    end_time: DateTime<FixedOffset>,
-    baseline: DateTime<FixedOffset>,
-    stacking_col: i32,
+    start_time: DateTime<Tz>,
+    end_time: DateTime<Tz>,
+    continues_before: bool,
+    continues_after: bool,
+    baseline: DateTime<Tz>,
+    layout: EventLayout,
     session_id: i64,
     user_id: String,
     game: Option<String>,
+    max_participants: Option<usize>,
+    waitlist_len: usize,
+    locked: bool,
+    // markdown for editing and the server's sanitized rendering of it
+    description: Option<String>,
+    description_html: Option<String>,
+    comment_count: usize,
+    // titles of other sessions the user attends at the same time
+    clashes_with: Vec<String>,
     offset: usize,
+    // the card was dragged to new times
+    on_reschedule: Callback<(i64, DateTime<Utc>, DateTime<Utc>)>,
 ) -> impl IntoView {
     let is_user_owner = user_id == owner.get_name();
+    // a drag ends with a click on the card, which shouldn't open the detail modal
+    let just_dragged = StoredValue::new(false);
+    // clicking the card opens the detail modal with every participant
+    let detail_open = RwSignal::new(false);
+    let open_detail = move |ev: ev::MouseEvent| {
+        // controls and modals on the card handle their own clicks
+        let on_control = event_target::<Element>(&ev)
+            .closest("button, a, input, select, textarea, label, details, dialog")
+            .ok()
+            .flatten()
+            .is_some();
+        if !just_dragged.get_value() && !on_control {
+            detail_open.set(true);
+        }
+        just_dragged.set_value(false);
+    };
 
     let game_selected = game.is_some();
     let start_pct = calculate_time_pct(start_time, baseline, offset);
     let end_pct = calculate_time_pct(end_time, baseline, offset);
-    log!("creating event card");
+    let state = expect_context::<Store<GlobalState>>();
+    let can_manage =
+        state
+            .role()
+            .get_untracked()
+            .can_manage_session(&user_id, &owner.get_name(), locked);
+    let use_24h = state.server_settings().use_24h().get_untracked();
+    let day_time_format = format!("%a {}", time_format(use_24h));
+    let zoned_time_format = format!("{day_time_format} %Z");
+
+    // managers drag the card to move it, or its bottom edge to change its length. Cards cut
+    // off by the window can't be dragged since part of them isn't shown
+    let draggable = can_manage && !continues_before && !continues_after;
+    let container = NodeRef::<Div>::new();
+    let (drag, set_drag) = signal(None::<CardDrag>);
+    let preview = move || {
+        drag().filter(|d| d.moved).map(|d| {
+            drag_times(
+                start_time,
+                end_time,
+                d.delta_pct,
+                d.resize,
+                baseline,
+                offset,
+                SNAP_MINUTES,
+            )
+        })
+    };
+    let start_drag = move |ev: ev::MouseEvent, resize: bool| {
+        // presses inside the card's modals are theirs
+        let in_dialog = event_target::<Element>(&ev)
+            .closest("dialog")
+            .ok()
+            .flatten()
+            .is_some();
+        if !draggable || in_dialog || ev.button() != 0 {
+            return;
+        }
+        just_dragged.set_value(false);
+        let day_height = container
+            .get_untracked()
+            .and_then(|el| el.parent_element())
+            .map(|p| p.client_height() as f64)
+            .unwrap_or_default();
+        if day_height > 0. {
+            set_drag(Some(CardDrag {
+                origin_y: ev.client_y(),
+                day_height,
+                resize,
+                delta_pct: 0.,
+                moved: false,
+            }));
+        }
+    };
+    let _ = use_event_listener(use_window(), ev::mousemove, move |ev| {
+        if drag.get_untracked().is_some() {
+            set_drag.update(|d| {
+                if let Some(d) = d {
+                    let dy = ev.client_y() - d.origin_y;
+                    d.moved |= dy.abs() >= DRAG_THRESHOLD_PX;
+                    d.delta_pct = dy as f64 / d.day_height;
+                }
+            });
+        }
+    });
+    let _ = use_event_listener(use_window(), ev::mouseup, move |_| {
+        if drag.get_untracked().is_some() {
+            if let Some((new_start, new_end)) = preview() {
+                just_dragged.set_value(true);
+                if (new_start, new_end) != (start_time, end_time) {
+                    on_reschedule.run((session_id, new_start.to_utc(), new_end.to_utc()));
+                }
+            }
+            set_drag(None);
+        }
+    });
+
+    // flatten the edges of cards that are cut off by the window, so they read as continuing
+    let card_class = format!(
+        "relative z-1 w-full min-w-0 mr-1 h-full card bg-primary card-border border-primary-content shadow-sm {} {}",
+        if continues_before { "rounded-t-none border-t-0" } else { "" },
+        if continues_after { "rounded-b-none border-b-0" } else { "" },
+    );
 
     view! {
-        <div style={ format!("position: absolute; display: flex; top: {}%; bottom: {}%; left: {}rem;", start_pct * 100., (1. - end_pct) * 100., 4 + stacking_col * 12) }>
-            <div class="relative z-1 w-48 h-full card bg-primary card-border border-primary-content shadow-sm">
+        <div
+            node_ref=container
+            style={ move || {
+                // while dragging, the card follows the pointer
+                let (top, bottom) = match preview() {
+                    Some((s, e)) => (calculate_time_pct(s, baseline, offset), calculate_time_pct(e, baseline, offset)),
+                    None => (start_pct, end_pct),
+                };
+                // the cluster shares the width right of the hour labels
+                format!(
+                    "position: absolute; display: flex; top: {}%; bottom: {}%; left: calc(4rem + (100% - 4rem) * {} / {}); width: calc((100% - 4rem) * {} / {});",
+                    top * 100., (1. - bottom) * 100., layout.column, layout.columns, layout.span, layout.columns,
+                )
+            } }
+            class:z-3=move || drag().is_some_and(|d| d.moved)
+            class:opacity-80=move || drag().is_some_and(|d| d.moved)
+            class:select-none=draggable
+            class:cursor-move=draggable
+            on:mousedown=move |ev| start_drag(ev, false)
+        >
+            <div class={card_class} class:cursor-pointer=!draggable on:click=open_detail>
+                {
+                    move || preview().map(|(s, e)| view! {
+                        <span class="absolute -top-6 left-1/2 -translate-x-1/2 badge badge-secondary whitespace-nowrap">
+                            { format!("{} - {}", s.format(time_format(use_24h)), e.format(time_format(use_24h))) }
+                        </span>
+                    })
+                }
+                {
+                    if continues_before {
+                        view! {
+                            // dashed edge in place of the card's own border
+                            <div class="absolute top-0 inset-x-0 border-t-4 border-dashed border-primary-content"></div>
+                            <span class="absolute top-1 left-1/2 -translate-x-1/2 text-xs opacity-70">
+                                { format!("▲ from {}", start_time.format(&day_time_format)) }
+                            </span>
+                        }.into_any()
+                    } else {
+                        view! {}.into_any()
+                    }
+                }
                 <div class="card-body">
-                    <div class="absolute top-2 right-2">
-                        <DeleteEventModal session_id={session_id} owner_id={owner.get_name()}/>
+                    <div class="absolute top-2 right-2 flex">
+                        <EventDetailModal
+                            session_id={session_id}
+                            title={title.clone()}
+                            start_time={start_time}
+                            end_time={end_time}
+                            game={game.clone()}
+                            description_html={description_html.clone()}
+                            comment_count={comment_count}
+                            can_manage={can_manage}
+                            locked={locked}
+                            open={detail_open}
+                        />
+                        {
+                            can_manage.then(|| view! {
+                                <UpdateEventModal
+                                    session_id={session_id}
+                                    title={title.clone()}
+                                    start_time={start_time}
+                                    end_time={end_time}
+                                    game={game.clone()}
+                                    max_participants={max_participants}
+                                    description={description.clone()}
+                                    locked={locked}
+                                    owner_tz={owner.get_tz()}
+                                />
+                            })
+                        }
+                        <DeleteEventModal session_id={session_id} can_manage={can_manage}/>
                     </div>
-                    <h2 class="text-xl font-bold card-title">{ title }</h2>
+                    <h2 class="text-xl font-bold card-title">
+                        { title }
+                        { locked.then(|| view! { <span class="badge badge-sm badge-warning">Locked</span> }) }
+                        {
+                            (!clashes_with.is_empty()).then(|| view! {
+                                <span class="badge badge-sm badge-error" title={format!("Also in {}", clashes_with.join(", "))}>
+                                    Double booked
+                                </span>
+                            })
+                        }
+                    </h2>
                     // game title if game selected
                     {
                         if game_selected {
@@ -55,63 +275,149 @@ pub fn EventCard(
                             view! {}.into_any()
                         }
                     }
+                    // description, collapsed so cards keep their size
+                    {
+                        description_html.map(|html| view! {
+                            <details class="collapse collapse-arrow bg-primary">
+                                <summary class="collapse-title p-0 min-h-0 text-sm">Details</summary>
+                                <div class="collapse-content p-0 text-sm break-words [&_a]:link [&_ul]:list-disc [&_ul]:pl-4 [&_ol]:list-decimal [&_ol]:pl-4" inner_html=html></div>
+                            </details>
+                        })
+                    }
                     // owner
                     <div class="flex flex-row gap-1">
                         <h2>Owner: </h2>
                         <span class="text-sm">{ owner.get_name() }</span>
                     </div>
-                    // participants
-                    <div class="avatar-group bg-primary -space-x-4">
-                        {
-                            participants.iter()
-                            .map(|p| view! {
-                                <div class="avatar border-primary border-2">
-                                    <div class="w-8">
-                                        <img
-                                            src={ get_url() }
-                                            alt={format!("{}'s profile picture", p.get_name())}
-                                            loading="eager"
-                                        />
-                                    </div>
-                                </div>
-                            })
-                            .collect_view()
-                        }
-                        <div class="flex flex-row">
-                            <div class="flex-1">
+                    // capacity, e.g. "3/5 + 2 waiting"
+                    <div class="flex flex-row gap-1">
+                        <h2>Players: </h2>
+                        <span class="text-sm">
                             {
-                                if participants.len() > 4 {
-                                    view! {
-                                        <div class="avatar avatar-placeholder border-primary border-2">
-                                            <div class="bg-neutral text-neutral-content w-8">
-                                                <span>{ format!("+{}", participants.len() - 1) }</span>
-                                            </div>
-                                        </div>
-                                    }.into_any()
+                                let attending = participants.iter().filter(|p| p.status.is_attending()).count();
+                                let going = match max_participants {
+                                    Some(max) => format!("{attending}/{max}"),
+                                    None => attending.to_string(),
+                                };
+                                if waitlist_len > 0 {
+                                    format!("{going} + {waitlist_len} waiting")
                                 } else {
-                                    view! {}.into_any()
+                                    going
                                 }
                             }
-                            </div>
+                        </span>
+                    </div>
+                    // start time in each participant's own timezone
+                    <details class="collapse collapse-arrow bg-primary">
+                        <summary class="collapse-title p-0 min-h-0 text-sm">Local times</summary>
+                        <ul class="collapse-content p-0 text-sm">
                             {
-                                // todo: decouple this (user id from username)
-                                if !is_user_owner {
-                                    view! {
-                                        <JoinLeaveSessionButton session_id={session_id} />
-                                    }.into_any()
-                                } else {
-                                    view! {}.into_any()
-                                }
+                                participants.iter()
+                                .map(|p| view! {
+                                    <li>
+                                        <span class="font-bold">{ format!("{}: ", p.get_name()) }</span>
+                                        {
+                                            match p.get_tz() {
+                                                Some(tz) => start_time.with_timezone(&tz).format(&zoned_time_format).to_string(),
+                                                None => "timezone not set".to_string(),
+                                            }
+                                        }
+                                    </li>
+                                })
+                                .collect_view()
                             }
-                        </div>
+                        </ul>
+                    </details>
+                    // participants, grouped by RSVP so organizers know who is actually coming.
+                    // Only four are shown per group, the detail modal lists everyone
+                    {
+                        RsvpStatus::ALL.into_iter()
+                        .map(|status| (status, participants.iter().filter(|p| p.status == status).cloned().collect::<Vec<_>>()))
+                        .filter(|(_, group)| !group.is_empty())
+                        .map(|(status, group)| view! {
+                            <div class="flex flex-row items-center gap-2">
+                                <span class="text-xs w-16">{ format!("{} ({})", status.label(), group.len()) }</span>
+                                <div class="avatar-group bg-primary -space-x-4">
+                                    {
+                                        group.iter().take(4)
+                                        .map(|p| {
+                                            let name = match p.eta {
+                                                Some(eta) => format!("{}, arriving {}", p.get_name(), eta.with_timezone(&start_time.timezone()).format(time_format(use_24h))),
+                                                None => p.get_name(),
+                                            };
+                                            view! {
+                                                <div class="avatar border-primary border-2" title={name.clone()}>
+                                                    <div class="w-8">
+                                                        <img
+                                                            src={ get_url() }
+                                                            alt={format!("{name}'s profile picture")}
+                                                            loading="eager"
+                                                        />
+                                                    </div>
+                                                </div>
+                                            }
+                                        })
+                                        .collect_view()
+                                    }
+                                    {
+                                        (group.len() > 4).then(|| view! {
+                                            <div class="avatar avatar-placeholder border-primary border-2">
+                                                <div class="bg-neutral text-neutral-content w-8">
+                                                    <span>{ format!("+{}", group.len() - 4) }</span>
+                                                </div>
+                                            </div>
+                                        })
+                                    }
+                                </div>
+                            </div>
+                        })
+                        .collect_view()
+                    }
+                    <div class="flex flex-row justify-end gap-1">
+                        {
+                            (is_user_owner && !locked).then(|| view! {
+                                <HostPicker
+                                    session_id={session_id}
+                                    candidates={participants.iter().filter(|p| p.get_name() != owner.get_name() && p.status.is_attending()).map(|p| p.get_name()).collect()}
+                                />
+                            })
+                        }
+                        // todo: decouple this (user id from username)
+                        { (!locked).then(|| view! { <JoinLeaveSessionButton session_id={session_id} /> }) }
                     </div>
                 </div>
+                {
+                    draggable.then(|| view! {
+                        <div
+                            class="absolute bottom-0 inset-x-0 h-2 cursor-ns-resize"
+                            title="Drag to change the length"
+                            on:mousedown=move |ev| {
+                                ev.stop_propagation();
+                                start_drag(ev, true);
+                            }
+                            // the handle only resizes
+                            on:click=|ev| ev.stop_propagation()
+                        ></div>
+                    })
+                }
+                {
+                    if continues_after {
+                        view! {
+                            <div class="absolute bottom-0 inset-x-0 border-b-4 border-dashed border-primary-content"></div>
+                            <span class="absolute bottom-1 left-1/2 -translate-x-1/2 text-xs opacity-70">
+                                { format!("▼ until {}", end_time.format(&day_time_format)) }
+                            </span>
+                        }.into_any()
+                    } else {
+                        view! {}.into_any()
+                    }
+                }
             </div>
         </div>
     }.into_any()
 }
 
-fn get_url() -> String {
+pub fn get_url() -> String {
     "https://wallpapers.com/images/featured/discord-profile-pictures-xk3qyllfj1j46kte.jpg"
         .to_string()
 }
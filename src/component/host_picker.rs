@@ -0,0 +1,110 @@
+use leptos::{logging::log, prelude::*};
+use reactive_stores::Store;
+
+use crate::{
+    app::{GlobalState, GlobalStateStoreFields},
+    error::{error_message, AppError},
+};
+
+/**
+ * Dropdown for the owner to hand hosting over to another participant
+ */
+#[component]
+pub fn HostPicker(session_id: i64, candidates: Vec<String>) -> impl IntoView {
+    let state = expect_context::<Store<GlobalState>>();
+    let link = state.url_params().get_untracked().encode();
+
+    let (error, set_error) = signal(None::<String>);
+
+    // handle ActionForm
+    let transfer = ServerAction::<TransferOwnership>::new();
+    let server_res = transfer.value();
+    Effect::new(move || match server_res() {
+        Some(Ok(new_owner)) => state.calendar_events().update(|v| {
+            set_error(None);
+            if let Some(session) = v.iter_mut().find(|s| s.session_id == session_id) {
+                session.set_owner(&new_owner);
+            }
+        }),
+        Some(Err(e)) => {
+            log!("{:?}", e);
+            set_error(Some(error_message(&e)));
+        }
+        None => {}
+    });
+
+    if candidates.is_empty() {
+        return ().into_any();
+    }
+
+    view! {
+        <div class="dropdown">
+            <div tabindex="0" role="button" class="btn btn-xs">Hand over</div>
+            <div tabindex="0" class="dropdown-content bg-base-300 rounded-box z-2 w-48 p-2 shadow-2xl">
+                <ActionForm action=transfer>
+                    <input type="text" class="hidden invisible" name="session_id" value={session_id}/>
+                    <input type="text" class="hidden invisible" name="link" value={link}/>
+                    <select class="select select-xs w-full" name="new_owner">
+                        {
+                            candidates.iter().map(|name| view! {
+                                <option value={name.clone()}>{ name.clone() }</option>
+                            }).collect_view()
+                        }
+                    </select>
+                    <button type="submit" class="btn btn-xs btn-neutral w-full mt-2">Make host</button>
+                    {
+                        move || error().map(|msg| view! { <p class="text-error text-xs mt-2">{msg}</p> })
+                    }
+                </ActionForm>
+            </div>
+        </div>
+    }
+    .into_any()
+}
+
+/**
+ * Hands a session over to another participant. Only the current owner can do this
+ */
+#[server]
+#[cfg_attr(
+    feature = "ssr",
+    tracing::instrument(
+        name = "transfer_ownership",
+        skip_all,
+        fields(session_id = %session_id, user_id = tracing::field::Empty),
+        err
+    )
+)]
+pub async fn transfer_ownership(
+    session_id: i64,
+    link: String,
+    new_owner: String,
+) -> Result<String, ServerFnError<AppError>> {
+    use crate::{
+        component::calendar_events::caller_session, config::pool, dao::sqlite_util::SqliteClient,
+        obf_util::verify_caller,
+    };
+
+    let caller = verify_caller(&link)?;
+    let user_id = caller.get_user_id();
+
+    let pool = pool()?;
+    let client = SqliteClient::from_pool(pool).await;
+
+    let session = caller_session(&client, &caller, session_id).await?;
+    if session.locked {
+        return Err(AppError::conflict("This session is locked").into());
+    }
+
+    match client
+        .transfer_ownership(session_id, &user_id, &new_owner, "transfer")
+        .await
+    {
+        Ok(true) => Ok(new_owner),
+        Ok(false) => Err(AppError::forbidden(
+            "Only the owner can hand a session over, and only to a participant",
+        )
+        .into()),
+        Err(e) => Err(e.into()),
+    }
+}
@@ -1,20 +1,126 @@
-use leptos::prelude::*;
+use chrono::{DateTime, Duration, Timelike};
+use chrono_tz::Tz;
+use leptos::{ev::MouseEvent, html::Div, prelude::*};
+use reactive_stores::Store;
+
+use crate::{
+    app::{GlobalState, GlobalStateStoreFields},
+    component::time_util::{
+        calculate_time_pct, day_window, format_hour, snap_to_minutes, time_at_pct, time_format,
+    },
+};
+
+// drag selections snap to quarter hours
+const SNAP_MINUTES: i64 = 15;
 
 /**
- * Hour grid. 24 divs, offset by a certain number of hours.
- * For example, if offset is 6, will start at 6am and end at 5am
+ * Hour grid. One div per hour of the displayed day, offset by a certain number of hours.
+ * For example, if offset is 6, will start at 6am and end at 5am.
+ * Days with a DST transition have 23 or 25 hours.
+ * Dragging over the grid selects a range of time and opens the new event modal with it.
  */
 #[component]
-pub fn HourGrid(offset: usize) -> impl IntoView {
-    (0..24)
-        .map(|h| {
-            let v = (h + offset) % 24;
-            view! {
-                <div class="h-36 flex-shrink-0">
-                    <hr class="z-0 border-contrast"/>
-                    <p class="z-0 pl-2 text-contrast">{format!("{:0>2}:00", v)}</p>
-                </div>
+pub fn HourGrid(
+    baseline: ReadSignal<Option<DateTime<Tz>>>,
+    offset: usize,
+    use_24h: bool,
+) -> impl IntoView {
+    let state = expect_context::<Store<GlobalState>>();
+    let new_event_draft = state.new_event_draft();
+
+    let hours = move || match baseline() {
+        Some(b) => {
+            let (window_start, window_end) = day_window(b, offset);
+            (0..(window_end - window_start).num_hours())
+                .map(|h| (window_start + Duration::hours(h)).hour() as usize)
+                .collect::<Vec<_>>()
+        }
+        None => (0..24).map(|h| (h + offset) % 24).collect(),
+    };
+
+    // percent through the day where the drag started and where the pointer is now
+    let (drag, set_drag) = signal::<Option<(f64, f64)>>(None);
+    let surface = NodeRef::<Div>::new();
+    let pointer_pct = move |ev: &MouseEvent| {
+        surface
+            .get_untracked()
+            .map(|el| ev.offset_y() as f64 / el.client_height().max(1) as f64)
+    };
+
+    // the dragged range, snapped. Times in the form are relative to the displayed day, so
+    // the range has to end before the next day starts
+    let selection = move || {
+        let b = baseline()?;
+        let (from, to) = drag()?;
+        let (_, window_end) = day_window(b, offset);
+        let snap = |pct: f64| snap_to_minutes(time_at_pct(pct, b, offset), SNAP_MINUTES);
+        let start = snap(from.min(to));
+        let end = snap(from.max(to)).min(window_end - Duration::minutes(SNAP_MINUTES));
+        (end > start).then_some((start, end))
+    };
+
+    let finish_drag = move |_| {
+        if let Some((start, end)) = selection() {
+            new_event_draft.set(Some((start.to_utc(), end.to_utc())));
+        }
+        set_drag(None);
+    };
+
+    view! {
+        {
+            move || {
+                hours()
+                    .into_iter()
+                    .map(|v| {
+                        view! {
+                            <div class="h-36 flex-shrink-0">
+                                <hr class="z-0 border-contrast"/>
+                                <p class="z-0 pl-2 text-contrast">{format_hour(v, use_24h)}</p>
+                            </div>
+                        }
+                    })
+                    .collect_view()
+            }
+        }
+        // drag surface, below the events so they stay clickable
+        <div
+            node_ref=surface
+            class="absolute inset-0 z-0 select-none cursor-crosshair"
+            on:mousedown=move |ev| {
+                if ev.button() == 0 {
+                    ev.prevent_default();
+                    set_drag(pointer_pct(&ev).map(|pct| (pct, pct)));
+                }
+            }
+            on:mousemove=move |ev| {
+                if drag.get_untracked().is_some() {
+                    if let Some(pct) = pointer_pct(&ev) {
+                        set_drag.update(|d| if let Some((_, to)) = d { *to = pct });
+                    }
+                }
             }
-        })
-        .collect_view()
+            on:mouseup=finish_drag
+            on:mouseleave=move |_| set_drag(None)
+        >
+        {
+            move || selection().map(|(start, end)| {
+                let b = baseline.get_untracked().unwrap();
+                view! {
+                    <div
+                        class="absolute left-16 right-0 pointer-events-none rounded-box bg-secondary/40 border border-secondary px-2"
+                        style={ format!(
+                            "top: {}%; bottom: {}%;",
+                            calculate_time_pct(start, b, offset) * 100.,
+                            (1. - calculate_time_pct(end, b, offset)) * 100.,
+                        ) }
+                    >
+                        <span class="text-xs text-secondary-content">
+                            { format!("{} - {}", start.format(time_format(use_24h)), end.format(time_format(use_24h))) }
+                        </span>
+                    </div>
+                }
+            })
+        }
+        </div>
+    }
 }
@@ -3,7 +3,11 @@ use reactive_stores::Store;
 
 use crate::{
     app::{GlobalState, GlobalStateStoreFields},
-    component::model::User,
+    component::{
+        model::{GamingSession, JoinStatus, LeaveUpdate, RsvpStatus, RsvpUpdate, User},
+        validation::MAX_MINUTES_LATE,
+    },
+    error::{clashes, error_message, AppError},
     obf_util::UrlParamsStoreFields as _,
 };
 
@@ -11,123 +15,399 @@ use crate::{
 pub fn JoinLeaveSessionButton(session_id: i64) -> impl IntoView {
     // get state
     let state = expect_context::<Store<GlobalState>>();
-    let user_id = state.url_params().user_id().get_untracked();
+    let link = state.url_params().get_untracked().encode();
 
     // TODO : get user id from state
 
-    // get if user is participating
+    // get if user is participating, waiting for a spot, or if the session is full
     let calendar_events = state.calendar_events();
-    let is_user_participating = move || {
+    let user_status = move || {
         let user_id = state.url_params().user_id().get_untracked();
         calendar_events
             .get()
             .iter()
-            .find(|s| {
-                s.session_id == session_id && s.participants.iter().any(|p| p.get_name() == user_id)
-            })
-            .is_some()
+            .find(|s| s.session_id == session_id)
+            .and_then(|s| s.participants.iter().find(|p| p.get_name() == user_id))
+            .map(|p| p.status)
+    };
+    let is_user_waitlisted = move || {
+        let user_id = state.url_params().user_id().get_untracked();
+        calendar_events.get().iter().any(|s| {
+            s.session_id == session_id && s.waitlist.iter().any(|p| p.get_name() == user_id)
+        })
+    };
+    let is_session_full = move || {
+        calendar_events
+            .get()
+            .iter()
+            .any(|s| s.session_id == session_id && s.is_full())
     };
 
+    // why the last join or leave failed
+    let (error, set_error) = signal(None::<String>);
+    // RSVP that would double book the user, waiting for them to confirm
+    let (last_status, set_last_status) = signal(RsvpStatus::Going);
+    let (unconfirmed, set_unconfirmed) = signal(None::<RsvpStatus>);
+
     // handle RemoveUser ActionForm
     let remove_user = ServerAction::<RemoveUser>::new();
     let server_res = remove_user.value();
     Effect::new(move || match server_res() {
-        Some(Ok(())) => state.calendar_events().update(|v| {
+        Some(Ok(update)) => state.calendar_events().update(|v| {
+            set_error(None);
             let user_id = state.url_params().user_id().get_untracked();
             if let Some(session) = v.iter_mut().find(|s| s.session_id == session_id) {
-                if let Some(idx) = session
-                    .participants
-                    .iter()
-                    .position(|p| p.get_name() == user_id.clone())
-                {
-                    session.participants.remove(idx);
+                session.participants.retain(|p| p.get_name() != user_id);
+                session.waitlist.retain(|p| p.get_name() != user_id);
+                promote(session, update.promoted);
+                if let Some(new_owner) = update.new_owner {
+                    session.set_owner(&new_owner);
                 }
             }
         }),
         Some(Err(e)) => {
             log!("{:?}", e);
+            set_error(Some(error_message(&e)));
         }
         None => {}
     });
 
-    // handle AddUser ActionForm
+    // handle AddUser ActionForm and RSVP changes
     let add_user = ServerAction::<AddUser>::new();
     let server_res = add_user.value();
     Effect::new(move || match server_res() {
-        Some(Ok(())) => state.calendar_events().update(|v| {
+        Some(Ok(update)) => state.calendar_events().update(|v| {
+            set_error(None);
+            set_unconfirmed(None);
             let user_id = state.url_params().user_id().get_untracked();
             if let Some(session) = v.iter_mut().find(|s| s.session_id == session_id) {
-                if !session
+                if let Some(user) = session
                     .participants
-                    .iter()
-                    .any(|p| p.get_name() == user_id.clone())
+                    .iter_mut()
+                    .find(|p| p.get_name() == user_id)
                 {
-                    session.participants.push(User {
-                        name: user_id.clone(),
-                        picture: "placeholder".to_string(),
-                    })
+                    user.status = update.status;
+                    user.eta = update.eta;
+                } else {
+                    let list = match update.joined {
+                        JoinStatus::Joined => &mut session.participants,
+                        JoinStatus::Waitlisted => &mut session.waitlist,
+                    };
+                    if !list.iter().any(|p| p.get_name() == user_id.clone()) {
+                        list.push(User {
+                            name: user_id.clone(),
+                            picture: "placeholder".to_string(),
+                            timezone: state.user_timezone().get_untracked(),
+                            status: update.status,
+                            eta: update.eta,
+                            joined_at: Some(chrono::Utc::now()),
+                        })
+                    }
                 }
+                promote(session, update.promoted);
             }
         }),
         Some(Err(e)) => {
             log!("{:?}", e);
+            set_error(Some(error_message(&e)));
+            set_unconfirmed((!clashes(&e).is_empty()).then(|| last_status.get_untracked()));
         }
         None => {}
     });
 
+    // minutes after the start a late user expects to arrive
+    let (minutes_late, set_minutes_late) = signal("15".to_string());
+    let rsvp = move |status: RsvpStatus, force: bool| {
+        set_last_status(status);
+        add_user.dispatch(AddUser {
+            link: state.url_params().get_untracked().encode(),
+            session_id: session_id.to_string(),
+            status: status.to_string(),
+            minutes_late: minutes_late.get_untracked(),
+            force,
+        });
+    };
+
     view! {
         {
-            if is_user_participating() {
+            if user_status().is_some() {
+                view! {
+                    <div class="flex flex-row gap-1">
+                        <select
+                            class="select select-xs w-24"
+                            aria-label="RSVP"
+                            on:change=move |ev| {
+                                if let Ok(status) = event_target_value(&ev).parse() {
+                                    rsvp(status, false);
+                                }
+                            }
+                        >
+                            {
+                                RsvpStatus::ALL.into_iter().map(|status| view! {
+                                    <option value={status.as_str()} selected=move || user_status() == Some(status)>
+                                        { status.label() }
+                                    </option>
+                                }).collect_view()
+                            }
+                        </select>
+                        {
+                            move || (user_status() == Some(RsvpStatus::Late)).then(|| view! {
+                                <input
+                                    type="number"
+                                    class="input input-xs w-14"
+                                    min="0"
+                                    max=MAX_MINUTES_LATE
+                                    step="5"
+                                    aria-label="Minutes late"
+                                    prop:value=minutes_late
+                                    on:change=move |ev| {
+                                        set_minutes_late(event_target_value(&ev));
+                                        rsvp(RsvpStatus::Late, false);
+                                    }
+                                />
+                            })
+                        }
+                        <ActionForm action=remove_user>
+                            <input type="text" class="hidden invisible" name="session_id" value={session_id}/>
+                            <input type="text" class="hidden invisible" name="link" value={link.clone()}/>
+                            <button class="btn btn-xs btn-round">{"-"}</button>
+                        </ActionForm>
+                    </div>
+                }.into_any()
+            } else if is_user_waitlisted() {
                 view! {
                     <ActionForm action=remove_user>
                         <input type="text" class="hidden invisible" name="session_id" value={session_id}/>
-                        <input type="text" class="hidden invisible" name="user_id" value={user_id}/>
+                        <input type="text" class="hidden invisible" name="link" value={link}/>
                         <button class="btn btn-round">{"-"}</button>
                     </ActionForm>
                 }.into_any()
+            } else if is_session_full() {
+                view! {
+                    <button class="btn btn-round btn-warning" on:click=move |_| rsvp(RsvpStatus::Going, false)>Waitlist</button>
+                }.into_any()
             } else {
                 view! {
-                    <ActionForm action=add_user>
-                        <input type="text" class="hidden invisible" name="session_id" value={session_id}/>
-                        <input type="text" class="hidden invisible" name="user_id" value={user_id}/>
-                        <button class="btn btn-round">{"+"}</button>
-                    </ActionForm>
+                    <button class="btn btn-round" on:click=move |_| rsvp(RsvpStatus::Going, false)>{"+"}</button>
                 }.into_any()
             }
         }
+        {
+            move || error().map(|msg| view! { <span class="text-error text-xs">{msg}</span> })
+        }
+        {
+            move || unconfirmed().map(|status| view! {
+                <button class="btn btn-xs btn-warning" on:click=move |_| rsvp(status, true)>Join anyway</button>
+            })
+        }
+    }
+}
+
+/// Moves the waitlisted user that took a freed spot into the participants
+fn promote(session: &mut GamingSession, promoted: Option<String>) {
+    if let Some(promoted) = promoted {
+        if let Some(idx) = session
+            .waitlist
+            .iter()
+            .position(|p| p.get_name() == promoted)
+        {
+            let user = session.waitlist.remove(idx);
+            session.participants.push(user);
+        }
     }
 }
 
+/**
+ * Joins a session, or its waitlist if the session is full, or updates the user's RSVP if they
+ * already joined. Late users arrive `minutes_late` after the start
+ */
 #[server]
-pub async fn add_user(user_id: String, session_id: String) -> Result<(), ServerFnError> {
-    use crate::dao::sqlite_util::SqliteClient;
-    use sqlx::{Pool, Sqlite};
+#[cfg_attr(
+    feature = "ssr",
+    tracing::instrument(
+        name = "add_user",
+        skip_all,
+        fields(session_id = %session_id, user_id = tracing::field::Empty),
+        err
+    )
+)]
+pub async fn add_user(
+    link: String,
+    session_id: String,
+    status: String,
+    minutes_late: String,
+    #[server(default)] force: bool,
+) -> Result<RsvpUpdate, ServerFnError<AppError>> {
+    use crate::{
+        component::calendar_events::find_clashes,
+        dao::sqlite_util::{JoinOutcome, SqliteClient},
+    };
+    use crate::{
+        component::{calendar_events::caller_session, validation::validate_minutes_late},
+        config::pool,
+        error::FieldErrors,
+        obf_util::verify_caller,
+    };
+    use chrono::{DateTime, Duration, Utc};
 
-    let pool = use_context::<Pool<Sqlite>>().expect("pool not found");
+    let caller = verify_caller(&link)?;
+    let user_id = caller.get_user_id();
+
+    let pool = pool()?;
     let client = SqliteClient::from_pool(pool).await;
+    let session_id = session_id
+        .parse::<i64>()
+        .map_err(|_| AppError::validation("invalid session"))?;
+    let status = status
+        .parse::<RsvpStatus>()
+        .map_err(|_| AppError::validation("invalid RSVP"))?;
+    let minutes_late = match status {
+        RsvpStatus::Late => Some(validate_minutes_late(&minutes_late).map_err(|e| {
+            let mut errors = FieldErrors::default();
+            errors.add("minutes_late", e);
+            AppError::from(errors)
+        })?),
+        _ => None,
+    };
+
+    let session = caller_session(&client, &caller, session_id).await?;
+    if session.locked {
+        return Err(AppError::conflict("This session is locked").into());
+    }
+
+    let start_time = DateTime::parse_from_rfc3339(&session.start_time)
+        .map_err(AppError::storage)?
+        .with_timezone(&Utc);
+    let end_time = DateTime::parse_from_rfc3339(&session.end_time)
+        .map_err(AppError::storage)?
+        .with_timezone(&Utc);
+
+    // warn before the user commits to two sessions at once, unless they confirmed
+    if status.is_attending() && !force {
+        let clashes = find_clashes(
+            &client,
+            &session.server_id,
+            &user_id,
+            start_time,
+            end_time,
+            Some(session_id),
+        )
+        .await?;
+        if !clashes.is_empty() {
+            return Err(AppError::DoubleBooked(clashes).into());
+        }
+    }
+
+    let eta = minutes_late.map(|minutes| start_time + Duration::minutes(minutes));
+
+    let users = client.get_session_users(session_id).await?;
+
+    if users.iter().any(|u| u.user_id == user_id) {
+        let updated = client
+            .update_session_user_status(
+                session_id,
+                &user_id,
+                status.as_str(),
+                eta.map(|eta| eta.to_rfc3339()),
+            )
+            .await?;
+        if !updated {
+            return Err(AppError::conflict("This session is full").into());
+        }
+
+        // not going gives the spot to the first in line
+        let promoted = if status.is_attending() {
+            None
+        } else {
+            client.promote_waitlist(session_id).await?
+        };
+        return Ok(RsvpUpdate {
+            joined: JoinStatus::Joined,
+            status,
+            eta,
+            promoted,
+        });
+    }
 
     match client
-        .create_session_user(&user_id, session_id.parse::<i64>().unwrap(), "placeholder")
+        .add_session_user(
+            &user_id,
+            session_id,
+            "placeholder",
+            status.as_str(),
+            eta.map(|eta| eta.to_rfc3339()),
+        )
         .await
     {
-        Ok(_) => Ok(()),
-        Err(e) => Err(ServerFnError::new(e)),
+        Ok(JoinOutcome::Joined) => Ok(RsvpUpdate {
+            joined: JoinStatus::Joined,
+            status,
+            eta,
+            promoted: None,
+        }),
+        // the waitlist doesn't keep an RSVP, promoted users are going
+        Ok(JoinOutcome::Waitlisted) => Ok(RsvpUpdate {
+            joined: JoinStatus::Waitlisted,
+            status: RsvpStatus::Going,
+            eta: None,
+            promoted: None,
+        }),
+        Err(e) => Err(e.into()),
     }
 }
 
+/**
+ * Leaves a session or its waitlist. The first waitlisted user takes the freed spot, and if the
+ * owner leaves, the earliest joiner takes over hosting
+ */
 #[server]
-pub async fn remove_user(user_id: String, session_id: String) -> Result<(), ServerFnError> {
-    use crate::dao::sqlite_util::SqliteClient;
-    use sqlx::{Pool, Sqlite};
+#[cfg_attr(
+    feature = "ssr",
+    tracing::instrument(
+        name = "remove_user",
+        skip_all,
+        fields(session_id = %session_id, user_id = tracing::field::Empty),
+        err
+    )
+)]
+pub async fn remove_user(
+    link: String,
+    session_id: String,
+) -> Result<LeaveUpdate, ServerFnError<AppError>> {
+    use crate::{
+        component::calendar_events::caller_session, config::pool, dao::sqlite_util::SqliteClient,
+        obf_util::verify_caller,
+    };
+
+    let caller = verify_caller(&link)?;
+    let user_id = caller.get_user_id();
 
-    let pool = use_context::<Pool<Sqlite>>().expect("pool not found");
+    let pool = pool()?;
     let client = SqliteClient::from_pool(pool).await;
+    let session_id = session_id
+        .parse::<i64>()
+        .map_err(|_| AppError::validation("invalid session"))?;
 
-    match client
-        .delete_session_user(session_id.parse::<i64>().unwrap(), &user_id)
-        .await
-    {
-        Ok(_) => Ok(()),
-        Err(e) => Err(ServerFnError::new(e)),
+    let session = caller_session(&client, &caller, session_id).await?;
+    if session.locked {
+        return Err(AppError::conflict("This session is locked").into());
+    }
+    // owners can only leave if somebody can take over hosting
+    if session.owner == user_id {
+        let has_successor = client.get_successor(session_id, &user_id).await?.is_some();
+        let has_waitlist = !client.get_session_waitlist(session_id).await?.is_empty();
+        if !has_successor && !has_waitlist {
+            return Err(
+                AppError::conflict("Nobody else can host this session, delete it instead").into(),
+            );
+        }
+    }
+
+    match client.remove_participant(session_id, &user_id).await {
+        Ok(outcome) => Ok(LeaveUpdate {
+            promoted: outcome.promoted,
+            new_owner: outcome.new_owner,
+        }),
+        Err(e) => Err(e.into()),
     }
 }
@@ -1,6 +1,9 @@
+pub mod agenda;
+mod availability_poll;
 pub mod calendar;
 mod calendar_events;
 mod event_card;
+mod host_picker;
 mod hour_grid;
 mod join_leave_session_button;
 pub mod modal;
@@ -8,3 +11,5 @@ pub mod model;
 pub mod navbar;
 mod time_overlay;
 mod time_util;
+mod timezone_picker;
+pub mod validation;
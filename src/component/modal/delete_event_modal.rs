@@ -3,18 +3,18 @@ use reactive_stores::Store;
 
 use crate::{
     app::{GlobalState, GlobalStateStoreFields as _},
-    obf_util::UrlParamsStoreFields,
+    error::{error_message, AppError},
 };
 
 #[component]
-pub fn DeleteEventModal(session_id: i64, owner_id: String) -> impl IntoView {
+pub fn DeleteEventModal(session_id: i64, can_manage: bool) -> impl IntoView {
     let state = expect_context::<Store<GlobalState>>();
     let calendar_events = state.calendar_events();
-    let user_id = state.url_params().user_id().get_untracked();
-    let modal_name = format!("modal_{}", session_id);
+    let link = state.url_params().get_untracked().encode();
+    let modal_name = format!("modal_{session_id}");
 
     // noderef and error signal (window)
-    let (error_status, set_error_status) = signal(false);
+    let (error_status, set_error_status) = signal::<Option<String>>(None);
 
     // sometimes modals replace each other upon deletion
     let e = NodeRef::<Dialog>::new();
@@ -30,39 +30,39 @@ pub fn DeleteEventModal(session_id: i64, owner_id: String) -> impl IntoView {
             e.get().unwrap().close();
         }
         Some(Err(e)) => {
-            set_error_status(true);
             log!("{:?}", e);
+            set_error_status(Some(error_message(&e)));
         }
         None => {}
     });
 
-    // only show if user owns event
-    if user_id == owner_id {
+    // only show to the owner and moderators
+    if can_manage {
         view! {
-            <button type="button" onclick={format!("{}.showModal()", modal_name)} class="btn btn-sm btn-circle btn-ghost">{"✕"}</button>
+            <button type="button" onclick={format!("{modal_name}.showModal()")} class="btn btn-sm btn-circle btn-ghost">{"✕"}</button>
             <dialog node_ref=e id={modal_name} class="modal">
             <div class="modal-box">
                 <div class="flex">
                     <h3 class="text-lg flex-1 font-bold">Are you sure?</h3>
                     <form class="dialog flex-0">
-                        <button type="button" onclick={format!("{}.close()", modal_name)} class="btn btn-sm btn-circle btn-ghost">{"✕"}</button>
+                        <button type="button" onclick={format!("{modal_name}.close()")} class="btn btn-sm btn-circle btn-ghost">{"✕"}</button>
                     </form>
                 </div>
                 <p class="py-4">Deleting events is permanent</p>
                 {
-                    move || if error_status() {view!{
+                    move || error_status().map(|msg| view! {
                         <div role="alert" class="alert alert-error">
                             <svg xmlns="http://www.w3.org/2000/svg" class="h-6 w-6 shrink-0 stroke-current" fill="none" viewBox="0 0 24 24">
                                 <path stroke-linecap="round" stroke-linejoin="round" stroke-width="2" d="M10 14l2-2m0 0l2-2m-2 2l-2-2m2 2l2 2m7-2a9 9 0 11-18 0 9 9 0 0118 0z" />
                             </svg>
-                            <span>Error! Please Try Again</span>
+                            <span>{ msg }</span>
                         </div>
-                    }.into_any()} 
-                    else { view! {}.into_any() }
+                    })
                 }
                 <div class="modal-action">
                     <ActionForm action=delete_event>
                         <input type="text" class="hidden invisible" name="session_id" value={session_id}/>
+                        <input type="text" class="hidden invisible" name="link" value={link}/>
                         <button class="btn btn-error">Delete</button>
                     </ActionForm>
                 </div>
@@ -74,16 +74,44 @@ pub fn DeleteEventModal(session_id: i64, owner_id: String) -> impl IntoView {
     }
 }
 
+/**
+ * Deletes a session. Only its current owner or a moderator can, ownership may have changed since
+ * the page loaded
+ */
 #[server]
-pub async fn delete_event(session_id: i64) -> Result<(), ServerFnError> {
-    use crate::dao::sqlite_util::SqliteClient;
-    use sqlx::{Pool, Sqlite};
+#[cfg_attr(
+    feature = "ssr",
+    tracing::instrument(
+        name = "delete_event",
+        skip_all,
+        fields(session_id = %session_id, user_id = tracing::field::Empty),
+        err
+    )
+)]
+pub async fn delete_event(session_id: i64, link: String) -> Result<(), ServerFnError<AppError>> {
+    use crate::{
+        component::{calendar_events::caller_session, model::Role},
+        config::pool,
+        dao::sqlite_util::SqliteClient,
+        obf_util::verify_caller,
+    };
 
-    let pool = use_context::<Pool<Sqlite>>().expect("pool not found");
+    let caller = verify_caller(&link)?;
+    let user_id = caller.get_user_id();
+
+    let pool = pool()?;
     let client = SqliteClient::from_pool(pool).await;
 
+    let session = caller_session(&client, &caller, session_id).await?;
+    let role = Role::from(client.get_role(&session.server_id, &user_id).await?);
+    if !role.can_manage_session(&user_id, &session.owner, session.locked) {
+        return Err(
+            AppError::forbidden("Only the owner or a moderator can delete this session").into(),
+        );
+    }
+
     match client.delete_session(session_id).await {
         Ok(()) => Ok(()),
-        Err(e) => Err(ServerFnError::new(e)),
+        Err(e) => Err(e.into()),
     }
 }
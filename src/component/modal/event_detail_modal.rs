@@ -0,0 +1,506 @@
+use chrono::DateTime;
+use chrono_tz::Tz;
+use leptos::{html::Dialog, logging::log, prelude::*};
+use reactive_stores::Store;
+
+use crate::{
+    app::{GlobalState, GlobalStateStoreFields},
+    component::{
+        event_card::get_url,
+        host_picker::HostPicker,
+        model::{Comment, GamingSession, RsvpStatus, ServerSettingsStoreFields},
+        time_util::time_format,
+        validation::{validate_comment, MAX_COMMENT_CHARS},
+    },
+    error::{error_message, AppError},
+    obf_util::UrlParamsStoreFields,
+};
+
+/**
+ * Modal with everything about an event: every participant, owner actions and the discussion
+ * thread. Participants and the thread are loaded when the modal is opened, there is no live
+ * channel so it shows what was there at that point plus the user's own changes.
+ * Setting `open` opens the modal, it is reset once the modal is closed
+ */
+#[component]
+pub fn EventDetailModal(
+    session_id: i64,
+    title: String,
+    start_time: DateTime<Tz>,
+    end_time: DateTime<Tz>,
+    game: Option<String>,
+    description_html: Option<String>,
+    comment_count: usize,
+    // the user may edit or delete the session, their dialogs belong to the card
+    can_manage: bool,
+    locked: bool,
+    open: RwSignal<bool>,
+) -> impl IntoView {
+    let state = expect_context::<Store<GlobalState>>();
+    let user_id = state.url_params().user_id().get_untracked();
+    let link = state.url_params().get_untracked().encode();
+    let calendar_events = state.calendar_events();
+    let use_24h = state.server_settings().use_24h().get_untracked();
+    let modal_name = format!("detail_modal_{session_id}");
+    let tz = start_time.timezone();
+    let day_time_format = format!("%a {}", time_format(use_24h));
+
+    let e = NodeRef::<Dialog>::new();
+    let (error_status, set_error_status) = signal::<Option<String>>(None);
+    let (comments, set_comments) = signal::<Vec<Comment>>(vec![]);
+    let (count, set_count) = signal(comment_count);
+    let (draft, set_draft) = signal(String::new());
+
+    let load_session = ServerAction::<GetSession>::new();
+    let session_res = load_session.value();
+    let session = move || session_res.with(|res| res.clone().and_then(|res| res.ok()));
+    Effect::new(move || {
+        if let Some(Err(e)) = session_res() {
+            log!("{:?}", e);
+            set_error_status(Some(error_message(&e)));
+        }
+    });
+
+    let load_comments = ServerAction::<GetComments>::new();
+    let load_res = load_comments.value();
+    Effect::new(move || match load_res() {
+        Some(Ok(thread)) => {
+            set_count(thread.len());
+            set_comments(thread);
+        }
+        Some(Err(e)) => {
+            log!("{:?}", e);
+            set_error_status(Some(error_message(&e)));
+        }
+        None => {}
+    });
+
+    // handle ActionForm
+    let post_comment = ServerAction::<PostComment>::new();
+    let post_res = post_comment.value();
+    Effect::new(move || match post_res() {
+        Some(Ok(comment)) => {
+            set_comments.update(|v| v.push(comment));
+            set_count.update(|n| *n += 1);
+            set_draft(String::new());
+            set_error_status(None);
+        }
+        Some(Err(e)) => {
+            log!("{:?}", e);
+            set_error_status(Some(error_message(&e)));
+        }
+        None => {}
+    });
+
+    let delete_comment = ServerAction::<DeleteComment>::new();
+    let delete_res = delete_comment.value();
+    Effect::new(move || match delete_res() {
+        Some(Ok(comment_id)) => {
+            set_comments.update(|v| v.retain(|c| c.comment_id != comment_id));
+            set_count.update(|n| *n = n.saturating_sub(1));
+            set_error_status(None);
+        }
+        Some(Err(e)) => {
+            log!("{:?}", e);
+            set_error_status(Some(error_message(&e)));
+        }
+        None => {}
+    });
+
+    let load_link = link.clone();
+    Effect::new(move || {
+        if open() {
+            if let Some(dialog) = e.get() {
+                if !dialog.open() {
+                    let _ = dialog.show_modal();
+                    load_session.dispatch(GetSession {
+                        session_id,
+                        link: load_link.clone(),
+                    });
+                    load_comments.dispatch(GetComments {
+                        session_id,
+                        link: load_link.clone(),
+                    });
+                }
+            }
+        }
+    });
+
+    // updating the calendar re-renders its cards, so the count there catches up once the
+    // modal is closed
+    let on_close = move |_| {
+        open.set(false);
+        let count = count.get_untracked();
+        if count != comment_count {
+            calendar_events.update(|v| {
+                if let Some(s) = v.iter_mut().find(|s| s.session_id == session_id) {
+                    s.comment_count = count;
+                }
+            });
+        }
+    };
+
+    view! {
+        <button
+            type="button"
+            class="btn btn-sm btn-ghost px-1 gap-1"
+            aria-label="Details and comments"
+            on:click=move |_| open.set(true)
+        >
+            <svg xmlns="http://www.w3.org/2000/svg" fill="none" viewBox="0 0 24 24" stroke-width="1.5" stroke="currentColor" class="size-4">
+                <path stroke-linecap="round" stroke-linejoin="round" d="M8.625 12a.375.375 0 1 1-.75 0 .375.375 0 0 1 .75 0Zm0 0H8.25m4.125 0a.375.375 0 1 1-.75 0 .375.375 0 0 1 .75 0Zm0 0H12m4.125 0a.375.375 0 1 1-.75 0 .375.375 0 0 1 .75 0Zm0 0h-.375M21 12c0 4.556-4.03 8.25-9 8.25a9.764 9.764 0 0 1-2.555-.337A5.972 5.972 0 0 1 5.41 20.97a5.969 5.969 0 0 1-.474-.065 4.48 4.48 0 0 0 .978-2.025c.09-.457-.133-.901-.467-1.226C3.93 16.178 3 14.189 3 12c0-4.556 4.03-8.25 9-8.25s9 3.694 9 8.25Z" />
+            </svg>
+            { move || count().to_string() }
+        </button>
+        <dialog node_ref=e id={modal_name.clone()} class="modal" on:close=on_close>
+            <div class="modal-box w-96">
+                <div class="flex">
+                    <h3 class="text-lg flex-1 font-bold">{ title }</h3>
+                    <form class="dialog flex-0">
+                        <button type="button" onclick={format!("{modal_name}.close()")} class="btn btn-sm btn-circle btn-ghost">{"✕"}</button>
+                    </form>
+                </div>
+                <p class="text-sm opacity-70">
+                    {
+                        format!(
+                            "{} - {} {}{}",
+                            start_time.format(&day_time_format),
+                            end_time.format(&day_time_format),
+                            end_time.format("%Z"),
+                            game.map(|game| format!(" · {game}")).unwrap_or_default(),
+                        )
+                    }
+                </p>
+                {
+                    description_html.map(|html| view! {
+                        <div class="mt-2 text-sm break-words [&_a]:link [&_ul]:list-disc [&_ul]:pl-4 [&_ol]:list-decimal [&_ol]:pl-4" inner_html=html></div>
+                    })
+                }
+                <div class="divider">Players</div>
+                {
+                    let user_id = user_id.clone();
+                    let day_time_format = day_time_format.clone();
+                    move || match session() {
+                        Some(session) => view! {
+                            <SessionPeople
+                                session=session
+                                user_id=user_id.clone()
+                                tz=tz
+                                day_time_format=day_time_format.clone()
+                                use_24h=use_24h
+                                can_manage=can_manage
+                                locked=locked
+                            />
+                        }.into_any(),
+                        None => view! { <span class="loading loading-dots loading-sm"></span> }.into_any(),
+                    }
+                }
+                <div class="divider">Comments</div>
+                {
+                    move || error_status().map(|msg| view! {
+                        <div role="alert" class="alert alert-error">
+                            <svg xmlns="http://www.w3.org/2000/svg" class="h-6 w-6 shrink-0 stroke-current" fill="none" viewBox="0 0 24 24">
+                                <path stroke-linecap="round" stroke-linejoin="round" stroke-width="2" d="M10 14l2-2m0 0l2-2m-2 2l-2-2m2 2l2 2m7-2a9 9 0 11-18 0 9 9 0 0118 0z" />
+                            </svg>
+                            <span>{ msg }</span>
+                        </div>
+                    })
+                }
+                <ul class="list max-h-64 overflow-y-auto">
+                    {
+                        let user_id = user_id.clone();
+                        let link = link.clone();
+                        let day_time_format = day_time_format.clone();
+                        move || comments.get().into_iter().map(|c| {
+                            let comment_id = c.comment_id;
+                            let own = c.user_id == user_id;
+                            let link = link.clone();
+                            view! {
+                                <li class="list-row px-0">
+                                    <div class="list-col-grow">
+                                        <div class="text-xs">
+                                            <span class="font-bold">{ c.user_id.clone() }</span>
+                                            <span class="opacity-60">{ format!(" · {}", c.created_at.with_timezone(&tz).format(&day_time_format)) }</span>
+                                        </div>
+                                        <p class="text-sm whitespace-pre-line break-words">{ c.body.clone() }</p>
+                                    </div>
+                                    {
+                                        own.then(|| view! {
+                                            <button
+                                                type="button"
+                                                class="btn btn-xs btn-ghost"
+                                                aria-label="Delete comment"
+                                                on:click=move |_| {
+                                                    delete_comment.dispatch(DeleteComment {
+                                                        comment_id,
+                                                        link: link.clone(),
+                                                    });
+                                                }
+                                            >
+                                                {"✕"}
+                                            </button>
+                                        })
+                                    }
+                                </li>
+                            }
+                        }).collect_view()
+                    }
+                    {
+                        move || (load_res.with(|r| r.as_ref().is_some_and(|r| r.is_ok())) && comments.with(|c| c.is_empty())).then(|| view! {
+                            <li class="text-sm opacity-70">No comments yet</li>
+                        })
+                    }
+                </ul>
+                <ActionForm action=post_comment>
+                    // hidden vars for action form
+                    <input type="text" class="hidden invisible" name="session_id" value={session_id}/>
+                    <input type="text" class="hidden invisible" name="link" value={link.clone()}/>
+                    <div class="flex gap-2 mt-2">
+                        <textarea
+                            class="textarea textarea-sm flex-1"
+                            name="body"
+                            rows="2"
+                            placeholder="Bringing snacks, running 10 min late..."
+                            maxlength=MAX_COMMENT_CHARS
+                            required
+                            prop:value=draft
+                            on:input=move |ev| set_draft(event_target_value(&ev))
+                        ></textarea>
+                        // same rules as the server, e.g. whitespace only drafts can't be sent
+                        <button
+                            type="submit"
+                            class="btn btn-neutral self-end"
+                            disabled=move || draft.with(|d| validate_comment(d).is_err())
+                        >
+                            Send
+                        </button>
+                    </div>
+                </ActionForm>
+            </div>
+        </dialog>
+    }
+}
+
+/**
+ * Owner, participants by RSVP and the waitlist of a session, with the owner's actions
+ */
+#[component]
+fn SessionPeople(
+    session: GamingSession,
+    user_id: String,
+    tz: Tz,
+    day_time_format: String,
+    use_24h: bool,
+    can_manage: bool,
+    locked: bool,
+) -> impl IntoView {
+    let session_id = session.session_id;
+    let owner = session.owner.get_name();
+    let is_owner = owner == user_id;
+    let candidates = session
+        .participants
+        .iter()
+        .filter(|p| p.get_name() != owner && p.status.is_attending())
+        .map(|p| p.get_name())
+        .collect::<Vec<_>>();
+
+    let person = move |name: String, detail: String, badge: Option<&'static str>| {
+        view! {
+            <li class="list-row px-0 py-1 items-center">
+                <div class="avatar">
+                    <div class="w-8 rounded-full">
+                        <img src={ get_url() } alt={format!("{name}'s profile picture")} />
+                    </div>
+                </div>
+                <div>
+                    <div class="text-sm font-bold">{ name }</div>
+                    <div class="text-xs opacity-60">{ detail }</div>
+                </div>
+                { badge.map(|label| view! { <span class="badge badge-sm">{ label }</span> }) }
+            </li>
+        }
+    };
+
+    view! {
+        <p class="text-sm">
+            <span class="font-bold">Owner: </span>
+            { owner.clone() }
+        </p>
+        <ul class="list max-h-64 overflow-y-auto">
+            {
+                RsvpStatus::ALL.into_iter().flat_map(|status| {
+                    session.participants.iter().filter(move |p| p.status == status).cloned()
+                })
+                .map(|p| {
+                    let mut detail = p
+                        .joined_at
+                        .map(|t| format!("RSVP'd {}", t.with_timezone(&tz).format(&day_time_format)))
+                        .unwrap_or_default();
+                    if let Some(eta) = p.eta {
+                        detail = format!("arriving {} · {}", eta.with_timezone(&tz).format(time_format(use_24h)), detail);
+                    }
+                    person(p.get_name(), detail, Some(p.status.label()))
+                })
+                .collect_view()
+            }
+            {
+                session.waitlist.iter().enumerate().map(|(i, p)| {
+                    person(p.get_name(), format!("#{} on the waitlist", i + 1), Some("Waiting"))
+                })
+                .collect_view()
+            }
+        </ul>
+        <div class="flex flex-row justify-end gap-1 mt-2">
+            { (is_owner && !locked).then(|| view! { <HostPicker session_id={session_id} candidates={candidates} /> }) }
+            {
+                can_manage.then(|| view! {
+                    <button type="button" class="btn btn-xs" onclick={format!("update_modal_{session_id}.showModal()")}>Edit</button>
+                    <button type="button" class="btn btn-xs btn-error" onclick={format!("modal_{session_id}.showModal()")}>Delete</button>
+                })
+            }
+        </div>
+    }
+}
+
+/**
+ * A session with its participants and waitlist
+ */
+#[server]
+#[cfg_attr(
+    feature = "ssr",
+    tracing::instrument(
+        name = "get_session",
+        skip_all,
+        fields(session_id = %session_id, user_id = tracing::field::Empty),
+        err
+    )
+)]
+pub async fn get_session(
+    session_id: i64,
+    link: String,
+) -> Result<GamingSession, ServerFnError<AppError>> {
+    use crate::{
+        component::calendar_events::{caller_session, load_session},
+        config::pool,
+        dao::sqlite_util::SqliteClient,
+        obf_util::verify_caller,
+    };
+
+    let caller = verify_caller(&link)?;
+
+    let pool = pool()?;
+    let client = SqliteClient::from_pool(pool).await;
+
+    let record = caller_session(&client, &caller, session_id).await?;
+    Ok(load_session(&client, &record).await?)
+}
+
+/**
+ * A session's discussion thread, oldest first
+ */
+#[server]
+#[cfg_attr(
+    feature = "ssr",
+    tracing::instrument(
+        name = "get_comments",
+        skip_all,
+        fields(session_id = %session_id, user_id = tracing::field::Empty),
+        err
+    )
+)]
+pub async fn get_comments(
+    session_id: i64,
+    link: String,
+) -> Result<Vec<Comment>, ServerFnError<AppError>> {
+    use crate::{
+        component::calendar_events::caller_session, config::pool, dao::sqlite_util::SqliteClient,
+        obf_util::verify_caller,
+    };
+
+    let caller = verify_caller(&link)?;
+
+    let pool = pool()?;
+    let client = SqliteClient::from_pool(pool).await;
+
+    caller_session(&client, &caller, session_id).await?;
+    Ok(client
+        .get_comments(session_id)
+        .await?
+        .iter()
+        .map(Comment::try_from)
+        .collect::<Result<Vec<_>, _>>()?)
+}
+
+/**
+ * Adds a comment to a session's thread
+ */
+#[server]
+#[cfg_attr(
+    feature = "ssr",
+    tracing::instrument(
+        name = "post_comment",
+        skip_all,
+        fields(session_id = %session_id, user_id = tracing::field::Empty),
+        err
+    )
+)]
+pub async fn post_comment(
+    session_id: i64,
+    link: String,
+    body: String,
+) -> Result<Comment, ServerFnError<AppError>> {
+    use crate::{
+        component::calendar_events::caller_session, config::pool, dao::sqlite_util::SqliteClient,
+        obf_util::verify_caller,
+    };
+
+    let body = validate_comment(&body).map_err(AppError::validation)?;
+
+    let caller = verify_caller(&link)?;
+    let user_id = caller.get_user_id();
+
+    let pool = pool()?;
+    let client = SqliteClient::from_pool(pool).await;
+
+    caller_session(&client, &caller, session_id).await?;
+    let record = client.create_comment(session_id, &user_id, &body).await?;
+    Ok(Comment::try_from(&record)?)
+}
+
+/**
+ * Deletes a comment, returning its id. Users can only delete their own
+ */
+#[server]
+#[cfg_attr(
+    feature = "ssr",
+    tracing::instrument(
+        name = "delete_comment",
+        skip_all,
+        fields(comment_id = %comment_id, user_id = tracing::field::Empty),
+        err
+    )
+)]
+pub async fn delete_comment(comment_id: i64, link: String) -> Result<i64, ServerFnError<AppError>> {
+    use crate::{
+        component::calendar_events::caller_session, config::pool, dao::sqlite_util::SqliteClient,
+        obf_util::verify_caller,
+    };
+
+    let caller = verify_caller(&link)?;
+    let user_id = caller.get_user_id();
+
+    let pool = pool()?;
+    let client = SqliteClient::from_pool(pool).await;
+
+    let comment = client
+        .get_comment(comment_id)
+        .await?
+        .ok_or(AppError::not_found("comment"))?;
+    if comment.user_id != user_id {
+        return Err(AppError::forbidden("You can only delete your own comments").into());
+    }
+    caller_session(&client, &caller, comment.session_id).await?;
+
+    client.delete_comment(comment_id).await?;
+    Ok(comment_id)
+}
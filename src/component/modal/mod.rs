@@ -1,2 +1,6 @@
 pub mod delete_event_modal;
+pub mod event_detail_modal;
 pub mod new_event_modal;
+pub mod poll_modal;
+pub mod roles_modal;
+pub mod update_event_modal;
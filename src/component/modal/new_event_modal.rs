@@ -1,14 +1,18 @@
-use chrono::{DateTime, FixedOffset};
+use chrono_tz::Tz;
 use leptos::{html::Dialog, logging::log, prelude::*};
 use reactive_stores::Store;
 
 use crate::{
     app::{GlobalState, GlobalStateStoreFields},
     component::{
-        model::{GamingSession, User},
-        time_util::{convert_simple_time, create_baseline, get_local_time},
+        model::GamingSession,
+        time_util::{create_baseline, get_local_time},
+        validation::{
+            earliest_start, EventForm, MAX_DESCRIPTION_CHARS, MAX_GAME_CHARS, MAX_PARTICIPANTS,
+            MAX_TITLE_CHARS,
+        },
     },
-    obf_util::UrlParamsStoreFields,
+    error::{clashes, error_message, field_errors, AppError, FieldErrors},
 };
 
 /**
@@ -17,21 +21,80 @@ use crate::{
 #[component]
 pub fn NewEventModal() -> impl IntoView {
     let state = expect_context::<Store<GlobalState>>();
-    let server_id = state.url_params().server_id().get_untracked();
-    let user_id = state.url_params().user_id().get_untracked();
+    let link = state.url_params().get_untracked().encode();
     let calendar_events = state.calendar_events();
 
     let e = NodeRef::<Dialog>::new();
-    let (error_status, set_error_status) = signal(false);
+    let (error_status, set_error_status) = signal::<Option<String>>(None);
+    let (invalid_fields, set_invalid_fields) = signal(FieldErrors::default());
+    // the event clashes with others the user attends, creating it needs confirmation
+    let (double_booked, set_double_booked) = signal(false);
 
-    // current time for timestamp
-    let (local_time, set_local_time) =
-        signal::<DateTime<FixedOffset>>(DateTime::from_timestamp(0, 0).unwrap().fixed_offset());
+    // times are entered in the creator's profile timezone, or the server's when they have none
+    let tz = state
+        .user_timezone()
+        .get_untracked()
+        .and_then(|t| t.parse::<Tz>().ok())
+        .unwrap_or(state.server_settings().get_untracked().get_tz());
+
+    // form values, checked with the server's rules while typing
+    let (title, set_title) = signal(String::new());
+    let (start, set_start) = signal(String::new());
+    let (end, set_end) = signal(String::new());
+    let (game, set_game) = signal(String::new());
+    let (max_participants, set_max_participants) = signal(String::new());
+    let (description, set_description) = signal(String::new());
+    let local_errors = Memo::new(move |_| {
+        let settings = state.server_settings().get();
+        let offset = settings.day_start_hour;
+        let now = get_local_time(tz);
+        let baseline = create_baseline(now, offset).ok()?;
+        EventForm {
+            title: title(),
+            start: start(),
+            end: end(),
+            game: game(),
+            max_participants: max_participants(),
+            description: description(),
+        }
+        .validate(baseline, offset, earliest_start(now.to_utc()))
+        .err()
+    });
+    // a range dragged over the calendar opens the modal with its times filled in
+    let new_event_draft = state.new_event_draft();
     Effect::new(move || {
-        // set time locally
-        let t = get_local_time();
-        set_local_time(t);
+        if let Some((draft_start, draft_end)) = new_event_draft.get() {
+            set_start(draft_start.with_timezone(&tz).format("%H:%M").to_string());
+            set_end(draft_end.with_timezone(&tz).format("%H:%M").to_string());
+            new_event_draft.set(None);
+            if let Some(dialog) = e.get_untracked() {
+                let _ = dialog.show_modal();
+            }
+        }
     });
+    let values = move |field: &str| match field {
+        "title" => title(),
+        "start" => start(),
+        "end" => end(),
+        "game" => game(),
+        "description" => description(),
+        _ => max_participants(),
+    };
+
+    // message under an input. The server's answer wins, local problems only show once the
+    // field has a value
+    let field_error = move |field: &'static str| {
+        move || {
+            invalid_fields
+                .with(|f| f.get(field))
+                .or_else(|| {
+                    (!values(field).is_empty())
+                        .then(|| local_errors.with(|e| e.as_ref().and_then(|e| e.get(field))))
+                        .flatten()
+                })
+                .map(|msg| view! { <p class="text-error">{ msg }</p> })
+        }
+    };
 
     // handle ActionForm
     let create_event = ServerAction::<CreateEvent>::new();
@@ -40,11 +103,17 @@ pub fn NewEventModal() -> impl IntoView {
         Some(Ok(s)) => {
             calendar_events.update(|v| v.push(s));
             e.get().unwrap().close();
-            set_error_status(false);
+            set_error_status(None);
+            set_invalid_fields(FieldErrors::default());
+            set_double_booked(false);
         }
         Some(Err(e)) => {
             log!("{:?}", e);
-            set_error_status(true);
+            // problems with single fields are shown next to them instead
+            let fields = field_errors(&e);
+            set_error_status(fields.is_empty().then(|| error_message(&e)));
+            set_double_booked(!clashes(&e).is_empty());
+            set_invalid_fields(fields);
         }
         None => {}
     });
@@ -68,21 +137,25 @@ pub fn NewEventModal() -> impl IntoView {
                     </div>
                     <ActionForm action=create_event>
                         // hidden vars for action form -- will change if there is a better fix
-                        <input type="text" class="hidden invisible" name="server_id" value={server_id}/>
-                        <input type="text" class="hidden invisible" name="user_id" value={user_id.clone()}/>
-                        <input type="text" class="hidden invisible" name="owner" value={user_id}/>
+                        <input type="text" class="hidden invisible" name="link" value={link}/>
                         <input type="text" class="hidden invisible" name="picture" value={"placeholder"}/>
-                        <input type="text" class="hidden invisible" name="baseline_time" value={move || local_time().to_rfc3339()} />
-                        <input type="text" class="hidden invisible" name="offset" value={state.offset().get_untracked()} />
-                        <fieldset class="fieldset w-full bg-base-200 border border-base-300 p-4 rounded-box">
+                        <input type="text" class="hidden invisible" name="timezone" value={tz.name()}/>
+                        // edits make the server's answer stale
+                        <fieldset
+                            class="fieldset w-full bg-base-200 border border-base-300 p-4 rounded-box"
+                            on:input=move |_| {
+                                set_invalid_fields(FieldErrors::default());
+                                set_double_booked(false);
+                            }
+                        >
                             {
-                                move || if error_status() {
+                                move || if let Some(msg) = error_status() {
                                     view! {
                                         <div role="alert" class="alert alert-error">
                                             <svg xmlns="http://www.w3.org/2000/svg" class="h-6 w-6 shrink-0 stroke-current" fill="none" viewBox="0 0 24 24">
                                                 <path stroke-linecap="round" stroke-linejoin="round" stroke-width="2" d="M10 14l2-2m0 0l2-2m-2 2l-2-2m2 2l2 2m7-2a9 9 0 11-18 0 9 9 0 0118 0z" />
                                             </svg>
-                                            <span>Error! Please Try Again</span>
+                                            <span>{ msg }</span>
                                         </div>
                                     }.into_any()
                                 } else {
@@ -92,18 +165,59 @@ pub fn NewEventModal() -> impl IntoView {
                             <legend class="fieldset-legend">Event</legend>
 
                             <label class="fieldset-label">Title</label>
-                            <input type="text" class="input" placeholder="Title" name="title" maxlength="30" required />
+                            <input
+                                type="text"
+                                class="input"
+                                placeholder="Title"
+                                name="title"
+                                maxlength=MAX_TITLE_CHARS
+                                required
+                                on:input=move |ev| set_title(event_target_value(&ev))
+                            />
+                            { field_error("title") }
 
                             <label class="fieldset-label">Start Time</label>
-                            <input type="time" class="input" name="start" required />
+                            <input type="time" class="input" name="start" required prop:value=start on:input=move |ev| set_start(event_target_value(&ev)) />
+                            { field_error("start") }
 
                             <label class="fieldset-label">End Time</label>
-                            <input type="time" class="input" name="end" required />
+                            <input type="time" class="input" name="end" required prop:value=end on:input=move |ev| set_end(event_target_value(&ev)) />
+                            { field_error("end") }
+
+                            <p class="fieldset-label">{ format!("Times are in {tz}") }</p>
 
                             <label class="fieldset-label">Game (optional)</label>
-                            <input type="text" class="input" name="game" maxlength="30" />
+                            <input type="text" class="input" name="game" maxlength=MAX_GAME_CHARS on:input=move |ev| set_game(event_target_value(&ev)) />
+                            { field_error("game") }
+
+                            <label class="fieldset-label">Max Players (optional)</label>
+                            <input
+                                type="number"
+                                class="input"
+                                name="max_participants"
+                                min="1"
+                                max=MAX_PARTICIPANTS
+                                on:input=move |ev| set_max_participants(event_target_value(&ev))
+                            />
+                            { field_error("max_participants") }
+
+                            <label class="fieldset-label">Description (optional, Markdown)</label>
+                            <textarea
+                                class="textarea"
+                                name="description"
+                                rows="3"
+                                placeholder="Bring mics, ranked only, need a healer"
+                                maxlength=MAX_DESCRIPTION_CHARS
+                                on:input=move |ev| set_description(event_target_value(&ev))
+                            ></textarea>
+                            { field_error("description") }
 
                             <button type="submit" class="btn btn-neutral mt-4">Create</button>
+                            {
+                                move || double_booked().then(|| view! {
+                                    <button type="submit" name="force" value="true" class="btn btn-warning">Create anyway</button>
+                                })
+                            }
                         </fieldset>
                     </ActionForm>
                 </div>
@@ -114,74 +228,118 @@ pub fn NewEventModal() -> impl IntoView {
 
 // TODO: How do I pass in additional things to this fn that aren't just from the forms
 #[server]
+#[cfg_attr(
+    feature = "ssr",
+    tracing::instrument(
+        name = "create_event",
+        skip_all,
+        fields(
+            server_id = tracing::field::Empty,
+            user_id = tracing::field::Empty
+        ),
+        err
+    )
+)]
 pub async fn create_event(
-    title: String,
-    start: String,
-    end: String,
-    server_id: String,
-    user_id: String,
-    owner: String,
+    link: String,
     picture: String,
-    baseline_time: String,
-    offset: String,
-    game: String,
-) -> Result<GamingSession, ServerFnError> {
-    use crate::dao::sqlite_util::SqliteClient;
-    use sqlx::{Pool, Sqlite};
-
-    let pool = use_context::<Pool<Sqlite>>().expect("pool not found");
-    let client = SqliteClient::from_pool(pool).await;
-
-    let offset_usize: usize = offset.parse().unwrap();
-    let game_opt = if game.trim().is_empty() {
-        None
-    } else {
-        Some(game)
+    timezone: String,
+    #[server(flatten)] event: EventForm,
+    #[server(default)] force: bool,
+) -> Result<GamingSession, ServerFnError<AppError>> {
+    use crate::component::{
+        calendar_events::find_clashes,
+        model::{RsvpStatus, ServerSettings, User},
+        validation::validate_entry_timezone,
+    };
+    use crate::{
+        config::pool,
+        dao::sqlite_util::{SessionDetails, SqliteClient},
+        markdown::render_markdown,
+        obf_util::verify_caller,
     };
 
-    let baseline = DateTime::parse_from_rfc3339(&baseline_time)?;
+    let caller = verify_caller(&link)?;
+    let (server_id, user_id) = (caller.get_server_id(), caller.get_user_id());
 
-    let adjusted_baseline = create_baseline(baseline, offset_usize)
-        .map_err(|e| ServerFnError::new("failed to adjust baseline"))?;
-    let start_datetime = convert_simple_time(start, adjusted_baseline, offset_usize);
-    let end_datetime = convert_simple_time(end, adjusted_baseline, offset_usize);
+    let pool = pool()?;
+    let client = SqliteClient::from_pool(pool).await;
 
-    let session_record = client
-        .create_session(
+    // times are entered relative to the current day in the creator's profile timezone, or the
+    // server's. The day start comes from the server's settings, not the client
+    let settings = client
+        .get_server(&server_id)
+        .await?
+        .map(|r| ServerSettings::from(&r))
+        .unwrap_or_else(crate::config::default_settings);
+    let offset = settings.day_start_hour;
+    let profile = client.get_profile(&user_id).await?;
+    let tz = validate_entry_timezone(
+        &timezone,
+        profile.as_ref().map(|p| p.timezone.as_str()),
+        settings.get_tz(),
+    )
+    .map_err(AppError::validation)?;
+    let now = get_local_time(tz);
+    let baseline = create_baseline(now, offset).map_err(AppError::storage)?;
+
+    let event = event
+        .validate(baseline, offset, earliest_start(now.to_utc()))
+        .map_err(AppError::from)?;
+
+    // the creator joins the event, warn if they're busy then unless they confirmed
+    if !force {
+        let clashes = find_clashes(
+            &client,
             &server_id,
-            &title,
-            &start_datetime.to_rfc3339(),
-            &end_datetime.to_rfc3339(),
-            &owner,
-            game_opt.clone(),
+            &user_id,
+            event.start.to_utc(),
+            event.end.to_utc(),
+            None,
         )
-        .await
-        .map_err(|e| ServerFnError::new(format!("failed to create session: {}", e)))?;
-    let session_id = session_record.session_id.unwrap();
+        .await?;
+        if !clashes.is_empty() {
+            return Err(AppError::DoubleBooked(clashes).into());
+        }
+    }
+
+    let session_record = client
+        .create_session(&server_id, &user_id, &SessionDetails::from(&event))
+        .await?;
+    let session_id = session_record
+        .session_id
+        .ok_or(AppError::storage("session was not created"))?;
     let user_result = client
-        .create_session_user(&user_id, session_id, &picture)
+        .create_session_user(&user_id, session_id, &picture, RsvpStatus::Going.as_str())
         .await;
 
     match user_result {
         Ok(_) => {
             let user = User {
                 name: user_id,
-                picture: picture,
+                picture,
+                timezone: profile.map(|p| p.timezone),
+                status: RsvpStatus::Going,
+                eta: None,
+                joined_at: Some(chrono::Utc::now()),
             };
             Ok(GamingSession {
-                server_id: server_id,
-                session_id: session_id,
-                title: title,
-                start_time: start_datetime.to_utc(),
-                end_time: end_datetime.to_utc(),
+                server_id,
+                session_id,
+                title: event.title,
+                start_time: event.start.to_utc(),
+                end_time: event.end.to_utc(),
                 owner: user.clone(),
                 participants: vec![user],
-                game: game_opt,
+                game: event.game,
+                max_participants: event.max_participants,
+                waitlist: vec![],
+                locked: false,
+                description_html: event.description.as_deref().map(render_markdown),
+                description: event.description,
+                comment_count: 0,
             })
         }
-        Err(e) => Err(ServerFnError::new(format!(
-            "failed to create session: {}",
-            e
-        ))),
+        Err(e) => Err(e.into()),
     }
 }
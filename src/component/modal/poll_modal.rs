@@ -0,0 +1,231 @@
+use chrono::Duration;
+use chrono_tz::Tz;
+use leptos::{html::Dialog, logging::log, prelude::*};
+use reactive_stores::Store;
+
+use crate::{
+    app::{GlobalState, GlobalStateStoreFields},
+    component::{
+        availability_poll::{CreatePoll, DeletePoll, SchedulePoll},
+        model::ServerSettingsStoreFields,
+        time_util::{best_slot, get_user_timezone, time_format},
+    },
+    error::error_message,
+    obf_util::UrlParamsStoreFields,
+};
+
+/**
+ * Modal to create availability polls, pick which one to paint over the calendar and let the
+ * organizer turn the best slot into a session
+ */
+#[component]
+pub fn PollModal() -> impl IntoView {
+    let state = expect_context::<Store<GlobalState>>();
+    let link = state.url_params().get_untracked().encode();
+    let polls = state.polls();
+    let active_poll = state.active_poll();
+    let calendar_events = state.calendar_events();
+    let use_24h = state.server_settings().use_24h().get_untracked();
+
+    let e = NodeRef::<Dialog>::new();
+    let (error_status, set_error_status) = signal::<Option<String>>(None);
+
+    // dates are whole days in the user's timezone, which is only known on the client
+    let (timezone, set_timezone) = signal(String::new());
+    Effect::new(move || {
+        set_timezone(get_user_timezone(&state.server_settings().get_untracked()).to_string());
+    });
+    let tz = move || timezone().parse::<Tz>().unwrap_or(Tz::UTC);
+
+    // poll that the last schedule or delete was for
+    let (pending_poll, set_pending_poll) = signal::<Option<i64>>(None);
+    let remove_pending_poll = move || {
+        if let Some(poll_id) = pending_poll.get_untracked() {
+            polls.update(|v| v.retain(|p| p.poll_id != poll_id));
+            if active_poll.get_untracked() == Some(poll_id) {
+                active_poll.set(None);
+            }
+        }
+    };
+
+    // handle CreatePoll ActionForm
+    let create_poll = ServerAction::<CreatePoll>::new();
+    let create_res = create_poll.value();
+    Effect::new(move || match create_res() {
+        Some(Ok(poll)) => {
+            active_poll.set(Some(poll.poll_id));
+            polls.update(|v| v.push(poll));
+            e.get().unwrap().close();
+            set_error_status(None);
+        }
+        Some(Err(e)) => {
+            log!("{:?}", e);
+            set_error_status(Some(error_message(&e)));
+        }
+        None => {}
+    });
+
+    // handle scheduling
+    let schedule_poll = ServerAction::<SchedulePoll>::new();
+    let schedule_res = schedule_poll.value();
+    Effect::new(move || match schedule_res() {
+        Some(Ok(session)) => {
+            calendar_events.update(|v| v.push(session));
+            remove_pending_poll();
+            e.get().unwrap().close();
+            set_error_status(None);
+        }
+        Some(Err(e)) => {
+            log!("{:?}", e);
+            set_error_status(Some(error_message(&e)));
+        }
+        None => {}
+    });
+
+    // handle deletion
+    let delete_poll = ServerAction::<DeletePoll>::new();
+    let delete_res = delete_poll.value();
+    Effect::new(move || match delete_res() {
+        Some(Ok(())) => {
+            remove_pending_poll();
+            set_error_status(None);
+        }
+        Some(Err(e)) => {
+            log!("{:?}", e);
+            set_error_status(Some(error_message(&e)));
+        }
+        None => {}
+    });
+
+    view! {
+        // banner while painting availability
+        {
+            move || {
+                polls
+                    .get()
+                    .into_iter()
+                    .find(|p| Some(p.poll_id) == active_poll.get())
+                    .map(|poll| view! {
+                        <div role="alert" class="alert absolute bottom-5 left-5">
+                            <span>{ format!("Click or drag over the hours you're free for \"{}\"", poll.title) }</span>
+                            <button type="button" class="btn btn-sm" on:click=move |_| active_poll.set(None)>Done</button>
+                        </div>
+                    })
+            }
+        }
+        <div class="absolute bottom-5 right-24">
+            <button class="btn btn-xl btn-circle btn-accent" onclick="poll_modal.showModal()" aria-label="Find a time">
+                <svg width="32" height="32" xmlns="http://www.w3.org/2000/svg" fill="none" viewBox="0 0 24 24" stroke-width="1.5" stroke="currentColor">
+                    <path stroke-linecap="round" stroke-linejoin="round" d="M6.75 3v2.25M17.25 3v2.25M3 18.75V7.5a2.25 2.25 0 0 1 2.25-2.25h13.5A2.25 2.25 0 0 1 21 7.5v11.25m-18 0A2.25 2.25 0 0 0 5.25 21h13.5A2.25 2.25 0 0 0 21 18.75m-18 0v-7.5A2.25 2.25 0 0 1 5.25 9h13.5A2.25 2.25 0 0 1 21 11.25v7.5" />
+                </svg>
+            </button>
+        </div>
+        <dialog node_ref=e id="poll_modal" class="modal">
+            <div class="modal-box w-96">
+                <div class="flex">
+                    <h3 class="text-lg flex-1 font-bold">Find a Time</h3>
+                    <form class="dialog flex-0">
+                        <button type="button" onclick="poll_modal.close()" class="btn btn-sm btn-circle btn-ghost">{"✕"}</button>
+                    </form>
+                </div>
+                {
+                    move || error_status().map(|msg| view! {
+                        <div role="alert" class="alert alert-error">
+                            <svg xmlns="http://www.w3.org/2000/svg" class="h-6 w-6 shrink-0 stroke-current" fill="none" viewBox="0 0 24 24">
+                                <path stroke-linecap="round" stroke-linejoin="round" stroke-width="2" d="M10 14l2-2m0 0l2-2m-2 2l-2-2m2 2l2 2m7-2a9 9 0 11-18 0 9 9 0 0118 0z" />
+                            </svg>
+                            <span>{ msg }</span>
+                        </div>
+                    })
+                }
+                // open polls for the displayed day
+                <ul class="list">
+                    {
+                        move || polls.get().into_iter().map(|poll| {
+                            let poll_id = poll.poll_id;
+                            let tz = tz();
+                            let best = best_slot(&poll.heatmap).map(|(start, end)| format!(
+                                "Best: {} - {} ({} free)",
+                                start.with_timezone(&tz).format(&format!("%a {}", time_format(use_24h))),
+                                end.with_timezone(&tz).format(time_format(use_24h)),
+                                poll.get_count(start),
+                            ));
+                            let is_owner = poll.owner == state.url_params().user_id().get_untracked();
+                            view! {
+                                <li class="list-row">
+                                    <div>
+                                        <div class="font-bold">{ poll.title.clone() }</div>
+                                        <div class="text-xs opacity-70">
+                                            { format!(
+                                                "{} to {}, {} answered",
+                                                poll.start_time.with_timezone(&tz).format("%b %-d"),
+                                                (poll.end_time - Duration::seconds(1)).with_timezone(&tz).format("%b %-d"),
+                                                poll.respondents,
+                                            ) }
+                                        </div>
+                                        <div class="text-xs">{ best.unwrap_or("Nobody has answered yet".to_string()) }</div>
+                                    </div>
+                                    <div class="flex flex-col gap-1">
+                                        <button
+                                            type="button"
+                                            class="btn btn-xs"
+                                            on:click=move |_| {
+                                                active_poll.set(Some(poll_id));
+                                                e.get().unwrap().close();
+                                            }
+                                        >Mark availability</button>
+                                        {
+                                            is_owner.then(|| view! {
+                                                <button
+                                                    type="button"
+                                                    class="btn btn-xs btn-primary"
+                                                    on:click=move |_| {
+                                                        set_pending_poll(Some(poll_id));
+                                                        schedule_poll.dispatch(SchedulePoll {
+                                                            poll_id,
+                                                            link: state.url_params().get_untracked().encode(),
+                                                        });
+                                                    }
+                                                >Schedule best slot</button>
+                                                <button
+                                                    type="button"
+                                                    class="btn btn-xs btn-error"
+                                                    on:click=move |_| {
+                                                        set_pending_poll(Some(poll_id));
+                                                        delete_poll.dispatch(DeletePoll {
+                                                            poll_id,
+                                                            link: state.url_params().get_untracked().encode(),
+                                                        });
+                                                    }
+                                                >Delete</button>
+                                            })
+                                        }
+                                    </div>
+                                </li>
+                            }
+                        }).collect_view()
+                    }
+                </ul>
+                <ActionForm action=create_poll>
+                    // hidden vars for action form
+                    <input type="text" class="hidden invisible" name="link" value={link}/>
+                    <input type="text" class="hidden invisible" name="timezone" value={timezone}/>
+                    <fieldset class="fieldset w-full bg-base-200 border border-base-300 p-4 rounded-box">
+                        <legend class="fieldset-legend">New Poll</legend>
+
+                        <label class="fieldset-label">Title</label>
+                        <input type="text" class="input" placeholder="Title" name="title" maxlength="30" required />
+
+                        <label class="fieldset-label">First Day</label>
+                        <input type="date" class="input" name="start_date" required />
+
+                        <label class="fieldset-label">Last Day</label>
+                        <input type="date" class="input" name="end_date" required />
+
+                        <button type="submit" class="btn btn-neutral mt-4">Create Poll</button>
+                    </fieldset>
+                </ActionForm>
+            </div>
+        </dialog>
+    }
+}
@@ -0,0 +1,204 @@
+use leptos::{html::Dialog, logging::log, prelude::*};
+use reactive_stores::Store;
+
+use crate::{
+    app::{GlobalState, GlobalStateStoreFields},
+    component::model::{MemberRole, Role},
+    error::{error_message, AppError},
+};
+
+/**
+ * Settings modal for admins to make users moderators or admins of the server
+ */
+#[component]
+pub fn RolesModal() -> impl IntoView {
+    let state = expect_context::<Store<GlobalState>>();
+    let link = state.url_params().get_untracked().encode();
+
+    let e = NodeRef::<Dialog>::new();
+    let (error_status, set_error_status) = signal::<Option<String>>(None);
+    let (roles, set_roles) = signal::<Vec<MemberRole>>(vec![]);
+
+    // handle ActionForm
+    let set_role = ServerAction::<SetRole>::new();
+    let server_res = set_role.value();
+    Effect::new(move || match server_res() {
+        Some(Ok(member)) => {
+            set_roles.update(|v| {
+                v.retain(|m| m.user_id != member.user_id);
+                if member.role != Role::Member {
+                    v.push(member);
+                    v.sort_by(|a, b| a.user_id.cmp(&b.user_id));
+                }
+            });
+            set_error_status(None);
+        }
+        Some(Err(e)) => {
+            log!("{:?}", e);
+            set_error_status(Some(error_message(&e)));
+        }
+        None => {}
+    });
+
+    view! {
+        <button
+            type="button"
+            class="btn m-1"
+            on:click=move |_| e.get().unwrap().show_modal().unwrap()
+        >
+            Roles
+        </button>
+        <dialog node_ref=e id="roles_modal" class="modal">
+            <div class="modal-box w-96">
+                <div class="flex">
+                    <h3 class="text-lg flex-1 font-bold">Server Roles</h3>
+                    <form class="dialog flex-0">
+                        <button type="button" onclick="roles_modal.close()" class="btn btn-sm btn-circle btn-ghost">{"✕"}</button>
+                    </form>
+                </div>
+                {
+                    move || error_status().map(|msg| view! {
+                        <div role="alert" class="alert alert-error">
+                            <svg xmlns="http://www.w3.org/2000/svg" class="h-6 w-6 shrink-0 stroke-current" fill="none" viewBox="0 0 24 24">
+                                <path stroke-linecap="round" stroke-linejoin="round" stroke-width="2" d="M10 14l2-2m0 0l2-2m-2 2l-2-2m2 2l2 2m7-2a9 9 0 11-18 0 9 9 0 0118 0z" />
+                            </svg>
+                            <span>{ msg }</span>
+                        </div>
+                    })
+                }
+                <Await future=get_roles(link.clone()) let:res>
+                    {
+                        if let Ok(v) = res.as_ref() {
+                            set_roles(v.clone());
+                        }
+                    }
+                </Await>
+                <ul class="list">
+                    {
+                        move || roles.get().into_iter().map(|member| view! {
+                            <li class="list-row">
+                                <div class="font-bold">{ member.user_id.clone() }</div>
+                                <div>{ member.role.label() }</div>
+                            </li>
+                        }).collect_view()
+                    }
+                </ul>
+                <ActionForm action=set_role>
+                    // hidden vars for action form
+                    <input type="text" class="hidden invisible" name="link" value={link}/>
+                    <fieldset class="fieldset w-full bg-base-200 border border-base-300 p-4 rounded-box">
+                        <legend class="fieldset-legend">Set Role</legend>
+
+                        <label class="fieldset-label">User</label>
+                        <input type="text" class="input" placeholder="User id" name="member_id" required />
+
+                        <label class="fieldset-label">Role</label>
+                        <select class="select" name="role">
+                            {
+                                Role::ALL.into_iter().map(|role| view! {
+                                    <option value={role.as_str()}>{ role.label() }</option>
+                                }).collect_view()
+                            }
+                        </select>
+
+                        <button type="submit" class="btn btn-neutral mt-4">Save</button>
+                    </fieldset>
+                </ActionForm>
+            </div>
+        </dialog>
+    }
+}
+
+/**
+ * Lists the server's admins and moderators. Only admins can
+ */
+#[server]
+#[cfg_attr(
+    feature = "ssr",
+    tracing::instrument(
+        name = "get_roles",
+        skip_all,
+        fields(
+            server_id = tracing::field::Empty,
+            user_id = tracing::field::Empty
+        ),
+        err
+    )
+)]
+pub async fn get_roles(link: String) -> Result<Vec<MemberRole>, ServerFnError<AppError>> {
+    use crate::{config::pool, dao::sqlite_util::SqliteClient, obf_util::verify_caller};
+
+    let caller = verify_caller(&link)?;
+    let (server_id, user_id) = (caller.get_server_id(), caller.get_user_id());
+
+    let pool = pool()?;
+    let client = SqliteClient::from_pool(pool).await;
+
+    let role = Role::from(client.get_role(&server_id, &user_id).await?);
+    if !role.can_manage_roles() {
+        return Err(AppError::forbidden("Only admins can manage roles").into());
+    }
+
+    match client.get_roles(&server_id).await {
+        Ok(records) => Ok(records.iter().map(MemberRole::from).collect()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/**
+ * Gives a user a role on the server, members are removed from the roles table.
+ * Admins can't change their own role, so a server always keeps an admin
+ */
+#[server]
+#[cfg_attr(
+    feature = "ssr",
+    tracing::instrument(
+        name = "set_role",
+        skip_all,
+        fields(
+            server_id = tracing::field::Empty,
+            user_id = tracing::field::Empty
+        ),
+        err
+    )
+)]
+pub async fn set_role(
+    link: String,
+    member_id: String,
+    role: String,
+) -> Result<MemberRole, ServerFnError<AppError>> {
+    use crate::{config::pool, dao::sqlite_util::SqliteClient, obf_util::verify_caller};
+
+    let caller = verify_caller(&link)?;
+    let (server_id, user_id) = (caller.get_server_id(), caller.get_user_id());
+
+    let pool = pool()?;
+    let client = SqliteClient::from_pool(pool).await;
+
+    let user_role = Role::from(client.get_role(&server_id, &user_id).await?);
+    if !user_role.can_manage_roles() {
+        return Err(AppError::forbidden("Only admins can manage roles").into());
+    }
+    if member_id == user_id {
+        return Err(AppError::forbidden("Admins can't change their own role").into());
+    }
+
+    let role = role
+        .parse::<Role>()
+        .map_err(|_| AppError::validation(format!("Unknown role: {role}")))?;
+    let res = match role {
+        Role::Member => client.delete_role(&server_id, &member_id).await,
+        _ => client
+            .upsert_role(&server_id, &member_id, role.as_str())
+            .await
+            .map(|_| ()),
+    };
+
+    match res {
+        Ok(()) => Ok(MemberRole {
+            user_id: member_id,
+            role,
+        }),
+        Err(e) => Err(e.into()),
+    }
+}
@@ -0,0 +1,306 @@
+use chrono::DateTime;
+use chrono_tz::Tz;
+use leptos::{html::Dialog, logging::log, prelude::*};
+use reactive_stores::Store;
+
+use crate::{
+    app::{GlobalState, GlobalStateStoreFields},
+    component::{
+        model::GamingSession,
+        validation::{
+            EventForm, MAX_DESCRIPTION_CHARS, MAX_GAME_CHARS, MAX_PARTICIPANTS, MAX_TITLE_CHARS,
+        },
+    },
+    error::{error_message, AppError},
+};
+
+/**
+ * Modal form to edit an event. Moderators can also lock it from here
+ */
+#[component]
+pub fn UpdateEventModal(
+    session_id: i64,
+    title: String,
+    start_time: DateTime<Tz>,
+    end_time: DateTime<Tz>,
+    game: Option<String>,
+    max_participants: Option<usize>,
+    description: Option<String>,
+    locked: bool,
+    // the owner's profile timezone, times may be entered in it instead
+    owner_tz: Option<Tz>,
+) -> impl IntoView {
+    let state = expect_context::<Store<GlobalState>>();
+    let link = state.url_params().get_untracked().encode();
+    let calendar_events = state.calendar_events();
+    let can_moderate = state.role().get_untracked().can_moderate();
+    let modal_name = format!("update_modal_{session_id}");
+
+    let e = NodeRef::<Dialog>::new();
+    let (error_status, set_error_status) = signal::<Option<String>>(None);
+
+    // replace the edited session in place
+    let on_update = move |session: GamingSession| {
+        calendar_events.update(|v| {
+            if let Some(s) = v.iter_mut().find(|s| s.session_id == session.session_id) {
+                *s = session;
+            }
+        });
+    };
+
+    // handle ActionForm
+    let update_event = ServerAction::<UpdateEvent>::new();
+    let server_res = update_event.value();
+    Effect::new(move || match server_res() {
+        Some(Ok(session)) => {
+            on_update(session);
+            e.get().unwrap().close();
+            set_error_status(None);
+        }
+        Some(Err(e)) => {
+            log!("{:?}", e);
+            set_error_status(Some(error_message(&e)));
+        }
+        None => {}
+    });
+
+    // handle locking
+    let set_lock = ServerAction::<SetSessionLock>::new();
+    let lock_res = set_lock.value();
+    Effect::new(move || match lock_res() {
+        Some(Ok(locked)) => calendar_events.update(|v| {
+            if let Some(s) = v.iter_mut().find(|s| s.session_id == session_id) {
+                s.locked = locked;
+            }
+        }),
+        Some(Err(e)) => {
+            log!("{:?}", e);
+            set_error_status(Some(error_message(&e)));
+        }
+        None => {}
+    });
+
+    let time_format = "%H:%M";
+    // the times shown follow the timezone they are entered in
+    let user_tz = start_time.timezone();
+    let (tz, set_tz) = signal(user_tz);
+    view! {
+        <button type="button" onclick={format!("{modal_name}.showModal()")} class="btn btn-sm btn-circle btn-ghost" aria-label="Edit event">
+            <svg xmlns="http://www.w3.org/2000/svg" fill="none" viewBox="0 0 24 24" stroke-width="1.5" stroke="currentColor" class="size-4">
+                <path stroke-linecap="round" stroke-linejoin="round" d="m16.862 4.487 1.687-1.688a1.875 1.875 0 1 1 2.652 2.652L6.832 19.82a4.5 4.5 0 0 1-1.897 1.13l-2.685.8.8-2.685a4.5 4.5 0 0 1 1.13-1.897L16.863 4.487Z" />
+            </svg>
+        </button>
+        <dialog node_ref=e id={modal_name.clone()} class="modal">
+            <div class="modal-box w-80">
+                <div class="flex">
+                    <h3 class="text-lg flex-1 font-bold">Edit Event</h3>
+                    <form class="dialog flex-0">
+                        <button type="button" onclick={format!("{modal_name}.close()")} class="btn btn-sm btn-circle btn-ghost">{"✕"}</button>
+                    </form>
+                </div>
+                {
+                    move || error_status().map(|msg| view! {
+                        <div role="alert" class="alert alert-error">
+                            <svg xmlns="http://www.w3.org/2000/svg" class="h-6 w-6 shrink-0 stroke-current" fill="none" viewBox="0 0 24 24">
+                                <path stroke-linecap="round" stroke-linejoin="round" stroke-width="2" d="M10 14l2-2m0 0l2-2m-2 2l-2-2m2 2l2 2m7-2a9 9 0 11-18 0 9 9 0 0118 0z" />
+                            </svg>
+                            <span>{ msg }</span>
+                        </div>
+                    })
+                }
+                <ActionForm action=update_event>
+                    // hidden vars for action form
+                    <input type="text" class="hidden invisible" name="session_id" value={session_id}/>
+                    <input type="text" class="hidden invisible" name="link" value={link}/>
+                    <fieldset class="fieldset w-full bg-base-200 border border-base-300 p-4 rounded-box">
+                        <legend class="fieldset-legend">Event</legend>
+
+                        <label class="fieldset-label">Title</label>
+                        <input type="text" class="input" name="title" maxlength=MAX_TITLE_CHARS value={title} required />
+
+                        <label class="fieldset-label">Start Time</label>
+                        <input type="time" class="input" name="start" prop:value=move || start_time.with_timezone(&tz()).format(time_format).to_string() required />
+
+                        <label class="fieldset-label">End Time</label>
+                        <input type="time" class="input" name="end" prop:value=move || end_time.with_timezone(&tz()).format(time_format).to_string() required />
+
+                        // the owner may be somewhere else, e.g. when a moderator fixes their event
+                        <label class="fieldset-label">Times are in</label>
+                        <label class="fieldset-label">
+                            <input type="radio" class="radio radio-xs" name="timezone" value={user_tz.name()} checked on:change=move |_| set_tz(user_tz) />
+                            { format!("Your time ({user_tz})") }
+                        </label>
+                        <label class="fieldset-label">
+                            <input
+                                type="radio"
+                                class="radio radio-xs"
+                                name="timezone"
+                                value={owner_tz.map(|t| t.name()).unwrap_or_default()}
+                                disabled={owner_tz.is_none()}
+                                on:change=move |_| if let Some(t) = owner_tz { set_tz(t) }
+                            />
+                            { format!("Owner's time ({})", owner_tz.map(|t| t.to_string()).unwrap_or("not set".to_string())) }
+                        </label>
+
+                        <label class="fieldset-label">Game (optional)</label>
+                        <input type="text" class="input" name="game" maxlength=MAX_GAME_CHARS value={game.unwrap_or_default()} />
+
+                        <label class="fieldset-label">Max Players (optional)</label>
+                        <input
+                            type="number"
+                            class="input"
+                            name="max_participants"
+                            min="1"
+                            max=MAX_PARTICIPANTS
+                            value={max_participants.map(|m| m.to_string()).unwrap_or_default()}
+                        />
+
+                        <label class="fieldset-label">Description (optional, Markdown)</label>
+                        <textarea class="textarea" name="description" rows="3" maxlength=MAX_DESCRIPTION_CHARS>
+                            { description.unwrap_or_default() }
+                        </textarea>
+
+                        <button type="submit" class="btn btn-neutral mt-4">Save</button>
+                    </fieldset>
+                </ActionForm>
+                {
+                    can_moderate.then(|| view! {
+                        <button
+                            type="button"
+                            class="btn btn-warning w-full mt-2"
+                            on:click=move |_| {
+                                set_lock.dispatch(SetSessionLock {
+                                    session_id,
+                                    link: state.url_params().get_untracked().encode(),
+                                    locked: !locked,
+                                });
+                            }
+                        >
+                            { if locked { "Unlock session" } else { "Lock session" } }
+                        </button>
+                    })
+                }
+            </div>
+        </dialog>
+    }
+}
+
+/**
+ * Edits a session's details. Times are entered on the session's own day, in `timezone`
+ */
+#[server]
+#[cfg_attr(
+    feature = "ssr",
+    tracing::instrument(
+        name = "update_event",
+        skip_all,
+        fields(session_id = %session_id, user_id = tracing::field::Empty),
+        err
+    )
+)]
+pub async fn update_event(
+    session_id: i64,
+    link: String,
+    timezone: String,
+    #[server(flatten)] event: EventForm,
+) -> Result<GamingSession, ServerFnError<AppError>> {
+    use crate::component::{
+        calendar_events::load_session,
+        model::{Role, ServerSettings},
+        time_util::create_baseline,
+        validation::{earliest_start, validate_timezone},
+    };
+    use crate::{
+        component::calendar_events::caller_session,
+        config::pool,
+        dao::sqlite_util::{SessionDetails, SqliteClient},
+        obf_util::verify_caller,
+    };
+    use chrono::{DateTime, Utc};
+
+    let caller = verify_caller(&link)?;
+    let user_id = caller.get_user_id();
+
+    let pool = pool()?;
+    let client = SqliteClient::from_pool(pool).await;
+
+    let session = caller_session(&client, &caller, session_id).await?;
+    let role = Role::from(client.get_role(&session.server_id, &user_id).await?);
+    if !role.can_manage_session(&user_id, &session.owner, session.locked) {
+        return Err(
+            AppError::forbidden("Only the owner or a moderator can edit this session").into(),
+        );
+    }
+
+    // keep the session on the day it was scheduled for
+    let settings = client
+        .get_server(&session.server_id)
+        .await?
+        .map(|r| ServerSettings::from(&r))
+        .unwrap_or_else(crate::config::default_settings);
+    let offset = settings.day_start_hour;
+    let tz = validate_timezone(&timezone).map_err(AppError::validation)?;
+    let current_start = DateTime::parse_from_rfc3339(&session.start_time)
+        .map_err(AppError::storage)?
+        .with_timezone(&tz);
+    let baseline = create_baseline(current_start, offset).map_err(AppError::storage)?;
+
+    // sessions that already started may keep their start time
+    let event = event
+        .validate(
+            baseline,
+            offset,
+            current_start.to_utc().min(earliest_start(Utc::now())),
+        )
+        .map_err(AppError::from)?;
+
+    let record = client
+        .update_session(session_id, &SessionDetails::from(&event))
+        .await?
+        .ok_or(AppError::not_found("session"))?;
+
+    Ok(load_session(&client, &record).await?)
+}
+
+/**
+ * Locks or unlocks a session. Only moderators can
+ */
+#[server]
+#[cfg_attr(
+    feature = "ssr",
+    tracing::instrument(
+        name = "set_session_lock",
+        skip_all,
+        fields(session_id = %session_id, user_id = tracing::field::Empty),
+        err
+    )
+)]
+pub async fn set_session_lock(
+    session_id: i64,
+    link: String,
+    locked: bool,
+) -> Result<bool, ServerFnError<AppError>> {
+    use crate::{
+        component::{calendar_events::caller_session, model::Role},
+        config::pool,
+        dao::sqlite_util::SqliteClient,
+        obf_util::verify_caller,
+    };
+
+    let caller = verify_caller(&link)?;
+    let user_id = caller.get_user_id();
+
+    let pool = pool()?;
+    let client = SqliteClient::from_pool(pool).await;
+
+    let session = caller_session(&client, &caller, session_id).await?;
+    let role = Role::from(client.get_role(&session.server_id, &user_id).await?);
+    if !role.can_moderate() {
+        return Err(AppError::forbidden("Only moderators can lock sessions").into());
+    }
+
+    match client.set_session_locked(session_id, locked).await {
+        Ok(()) => Ok(locked),
+        Err(e) => Err(e.into()),
+    }
+}
@@ -1,14 +1,25 @@
+use std::{fmt, str::FromStr};
+
 use chrono::Utc;
+use chrono_tz::Tz;
 use reactive_stores::Store;
 use serde::{Deserialize, Serialize};
 
 #[cfg(feature = "ssr")]
-use crate::dao::sqlite_util::UserRecord;
+use crate::dao::sqlite_util::{
+    CommentRecord, RoleRecord, ServerRecord, UserRecord, WaitlistRecord,
+};
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct User {
     pub name: String,
     pub picture: String, // TODO: put picture here,
+    pub timezone: Option<String>,
+    pub status: RsvpStatus,
+    /// when a late user expects to arrive
+    pub eta: Option<chrono::DateTime<Utc>>,
+    /// when the user last RSVP'd
+    pub joined_at: Option<chrono::DateTime<Utc>>,
 }
 
 impl User {
@@ -19,6 +30,25 @@ impl User {
     pub fn get_picture(&self) -> String {
         self.picture.clone()
     }
+
+    /// Parsed profile timezone, if the user has set a valid one
+    pub fn get_tz(&self) -> Option<Tz> {
+        self.timezone.as_ref().and_then(|tz| tz.parse().ok())
+    }
+}
+
+#[cfg(feature = "ssr")]
+impl From<&WaitlistRecord> for User {
+    fn from(record: &WaitlistRecord) -> Self {
+        Self {
+            name: record.user_id.clone(),
+            picture: record.user_photo.clone(),
+            timezone: None,
+            status: RsvpStatus::Going,
+            eta: None,
+            joined_at: None,
+        }
+    }
 }
 
 #[cfg(feature = "ssr")]
@@ -27,6 +57,18 @@ impl From<&UserRecord> for User {
         Self {
             name: record.user_id.clone(),
             picture: record.user_photo.clone(),
+            timezone: record.timezone.clone(),
+            status: record.status.parse().unwrap_or_default(),
+            eta: record
+                .eta
+                .as_ref()
+                .and_then(|eta| chrono::DateTime::parse_from_rfc3339(eta).ok())
+                .map(|eta| eta.with_timezone(&Utc)),
+            joined_at: record
+                .joined_at
+                .as_ref()
+                .and_then(|t| chrono::DateTime::parse_from_rfc3339(t).ok())
+                .map(|t| t.with_timezone(&Utc)),
         }
     }
 }
@@ -41,6 +83,374 @@ pub struct GamingSession {
     pub owner: User,
     pub participants: Vec<User>,
     pub game: Option<String>,
+    pub max_participants: Option<usize>,
+    /// users waiting for a spot, first in line first
+    pub waitlist: Vec<User>,
+    /// locked sessions can only be changed by moderators
+    pub locked: bool,
+    /// markdown as the owner wrote it
+    pub description: Option<String>,
+    /// the description rendered and sanitized by the server
+    pub description_html: Option<String>,
+    pub comment_count: usize,
+}
+
+impl GamingSession {
+    /// Makes a participant the owner, returns false if they aren't participating
+    pub fn set_owner(&mut self, user_id: &str) -> bool {
+        match self.participants.iter().find(|p| p.get_name() == user_id) {
+            Some(user) => {
+                self.owner = user.clone();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// participants taking up a spot, i.e. everyone who hasn't said they're not going
+    pub fn count_attending(&self) -> usize {
+        self.participants
+            .iter()
+            .filter(|p| p.status.is_attending())
+            .count()
+    }
+
+    pub fn count_status(&self, status: RsvpStatus) -> usize {
+        self.participants
+            .iter()
+            .filter(|p| p.status == status)
+            .count()
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.max_participants
+            .is_some_and(|max| self.count_attending() >= max)
+    }
+
+    /// whether the user takes part, not counting those who aren't going
+    pub fn is_attended_by(&self, user_id: &str) -> bool {
+        self.participants
+            .iter()
+            .any(|p| p.name == user_id && p.status.is_attending())
+    }
+
+    pub fn overlaps(&self, other: &GamingSession) -> bool {
+        self.start_time < other.end_time && other.start_time < self.end_time
+    }
+}
+
+/// Result of asking to join a session that may be full
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub enum JoinStatus {
+    Joined,
+    Waitlisted,
+}
+
+/// Whether a participant is coming, stored as the users.status column
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub enum RsvpStatus {
+    #[default]
+    Going,
+    Maybe,
+    Late,
+    NotGoing,
+}
+
+impl RsvpStatus {
+    pub const ALL: [RsvpStatus; 4] = [
+        RsvpStatus::Going,
+        RsvpStatus::Late,
+        RsvpStatus::Maybe,
+        RsvpStatus::NotGoing,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RsvpStatus::Going => "going",
+            RsvpStatus::Maybe => "maybe",
+            RsvpStatus::Late => "late",
+            RsvpStatus::NotGoing => "not_going",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            RsvpStatus::Going => "Going",
+            RsvpStatus::Maybe => "Maybe",
+            RsvpStatus::Late => "Late",
+            RsvpStatus::NotGoing => "Not going",
+        }
+    }
+
+    /// users who are not going give their spot back
+    pub fn is_attending(&self) -> bool {
+        *self != RsvpStatus::NotGoing
+    }
+}
+
+impl fmt::Display for RsvpStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for RsvpStatus {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        RsvpStatus::ALL
+            .into_iter()
+            .find(|status| status.as_str() == s)
+            .ok_or(format!("unknown rsvp status: {s}"))
+    }
+}
+
+/// Per-server role, stored in the roles table. Users without a row are members
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub enum Role {
+    Admin,
+    Moderator,
+    #[default]
+    Member,
+}
+
+impl Role {
+    pub const ALL: [Role; 3] = [Role::Admin, Role::Moderator, Role::Member];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::Admin => "admin",
+            Role::Moderator => "moderator",
+            Role::Member => "member",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Role::Admin => "Admin",
+            Role::Moderator => "Moderator",
+            Role::Member => "Member",
+        }
+    }
+
+    /// moderators and admins can edit, delete and lock any session on their server
+    pub fn can_moderate(&self) -> bool {
+        matches!(self, Role::Admin | Role::Moderator)
+    }
+
+    /// only admins can hand out roles
+    pub fn can_manage_roles(&self) -> bool {
+        *self == Role::Admin
+    }
+
+    /**
+     * Owners can change their own sessions until a moderator locks them,
+     * moderators can change any session
+     */
+    pub fn can_manage_session(&self, user_id: &str, owner: &str, locked: bool) -> bool {
+        self.can_moderate() || (user_id == owner && !locked)
+    }
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Role {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Role::ALL
+            .into_iter()
+            .find(|role| role.as_str() == s)
+            .ok_or(format!("unknown role: {s}"))
+    }
+}
+
+#[cfg(feature = "ssr")]
+impl From<Option<RoleRecord>> for Role {
+    fn from(record: Option<RoleRecord>) -> Self {
+        record.and_then(|r| r.role.parse().ok()).unwrap_or_default()
+    }
+}
+
+/// A user's role on a server, as shown on the roles settings page
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct MemberRole {
+    pub user_id: String,
+    pub role: Role,
+}
+
+#[cfg(feature = "ssr")]
+impl From<&RoleRecord> for MemberRole {
+    fn from(record: &RoleRecord) -> Self {
+        Self {
+            user_id: record.user_id.clone(),
+            role: record.role.parse().unwrap_or_default(),
+        }
+    }
+}
+
+/// Result of leaving a session, with whoever took the freed spot and the new host if the owner left
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct LeaveUpdate {
+    pub promoted: Option<String>,
+    pub new_owner: Option<String>,
+}
+
+/// Result of changing an RSVP, with the waitlisted user that took a freed spot
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct RsvpUpdate {
+    pub joined: JoinStatus,
+    pub status: RsvpStatus,
+    pub eta: Option<chrono::DateTime<Utc>>,
+    pub promoted: Option<String>,
+}
+
+/// Session the user already attends at the same time as the one they asked for
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Clash {
+    pub session_id: i64,
+    pub title: String,
+    pub start_time: chrono::DateTime<Utc>,
+    pub end_time: chrono::DateTime<Utc>,
+}
+
+/// Message in a session's discussion thread
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct Comment {
+    pub comment_id: i64,
+    pub user_id: String,
+    pub body: String,
+    pub created_at: chrono::DateTime<Utc>,
+}
+
+#[cfg(feature = "ssr")]
+impl TryFrom<&CommentRecord> for Comment {
+    type Error = crate::error::AppError;
+
+    fn try_from(record: &CommentRecord) -> Result<Self, Self::Error> {
+        Ok(Self {
+            comment_id: record
+                .comment_id
+                .ok_or(crate::error::AppError::storage("comment has no id"))?,
+            user_id: record.user_id.clone(),
+            body: record.body.clone(),
+            created_at: chrono::DateTime::parse_from_rfc3339(&record.created_at)
+                .map_err(crate::error::AppError::storage)?
+                .to_utc(),
+        })
+    }
+}
+
+/// Session on the user's agenda, with the link to its server's calendar
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AgendaEntry {
+    pub session: GamingSession,
+    pub calendar_url: String,
+}
+
+/// Number of users available in one hour long slot of a poll
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct SlotCount {
+    pub slot_start: chrono::DateTime<Utc>,
+    pub count: usize,
+}
+
+/// Availability poll over a range of days, aggregated into a heatmap of hour slots
+#[derive(Clone, Serialize, Debug, Deserialize)]
+pub struct AvailabilityPoll {
+    pub poll_id: i64,
+    pub server_id: String,
+    pub title: String,
+    pub owner: String,
+    pub start_time: chrono::DateTime<Utc>,
+    pub end_time: chrono::DateTime<Utc>,
+    pub heatmap: Vec<SlotCount>,
+    pub respondents: usize,
+    /// slots the current user marked as available
+    pub user_slots: Vec<chrono::DateTime<Utc>>,
+}
+
+impl AvailabilityPoll {
+    pub fn get_count(&self, slot_start: chrono::DateTime<Utc>) -> usize {
+        self.heatmap
+            .iter()
+            .find(|s| s.slot_start == slot_start)
+            .map(|s| s.count)
+            .unwrap_or(0)
+    }
+
+    /// Mark a slot for the current user, keeping the heatmap in sync
+    pub fn set_user_slot(&mut self, slot_start: chrono::DateTime<Utc>, available: bool) {
+        let is_marked = self.user_slots.contains(&slot_start);
+        if available == is_marked {
+            return;
+        }
+
+        if available {
+            self.user_slots.push(slot_start);
+            match self.heatmap.iter_mut().find(|s| s.slot_start == slot_start) {
+                Some(s) => s.count += 1,
+                None => self.heatmap.push(SlotCount {
+                    slot_start,
+                    count: 1,
+                }),
+            }
+        } else {
+            self.user_slots.retain(|s| *s != slot_start);
+            if let Some(s) = self.heatmap.iter_mut().find(|s| s.slot_start == slot_start) {
+                s.count = s.count.saturating_sub(1);
+            }
+        }
+    }
+}
+
+/// Per-server display and scheduling settings
+#[derive(Clone, Debug, Store, Serialize, Deserialize, PartialEq)]
+pub struct ServerSettings {
+    /// hour the calendar day starts at, e.g. 6 shows 06:00 to 05:59
+    pub day_start_hour: usize,
+    /// IANA timezone name, e.g. "Europe/Berlin"
+    pub timezone: String,
+    pub use_24h: bool,
+}
+
+impl Default for ServerSettings {
+    fn default() -> Self {
+        Self {
+            day_start_hour: 6,
+            timezone: "UTC".to_string(),
+            use_24h: true,
+        }
+    }
+}
+
+impl ServerSettings {
+    /// Parsed timezone. Falls back to UTC if the stored name is not a valid IANA zone
+    pub fn get_tz(&self) -> Tz {
+        self.timezone.parse().unwrap_or(Tz::UTC)
+    }
+}
+
+#[cfg(feature = "ssr")]
+impl From<&ServerRecord> for ServerSettings {
+    fn from(record: &ServerRecord) -> Self {
+        // writes are validated, but a bad row must not break every calendar of the server
+        let day_start_hour =
+            crate::component::validation::validate_day_start_hour(record.day_start_hour)
+                .unwrap_or_else(|e| {
+                    tracing::warn!(server_id = %record.server_id, "{}", e);
+                    ServerSettings::default().day_start_hour
+                });
+        Self {
+            day_start_hour,
+            timezone: record.timezone.clone(),
+            use_24h: record.use_24h,
+        }
+    }
 }
 
 pub struct Game {
@@ -1,4 +1,10 @@
 use leptos::prelude::*;
+use reactive_stores::Store;
+
+use crate::{
+    app::{GlobalState, GlobalStateStoreFields},
+    component::{modal::roles_modal::RolesModal, timezone_picker::TimezonePicker},
+};
 
 #[component]
 pub fn NavBar() -> impl IntoView {
@@ -19,6 +25,16 @@ pub fn NavBar() -> impl IntoView {
                 <a class="btn btn-ghost text-xl">Game Tonite!</a>
             </div>
             <div class="flex-none">
+                // profile settings need a user, which only the calendar page has
+                {
+                    use_context::<Store<GlobalState>>().map(|_| view! { <TimezonePicker /> })
+                }
+                // only admins hand out roles
+                {
+                    use_context::<Store<GlobalState>>()
+                        .filter(|state| state.role().get_untracked().can_manage_roles())
+                        .map(|_| view! { <RolesModal /> }.into_any())
+                }
                 <div class="dropdown dropdown-end">
                     <div tabindex="0" role="button" class="btn m-1">
                         Theme
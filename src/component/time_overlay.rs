@@ -1,17 +1,21 @@
-use chrono::{DateTime, FixedOffset};
+use chrono::DateTime;
+use chrono_tz::Tz;
 use leptos::prelude::*;
 
+use crate::component::time_util::time_format;
+
 /**
  * Time overlay component. Takes in a time and a bottom padding and creates an overlay displaying that time.
  */
 #[component]
 pub fn TimeOverlay(
     bottom_pad_pct: ReadSignal<f64>,
-    time: ReadSignal<DateTime<FixedOffset>>,
+    time: ReadSignal<DateTime<Tz>>,
+    use_24h: bool,
 ) -> impl IntoView {
     view! {
         <div class="z-2 absolute w-full flex-shrink-0" style={move || format!("bottom: {}%;", bottom_pad_pct()) }>
-            <p class="text-sm pr-2 text-right z-2 text-accent">{ move || format!("{}", time().format("%H:%M")) }</p>
+            <p class="text-sm pr-2 text-right z-2 text-accent">{ move || format!("{}", time().format(time_format(use_24h))) }</p>
             <div class="z-2 divider divider-accent h-px m-0"></div>
         </div>
     }
@@ -1,47 +1,169 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
 use anyhow::Result;
-use chrono::{DateTime, Duration, FixedOffset, TimeZone, Timelike, Utc};
+use chrono::{
+    DateTime, Duration, LocalResult, NaiveDate, NaiveDateTime, NaiveTime, Offset, TimeZone, Utc,
+};
+use chrono_tz::Tz;
 
-use super::model::GamingSession;
+use super::model::{GamingSession, ServerSettings, SlotCount};
 
-// get the local time with timezone from the client
-pub fn get_local_time() -> DateTime<FixedOffset> {
-    let mins_offset = js_sys::Date::new_0().get_timezone_offset();
-    let offset = FixedOffset::west_opt((mins_offset * 60.) as i32).unwrap();
+// get the current time in the given timezone
+pub fn get_local_time(tz: Tz) -> DateTime<Tz> {
+    Utc::now().with_timezone(&tz)
+}
+
+// IANA timezone of the browser, e.g. "America/New_York". None outside the browser
+pub fn get_browser_timezone() -> Option<Tz> {
+    #[cfg(target_arch = "wasm32")]
+    {
+        let options =
+            js_sys::Intl::DateTimeFormat::new(&js_sys::Array::new(), &js_sys::Object::new())
+                .resolved_options();
+        js_sys::Reflect::get(&options, &wasm_bindgen::JsValue::from_str("timeZone"))
+            .ok()?
+            .as_string()?
+            .parse()
+            .ok()
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    None
+}
 
-    offset.from_utc_datetime(&Utc::now().naive_utc())
+// timezone the calendar is shown in: the browser's, falling back to the server default
+pub fn get_user_timezone(settings: &ServerSettings) -> Tz {
+    get_browser_timezone().unwrap_or(settings.get_tz())
 }
 
-// Given a date and a number of hours offset for the display, return bottom padding
-pub fn calculate_timebar_bottom(t: DateTime<FixedOffset>, offset: usize) -> f64 {
-    let nsfm = t.num_seconds_from_midnight() as f64;
-    let offset_secs = offset as f64 * 3600.;
+// chrono format string for a time of day, following the 12h/24h preference
+pub fn time_format(use_24h: bool) -> &'static str {
+    if use_24h {
+        "%H:%M"
+    } else {
+        "%-I:%M %p"
+    }
+}
 
-    if nsfm < offset_secs {
-        100. * (offset_secs - nsfm) / 86400.
+// label for a whole hour on the hour grid, e.g. "18:00" or "6 PM"
+pub fn format_hour(hour: usize, use_24h: bool) -> String {
+    let t = NaiveTime::from_hms_opt(hour as u32 % 24, 0, 0).unwrap();
+    if use_24h {
+        t.format("%H:%M").to_string()
     } else {
-        100. * (1. - (nsfm - offset_secs) / 86400.)
+        t.format("%-I %p").to_string()
     }
 }
 
 /**
- * Get the percent throughout the timeline, with offset.
+ * Resolve a wall clock time in a timezone.
+ * Ambiguous times (DST fall back) resolve to the earlier instant.
+ * Times skipped by DST spring forward are shifted past the gap, so 02:30 becomes 03:30.
+ */
+pub fn resolve_local(tz: Tz, local: NaiveDateTime) -> DateTime<Tz> {
+    match tz.from_local_datetime(&local) {
+        LocalResult::Single(t) => t,
+        LocalResult::Ambiguous(earliest, _) => earliest,
+        LocalResult::None => {
+            // read the wall clock with the offset in effect before the gap
+            let before = tz
+                .offset_from_utc_datetime(&(local - Duration::days(1)))
+                .fix();
+            tz.from_utc_datetime(&(local - Duration::seconds(before.local_minus_utc() as i64)))
+        }
+    }
+}
+
+// wall clock time `offset` hours into a local date
+pub fn day_start(tz: Tz, date: NaiveDate, offset: usize) -> DateTime<Tz> {
+    resolve_local(
+        tz,
+        date.and_hms_opt(0, 0, 0).unwrap() + Duration::hours(offset as i64),
+    )
+}
+
+/**
+ * The displayed day for a baseline: from `offset` o'clock on the baseline's date until
+ * `offset` o'clock the next day. This is 23 or 25 hours long on DST transition days.
+ */
+pub fn day_window(baseline: DateTime<Tz>, offset: usize) -> (DateTime<Tz>, DateTime<Tz>) {
+    let tz = baseline.timezone();
+    let date = baseline.date_naive();
+    (
+        day_start(tz, date, offset),
+        day_start(tz, date + Duration::days(1), offset),
+    )
+}
+
+// Given a time and a number of hours offset for the display, return bottom padding
+pub fn calculate_timebar_bottom(t: DateTime<Tz>, offset: usize) -> f64 {
+    match create_baseline(t, offset) {
+        Ok(baseline) => 100. * (1. - calculate_time_pct(t, baseline, offset)),
+        Err(_) => 0.,
+    }
+}
+
+/**
+ * Get the percent throughout the displayed day, with offset.
  * Baseline time should be midnight on the day the offset is applied to.
  * Limited between 0 and 1.
  */
-pub fn calculate_time_pct(
-    time: DateTime<FixedOffset>,
-    baseline: DateTime<FixedOffset>,
+pub fn calculate_time_pct(time: DateTime<Tz>, baseline: DateTime<Tz>, offset: usize) -> f64 {
+    let (window_start, window_end) = day_window(baseline, offset);
+    let pct = (time - window_start).num_seconds() as f64
+        / (window_end - window_start).num_seconds() as f64;
+    pct.clamp(0., 1.)
+}
+
+/**
+ * Inverse of `calculate_time_pct`: the time at a percent throughout the displayed day.
+ * Percents outside of 0 and 1 are clamped to the window.
+ */
+pub fn time_at_pct(pct: f64, baseline: DateTime<Tz>, offset: usize) -> DateTime<Tz> {
+    let (window_start, window_end) = day_window(baseline, offset);
+    let seconds = (window_end - window_start).num_seconds() as f64 * pct.clamp(0., 1.);
+    window_start + Duration::seconds(seconds.round() as i64)
+}
+
+/**
+ * Round a time to the nearest multiple of the given number of minutes.
+ * Rounds in absolute time, so it also holds across DST transitions.
+ */
+pub fn snap_to_minutes(time: DateTime<Tz>, minutes: i64) -> DateTime<Tz> {
+    let step = minutes * 60;
+    let snapped = (time.timestamp() + step / 2).div_euclid(step) * step;
+    DateTime::from_timestamp(snapped, 0)
+        .map(|t| t.with_timezone(&time.timezone()))
+        .unwrap_or(time)
+}
+
+/**
+ * New times for a session dragged by a percent of the displayed day. Moving keeps its length,
+ * resizing only changes the end and keeps it at least one step after the start.
+ * Times snap to `minutes` and stay inside the displayed day.
+ */
+pub fn drag_times(
+    start: DateTime<Tz>,
+    end: DateTime<Tz>,
+    delta_pct: f64,
+    resize: bool,
+    baseline: DateTime<Tz>,
     offset: usize,
-) -> f64 {
-    let pct = (time - baseline).num_seconds() as f64 / 86400. - (offset as f64) / 24.;
-    if pct > 1. {
-        1.
-    } else if pct < 0. {
-        0.
+    minutes: i64,
+) -> (DateTime<Tz>, DateTime<Tz>) {
+    let (window_start, window_end) = day_window(baseline, offset);
+    let delta = (window_end - window_start).num_seconds() as f64 * delta_pct;
+    let delta = Duration::seconds(delta.round() as i64);
+    if resize {
+        let new_end = snap_to_minutes(end + delta, minutes)
+            .max(start + Duration::minutes(minutes))
+            .min(window_end);
+        (start, new_end)
     } else {
-        pct
+        let length = end - start;
+        let new_start = snap_to_minutes(start + delta, minutes)
+            .min(window_end - length)
+            .max(window_start);
+        (new_start, new_start + length)
     }
 }
 
@@ -50,92 +172,221 @@ pub fn calculate_time_pct(
  * If time < offset: return beginning of previous day
  * If time >= offset: return beginning of current day
  */
-pub fn create_baseline(
-    time: DateTime<FixedOffset>,
-    offset: usize,
-) -> Result<DateTime<FixedOffset>> {
-    let seconds_from_midnight = time.num_seconds_from_midnight();
-    if seconds_from_midnight < offset as u32 * 3600 {
-        Ok(time - Duration::seconds((86400 + seconds_from_midnight) as i64))
-    } else {
-        Ok(time - Duration::seconds(seconds_from_midnight as i64))
+pub fn create_baseline(time: DateTime<Tz>, offset: usize) -> Result<DateTime<Tz>> {
+    let tz = time.timezone();
+    let mut date = time.date_naive();
+    if time < day_start(tz, date, offset) {
+        date = date
+            .pred_opt()
+            .ok_or_else(|| anyhow::anyhow!("date out of range"))?;
     }
+
+    Ok(resolve_local(tz, date.and_hms_opt(0, 0, 0).unwrap()))
 }
 
 /**
- * Converts a simple html time (XX:XX) to a timestamp by comparing with baseline and offset
+ * Converts a simple html time (XX:XX) to a timestamp by comparing with baseline and offset.
+ * Fails if the time is malformed
  */
 pub fn convert_simple_time(
     time: String,
-    baseline: DateTime<FixedOffset>,
+    baseline: DateTime<Tz>,
     offset: usize,
-) -> DateTime<FixedOffset> {
-    let mut split_time = time.split(":");
-    let hours = split_time.next().unwrap().parse::<i32>().unwrap() as i64;
-    let minutes = split_time.next().unwrap().parse::<i32>().unwrap() as i64;
+) -> Result<DateTime<Tz>> {
+    let (hours, minutes) = time
+        .split_once(':')
+        .and_then(|(h, m)| Some((h.parse::<i64>().ok()?, m.parse::<i64>().ok()?)))
+        .filter(|(h, m)| (0..24).contains(h) && (0..60).contains(m))
+        .ok_or_else(|| anyhow::anyhow!("invalid time: {time:?}"))?;
 
-    if hours as usize >= offset {
-        baseline + Duration::minutes(60 * hours + minutes)
-    } else {
-        baseline + Duration::days(86400 + 60 * hours + minutes)
+    // times before the offset belong to the early hours of the next date
+    let mut date = baseline.date_naive();
+    if (hours as usize) < offset {
+        date += Duration::days(1);
     }
+
+    Ok(resolve_local(
+        baseline.timezone(),
+        date.and_hms_opt(0, 0, 0).unwrap() + Duration::minutes(60 * hours + minutes),
+    ))
+}
+
+/**
+ * Hour long poll slots that fall inside the displayed window.
+ * Slots are aligned to the poll start so that users in every timezone share them.
+ */
+pub fn poll_slots(
+    poll_start: DateTime<Utc>,
+    poll_end: DateTime<Utc>,
+    window_start: DateTime<Utc>,
+    window_end: DateTime<Utc>,
+) -> Vec<DateTime<Utc>> {
+    (0..(poll_end - poll_start).num_hours())
+        .map(|h| poll_start + Duration::hours(h))
+        .filter(|slot| *slot >= window_start && *slot < window_end)
+        .collect()
+}
+
+/**
+ * Best time range from a poll heatmap: the longest run of consecutive slots that the most
+ * users are available for. Ties go to the earliest run. None if nobody answered.
+ */
+pub fn best_slot(heatmap: &[SlotCount]) -> Option<(DateTime<Utc>, DateTime<Utc>)> {
+    let max = heatmap.iter().map(|s| s.count).max().filter(|m| *m > 0)?;
+    let mut best_slots: Vec<DateTime<Utc>> = heatmap
+        .iter()
+        .filter(|s| s.count == max)
+        .map(|s| s.slot_start)
+        .collect();
+    best_slots.sort();
+
+    // (start, end) of the best run so far and of the current run
+    let mut best: Option<(DateTime<Utc>, DateTime<Utc>)> = None;
+    let mut current: Option<(DateTime<Utc>, DateTime<Utc>)> = None;
+    for slot in best_slots {
+        current = match current {
+            Some((start, end)) if end == slot => Some((start, slot + Duration::hours(1))),
+            _ => Some((slot, slot + Duration::hours(1))),
+        };
+        let (start, end) = current.unwrap();
+        if best.is_none_or(|(best_start, best_end)| end - start > best_end - best_start) {
+            best = Some((start, end));
+        }
+    }
+
+    best
+}
+
+/**
+ * Where a session's card goes. Sessions that overlap, directly or through others, form a
+ * cluster that shares the width between `columns` columns. The card starts in `column` and
+ * widens into the `span - 1` columns to its right that are free while it runs
+ */
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct EventLayout {
+    pub column: i32,
+    pub columns: i32,
+    pub span: i32,
 }
 
 /**
  * Stack elements in horizontal space so they don't overlap
- * Returns a HashMap of session_id to positioning. Positioning starts at 0.
+ * Returns a HashMap of session_id to positioning. Columns start at 0.
  */
-pub fn get_events_stacking(events: &Vec<GamingSession>) -> HashMap<i64, i32> {
+pub fn get_events_stacking(events: &Vec<GamingSession>) -> HashMap<i64, EventLayout> {
     // sort by starting time
     let mut sorted_events = events.clone();
     sorted_events.sort_by_key(|e| e.start_time);
 
-    // map from position to ending time -- to check if a new event fits in position
-    let mut latest_times: HashMap<i32, DateTime<Utc>> = HashMap::new();
-
-    /*
-     * For each event:
-     *  - Check if it fits in position 0
-     *  - If not, increment position and try again
-     */
-    let mut return_map: HashMap<i64, i32> = HashMap::new();
-    for event in sorted_events {
-        let mut position_index = 0;
-        let mut is_complete: bool = false;
-        while !is_complete {
-            let _ = match latest_times.get(&position_index) {
-                // no events exist in position, good to insert
-                None => {
-                    latest_times.insert(position_index.clone(), event.end_time);
-                    return_map.insert(event.session_id, position_index.clone());
-                    is_complete = true;
-                }
-                // check if event fits (start time is after latest existing end time)
-                Some(t) => {
-                    if t <= &event.start_time {
-                        latest_times.insert(position_index.clone(), event.end_time);
-                        return_map.insert(event.session_id, position_index.clone());
-                        is_complete = true;
-                    } else {
-                        is_complete = false;
-                    }
-                }
-            };
-            // try next position
-            position_index += 1;
+    let mut return_map: HashMap<i64, EventLayout> = HashMap::new();
+    let mut cluster: Vec<(&GamingSession, i32)> = vec![];
+    let mut cluster_end: Option<DateTime<Utc>> = None;
+    for event in &sorted_events {
+        // an event starting after everything so far has ended begins a new cluster
+        if cluster_end.is_some_and(|t| t <= event.start_time) {
+            layout_cluster(&cluster, &mut return_map);
+            cluster.clear();
+        }
+        cluster_end = Some(match cluster_end {
+            Some(t) if !cluster.is_empty() => t.max(event.end_time),
+            _ => event.end_time,
+        });
+
+        // first column whose events have all ended
+        let mut column = 0;
+        while cluster
+            .iter()
+            .any(|(e, c)| *c == column && e.end_time > event.start_time)
+        {
+            column += 1;
         }
+        cluster.push((event, column));
     }
+    layout_cluster(&cluster, &mut return_map);
 
     return_map
 }
 
+// share the cluster's width and widen events into free columns to their right
+fn layout_cluster(cluster: &[(&GamingSession, i32)], layouts: &mut HashMap<i64, EventLayout>) {
+    let columns = cluster.iter().map(|(_, c)| c + 1).max().unwrap_or(0);
+    let overlaps = |a: &GamingSession, b: &GamingSession| {
+        a.start_time < b.end_time && b.start_time < a.end_time
+    };
+    for (event, column) in cluster {
+        let free = |c: i32| {
+            !cluster
+                .iter()
+                .any(|(e, other)| *other == c && overlaps(e, event))
+        };
+        let span = 1 + (column + 1..columns).take_while(|c| free(*c)).count() as i32;
+        layouts.insert(
+            event.session_id,
+            EventLayout {
+                column: *column,
+                columns,
+                span,
+            },
+        );
+    }
+}
+
+/**
+ * Double bookings of a user: for each session they attend, the titles of the other sessions
+ * they attend at the same time. Sessions without clashes are left out
+ */
+pub fn get_user_clashes(events: &[GamingSession], user_id: &str) -> HashMap<i64, Vec<String>> {
+    let attended: Vec<&GamingSession> = events
+        .iter()
+        .filter(|e| e.is_attended_by(user_id))
+        .collect();
+    let mut clashes: HashMap<i64, Vec<String>> = HashMap::new();
+    for event in &attended {
+        for other in &attended {
+            if event.session_id != other.session_id && event.overlaps(other) {
+                clashes
+                    .entry(event.session_id)
+                    .or_default()
+                    .push(other.title.clone());
+            }
+        }
+    }
+    clashes
+}
+
+/**
+ * Groups items by the day they start on in `tz`, earliest day first. Items keep their order
+ * within a day
+ */
+pub fn group_by_day<T>(
+    items: Vec<T>,
+    tz: Tz,
+    start_time: impl Fn(&T) -> DateTime<Utc>,
+) -> Vec<(NaiveDate, Vec<T>)> {
+    let mut days: BTreeMap<NaiveDate, Vec<T>> = BTreeMap::new();
+    for item in items {
+        let day = start_time(&item).with_timezone(&tz).date_naive();
+        days.entry(day).or_default().push(item);
+    }
+    days.into_iter().collect()
+}
+
 #[cfg(test)]
 mod tests {
     use crate::component::{
-        model::{GamingSession, User},
-        time_util::get_events_stacking,
+        model::{GamingSession, RsvpStatus, SlotCount, User},
+        time_util::{
+            best_slot, calculate_time_pct, calculate_timebar_bottom, convert_simple_time,
+            create_baseline, day_window, drag_times, format_hour, get_events_stacking,
+            get_user_clashes, group_by_day, poll_slots, snap_to_minutes, time_at_pct, EventLayout,
+        },
+    };
+    use chrono::{DateTime, Duration, Utc};
+    use chrono_tz::{
+        America::{New_York, Sao_Paulo},
+        Tz,
     };
-    use chrono::{DateTime, Utc};
+    use proptest::prelude::*;
     use std::collections::HashMap;
 
     struct Setup {
@@ -176,6 +427,10 @@ mod tests {
                 owner: User {
                     name: "username".to_string(),
                     picture: "picture".to_string(),
+                    timezone: None,
+                    status: RsvpStatus::Going,
+                    eta: None,
+                    joined_at: None,
                 },
                 game: Some("game".to_string()),
                 participants: vec![],
@@ -198,13 +453,110 @@ mod tests {
             owner: setup.owner,
             participants: setup.participants,
             game: setup.game,
+            max_participants: None,
+            waitlist: vec![],
+            locked: false,
+            description: None,
+            description_html: None,
+            comment_count: 0,
+        }
+    }
+
+    #[test]
+    fn test_user_clashes() {
+        let setup = Setup::new();
+        let with_user = |session_id: &i64, start, end, status| {
+            let mut session = create_gaming_session(session_id, start, end);
+            session.participants.push(User {
+                name: "alice".to_string(),
+                status,
+                ..setup.owner.clone()
+            });
+            session
+        };
+        let input = vec![
+            with_user(
+                &setup.session_id_1,
+                &setup.time_1,
+                &setup.time_3,
+                RsvpStatus::Going,
+            ),
+            with_user(
+                &setup.session_id_2,
+                &setup.time_2,
+                &setup.time_4,
+                RsvpStatus::Late,
+            ),
+            // not going doesn't count
+            with_user(
+                &setup.session_id_3,
+                &setup.time_3,
+                &setup.time_4,
+                RsvpStatus::NotGoing,
+            ),
+        ];
+        let res = get_user_clashes(&input, "alice");
+        assert_eq!(2, res.len());
+        assert_eq!(vec![setup.title.clone()], res[&setup.session_id_1]);
+        assert!(!res.contains_key(&setup.session_id_3));
+        assert!(get_user_clashes(&input, "bob").is_empty());
+    }
+
+    #[test]
+    fn test_group_by_day() {
+        let setup = Setup::new();
+        let next_day = setup.time_1 + Duration::days(1);
+        let input = vec![
+            create_gaming_session(&setup.session_id_1, &setup.time_1, &setup.time_2),
+            create_gaming_session(&setup.session_id_2, &setup.time_4, &next_day),
+            create_gaming_session(&setup.session_id_3, &next_day, &next_day),
+        ];
+        let ids = |days: Vec<(chrono::NaiveDate, Vec<GamingSession>)>| {
+            days.into_iter()
+                .map(|(day, sessions)| {
+                    (
+                        day.to_string(),
+                        sessions.iter().map(|s| s.session_id).collect::<Vec<_>>(),
+                    )
+                })
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            vec![
+                (
+                    "1996-12-19".to_string(),
+                    vec![setup.session_id_1, setup.session_id_2]
+                ),
+                ("1996-12-20".to_string(), vec![setup.session_id_3]),
+            ],
+            ids(group_by_day(input.clone(), Tz::UTC, |s| s.start_time))
+        );
+        // 19:00 UTC is already the next day in Bangkok
+        assert_eq!(
+            vec![
+                ("1996-12-19".to_string(), vec![setup.session_id_1]),
+                (
+                    "1996-12-20".to_string(),
+                    vec![setup.session_id_2, setup.session_id_3]
+                ),
+            ],
+            ids(group_by_day(input, Tz::Asia__Bangkok, |s| s.start_time))
+        );
+    }
+
+    fn layout(column: i32, columns: i32, span: i32) -> EventLayout {
+        EventLayout {
+            column,
+            columns,
+            span,
         }
     }
 
     #[test]
     fn test_empty_case() {
         let input: Vec<GamingSession> = vec![];
-        let expected: HashMap<i64, i32> = HashMap::new();
+        let expected: HashMap<i64, EventLayout> = HashMap::new();
         let res = get_events_stacking(&input);
         assert_eq!(expected, res);
     }
@@ -216,8 +568,10 @@ mod tests {
             create_gaming_session(&setup.session_id_1, &setup.time_1, &setup.time_3),
             create_gaming_session(&setup.session_id_2, &setup.time_2, &setup.time_4),
         ];
-        let expected: HashMap<i64, i32> =
-            HashMap::from([(setup.session_id_1, 0), (setup.session_id_2, 1)]);
+        let expected = HashMap::from([
+            (setup.session_id_1, layout(0, 2, 1)),
+            (setup.session_id_2, layout(1, 2, 1)),
+        ]);
         let res = get_events_stacking(&input);
         assert_eq!(expected, res);
     }
@@ -229,8 +583,10 @@ mod tests {
             create_gaming_session(&setup.session_id_1, &setup.time_1, &setup.time_2),
             create_gaming_session(&setup.session_id_2, &setup.time_2, &setup.time_3),
         ];
-        let expected: HashMap<i64, i32> =
-            HashMap::from([(setup.session_id_1, 0), (setup.session_id_2, 0)]);
+        let expected = HashMap::from([
+            (setup.session_id_1, layout(0, 1, 1)),
+            (setup.session_id_2, layout(0, 1, 1)),
+        ]);
         let res = get_events_stacking(&input);
         assert_eq!(expected, res);
     }
@@ -243,10 +599,10 @@ mod tests {
             create_gaming_session(&setup.session_id_2, &setup.time_2, &setup.time_4),
             create_gaming_session(&setup.session_id_3, &setup.time_3, &setup.time_4),
         ];
-        let expected: HashMap<i64, i32> = HashMap::from([
-            (setup.session_id_1, 0),
-            (setup.session_id_2, 1),
-            (setup.session_id_3, 0),
+        let expected = HashMap::from([
+            (setup.session_id_1, layout(0, 2, 1)),
+            (setup.session_id_2, layout(1, 2, 1)),
+            (setup.session_id_3, layout(0, 2, 1)),
         ]);
         let res = get_events_stacking(&input);
         assert_eq!(expected, res);
@@ -254,18 +610,346 @@ mod tests {
 
     #[test]
     fn test_second_third_event_same() {
+        // the first event is alone, so it keeps the whole width
         let setup = Setup::new();
         let input: Vec<GamingSession> = vec![
             create_gaming_session(&setup.session_id_1, &setup.time_1, &setup.time_3),
             create_gaming_session(&setup.session_id_2, &setup.time_3, &setup.time_4),
             create_gaming_session(&setup.session_id_3, &setup.time_3, &setup.time_4),
         ];
-        let expected: HashMap<i64, i32> = HashMap::from([
-            (setup.session_id_1, 0),
-            (setup.session_id_2, 0),
-            (setup.session_id_3, 1),
+        let expected = HashMap::from([
+            (setup.session_id_1, layout(0, 1, 1)),
+            (setup.session_id_2, layout(0, 2, 1)),
+            (setup.session_id_3, layout(1, 2, 1)),
         ]);
         let res = get_events_stacking(&input);
         assert_eq!(expected, res);
     }
+
+    #[test]
+    fn test_stacking_expands_into_free_columns() {
+        // 1 runs all along, 2 and 3 only at first. 4 follows 2 and can also take 3's column
+        let setup = Setup::new();
+        let input: Vec<GamingSession> = vec![
+            create_gaming_session(&1, &setup.time_1, &setup.time_3),
+            create_gaming_session(&2, &setup.time_1, &setup.time_2),
+            create_gaming_session(&3, &setup.time_1, &setup.time_2),
+            create_gaming_session(&4, &setup.time_2, &setup.time_3),
+        ];
+        let expected = HashMap::from([
+            (1, layout(0, 3, 1)),
+            (2, layout(1, 3, 1)),
+            (3, layout(2, 3, 1)),
+            (4, layout(1, 3, 2)),
+        ]);
+        assert_eq!(expected, get_events_stacking(&input));
+    }
+
+    proptest! {
+        #[test]
+        fn prop_overlapping_sessions_never_share_a_column(
+            times in prop::collection::vec((0i64..96, 1i64..48), 0..20)
+        ) {
+            // quarter hours over a day, up to 12 hours long
+            let setup = Setup::new();
+            let events: Vec<GamingSession> = times
+                .iter()
+                .enumerate()
+                .map(|(i, (start, length))| {
+                    let start_time = setup.time_1 + Duration::minutes(15 * start);
+                    let end_time = start_time + Duration::minutes(15 * length);
+                    create_gaming_session(&(i as i64), &start_time, &end_time)
+                })
+                .collect();
+            let layouts = get_events_stacking(&events);
+            prop_assert_eq!(events.len(), layouts.len());
+
+            let cols = |e: &GamingSession| {
+                let l = layouts[&e.session_id];
+                l.column..l.column + l.span
+            };
+            for a in &events {
+                let l = layouts[&a.session_id];
+                prop_assert!(l.column >= 0 && l.span >= 1 && l.column + l.span <= l.columns);
+                for b in events.iter().filter(|b| b.session_id != a.session_id) {
+                    if a.start_time < b.end_time && b.start_time < a.end_time {
+                        // same cluster, so the same width, and disjoint columns
+                        prop_assert_eq!(l.columns, layouts[&b.session_id].columns);
+                        let (a_cols, b_cols) = (cols(a), cols(b));
+                        prop_assert!(a_cols.end <= b_cols.start || b_cols.end <= a_cols.start);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_format_hour() {
+        assert_eq!("06:00", format_hour(6, true));
+        assert_eq!("18:00", format_hour(18, true));
+        assert_eq!("12 AM", format_hour(0, false));
+        assert_eq!("6 PM", format_hour(18, false));
+    }
+
+    fn new_york(s: &str) -> DateTime<Tz> {
+        DateTime::parse_from_rfc3339(s)
+            .unwrap()
+            .with_timezone(&New_York)
+    }
+
+    #[test]
+    fn test_baseline_before_offset() {
+        let t = new_york("2025-03-09T01:30:00-05:00");
+        let expected = new_york("2025-03-08T00:00:00-05:00");
+        assert_eq!(expected, create_baseline(t, 6).unwrap());
+    }
+
+    #[test]
+    fn test_baseline_after_offset() {
+        let t = new_york("2025-03-09T07:30:00-04:00");
+        let expected = new_york("2025-03-09T00:00:00-05:00");
+        assert_eq!(expected, create_baseline(t, 6).unwrap());
+    }
+
+    #[test]
+    fn test_baseline_midnight_skipped() {
+        // Sao Paulo skipped from 00:00 to 01:00 on 2018-11-04
+        let t = DateTime::parse_from_rfc3339("2018-11-04T12:00:00-02:00")
+            .unwrap()
+            .with_timezone(&Sao_Paulo);
+        let expected = DateTime::parse_from_rfc3339("2018-11-04T01:00:00-02:00").unwrap();
+        assert_eq!(expected, create_baseline(t, 0).unwrap());
+    }
+
+    #[test]
+    fn test_spring_forward_window_is_23_hours() {
+        let (start, end) = day_window(new_york("2025-03-08T00:00:00-05:00"), 6);
+        assert_eq!(new_york("2025-03-08T06:00:00-05:00"), start);
+        assert_eq!(new_york("2025-03-09T06:00:00-04:00"), end);
+        assert_eq!(Duration::hours(23), end - start);
+    }
+
+    #[test]
+    fn test_fall_back_window_is_25_hours() {
+        let (start, end) = day_window(new_york("2025-11-01T00:00:00-04:00"), 6);
+        assert_eq!(Duration::hours(25), end - start);
+    }
+
+    #[test]
+    fn test_time_pct_spring_forward() {
+        let baseline = new_york("2025-03-08T00:00:00-05:00");
+        let t = new_york("2025-03-09T05:00:00-04:00");
+        assert_eq!(22. / 23., calculate_time_pct(t, baseline, 6));
+    }
+
+    #[test]
+    fn test_time_pct_fall_back() {
+        let baseline = new_york("2025-11-01T00:00:00-04:00");
+        let t = new_york("2025-11-02T05:00:00-05:00");
+        assert_eq!(24. / 25., calculate_time_pct(t, baseline, 6));
+    }
+
+    #[test]
+    fn test_time_pct_clamped() {
+        let baseline = new_york("2025-11-01T00:00:00-04:00");
+        assert_eq!(
+            0.,
+            calculate_time_pct(new_york("2025-11-01T05:00:00-04:00"), baseline, 6)
+        );
+        assert_eq!(
+            1.,
+            calculate_time_pct(new_york("2025-11-02T07:00:00-05:00"), baseline, 6)
+        );
+    }
+
+    #[test]
+    fn test_time_at_pct_inverts_time_pct() {
+        // 25 hour day, every quarter hour maps back to itself
+        let baseline = new_york("2025-11-01T00:00:00-04:00");
+        let (start, end) = day_window(baseline, 6);
+        let mut t = start;
+        while t <= end {
+            let pct = calculate_time_pct(t, baseline, 6);
+            assert_eq!(t, time_at_pct(pct, baseline, 6));
+            t += Duration::minutes(15);
+        }
+    }
+
+    #[test]
+    fn test_time_at_pct_spring_forward() {
+        let baseline = new_york("2025-03-08T00:00:00-05:00");
+        assert_eq!(
+            new_york("2025-03-09T05:00:00-04:00"),
+            time_at_pct(22. / 23., baseline, 6)
+        );
+    }
+
+    #[test]
+    fn test_time_at_pct_clamped() {
+        let baseline = new_york("2025-11-01T00:00:00-04:00");
+        let (start, end) = day_window(baseline, 6);
+        assert_eq!(start, time_at_pct(-0.5, baseline, 6));
+        assert_eq!(end, time_at_pct(1.5, baseline, 6));
+    }
+
+    #[test]
+    fn test_snap_to_minutes() {
+        assert_eq!(
+            new_york("2025-03-08T10:15:00-05:00"),
+            snap_to_minutes(new_york("2025-03-08T10:22:29-05:00"), 15)
+        );
+        assert_eq!(
+            new_york("2025-03-08T10:30:00-05:00"),
+            snap_to_minutes(new_york("2025-03-08T10:22:30-05:00"), 15)
+        );
+        // quarter hours stay put, even on half hour timezones
+        let t = DateTime::parse_from_rfc3339("2025-03-08T10:45:00+05:30")
+            .unwrap()
+            .with_timezone(&Tz::Asia__Kolkata);
+        assert_eq!(t, snap_to_minutes(t + Duration::seconds(200), 15));
+    }
+
+    #[test]
+    fn test_drag_times_move() {
+        // 24 hour day, a 24th is an hour
+        let baseline = new_york("2025-03-10T00:00:00-04:00");
+        let start = new_york("2025-03-10T20:07:00-04:00");
+        let end = new_york("2025-03-10T22:07:00-04:00");
+        assert_eq!(
+            (
+                new_york("2025-03-10T21:00:00-04:00"),
+                new_york("2025-03-10T23:00:00-04:00")
+            ),
+            drag_times(start, end, 1. / 24., false, baseline, 6, 15)
+        );
+    }
+
+    #[test]
+    fn test_drag_times_move_stays_in_window() {
+        let baseline = new_york("2025-03-10T00:00:00-04:00");
+        let (window_start, window_end) = day_window(baseline, 6);
+        let start = new_york("2025-03-10T20:00:00-04:00");
+        let end = new_york("2025-03-10T22:00:00-04:00");
+        assert_eq!(
+            (window_end - Duration::hours(2), window_end),
+            drag_times(start, end, 0.5, false, baseline, 6, 15)
+        );
+        assert_eq!(
+            (window_start, window_start + Duration::hours(2)),
+            drag_times(start, end, -0.9, false, baseline, 6, 15)
+        );
+    }
+
+    #[test]
+    fn test_drag_times_resize() {
+        let baseline = new_york("2025-03-10T00:00:00-04:00");
+        let (_, window_end) = day_window(baseline, 6);
+        let start = new_york("2025-03-10T20:00:00-04:00");
+        let end = new_york("2025-03-10T22:00:00-04:00");
+        assert_eq!(
+            (start, new_york("2025-03-10T22:30:00-04:00")),
+            drag_times(start, end, 0.5 / 24., true, baseline, 6, 15)
+        );
+        // at least one step long, at most until the day ends
+        assert_eq!(
+            (start, start + Duration::minutes(15)),
+            drag_times(start, end, -0.5, true, baseline, 6, 15)
+        );
+        assert_eq!(
+            (start, window_end),
+            drag_times(start, end, 0.9, true, baseline, 6, 15)
+        );
+    }
+
+    #[test]
+    fn test_timebar_bottom_fall_back() {
+        let t = new_york("2025-11-02T05:00:00-05:00");
+        assert!((4. - calculate_timebar_bottom(t, 6)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_convert_simple_time_after_midnight() {
+        let baseline = new_york("2025-03-08T00:00:00-05:00");
+        let expected = new_york("2025-03-09T05:00:00-04:00");
+        assert_eq!(
+            expected,
+            convert_simple_time("05:00".to_string(), baseline, 6).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_convert_simple_time_spring_forward_gap() {
+        let baseline = new_york("2025-03-09T00:00:00-05:00");
+        let expected = new_york("2025-03-09T03:30:00-04:00");
+        assert_eq!(
+            expected,
+            convert_simple_time("02:30".to_string(), baseline, 0).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_convert_simple_time_fall_back_ambiguous() {
+        let baseline = new_york("2025-11-02T00:00:00-04:00");
+        let expected = new_york("2025-11-02T01:30:00-04:00");
+        assert_eq!(
+            expected,
+            convert_simple_time("01:30".to_string(), baseline, 0).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_convert_simple_time_malformed() {
+        let baseline = new_york("2025-03-08T00:00:00-05:00");
+        for time in ["", "7", "ab:cd", "24:00", "12:60", "-1:30"] {
+            assert!(convert_simple_time(time.to_string(), baseline, 6).is_err());
+        }
+    }
+
+    fn slot_count(s: &str, count: usize) -> SlotCount {
+        SlotCount {
+            slot_start: DateTime::parse_from_rfc3339(s).unwrap().to_utc(),
+            count,
+        }
+    }
+
+    #[test]
+    fn test_best_slot_empty() {
+        assert_eq!(None, best_slot(&[]));
+        assert_eq!(None, best_slot(&[slot_count("1996-12-19T16:00:00Z", 0)]));
+    }
+
+    #[test]
+    fn test_best_slot_longest_run() {
+        let setup = Setup::new();
+        let heatmap = vec![
+            slot_count("1996-12-19T12:00:00Z", 3),
+            slot_count("1996-12-19T16:00:00Z", 3),
+            slot_count("1996-12-19T18:00:00Z", 3),
+            slot_count("1996-12-19T17:00:00Z", 3),
+            slot_count("1996-12-19T19:00:00Z", 2),
+        ];
+        assert_eq!(Some((setup.time_1, setup.time_4)), best_slot(&heatmap));
+    }
+
+    #[test]
+    fn test_best_slot_ties_go_to_earliest() {
+        let setup = Setup::new();
+        let heatmap = vec![
+            slot_count("1996-12-19T18:00:00Z", 2),
+            slot_count("1996-12-19T16:00:00Z", 2),
+            slot_count("1996-12-19T17:00:00Z", 1),
+        ];
+        assert_eq!(Some((setup.time_1, setup.time_2)), best_slot(&heatmap));
+    }
+
+    #[test]
+    fn test_poll_slots_in_window() {
+        let setup = Setup::new();
+        let poll_start = setup.time_1 - Duration::days(1);
+        let poll_end = setup.time_4 + Duration::days(1);
+        assert_eq!(
+            vec![setup.time_1, setup.time_2, setup.time_3],
+            poll_slots(poll_start, poll_end, setup.time_1, setup.time_4)
+        );
+    }
 }
@@ -0,0 +1,115 @@
+use chrono_tz::TZ_VARIANTS;
+use leptos::{logging::log, prelude::*};
+use reactive_stores::Store;
+
+use crate::{
+    app::{GlobalState, GlobalStateStoreFields},
+    component::time_util::get_user_timezone,
+    error::{error_message, AppError},
+    obf_util::UrlParamsStoreFields,
+};
+
+/**
+ * Navbar dropdown to save the user's timezone to their profile.
+ * Other participants see event times converted to it.
+ */
+#[component]
+pub fn TimezonePicker() -> impl IntoView {
+    let state = expect_context::<Store<GlobalState>>();
+    let link = state.url_params().get_untracked().encode();
+    let user_timezone = state.user_timezone();
+
+    // preselect the saved timezone, or the browser's if none is saved
+    let (selected, set_selected) = signal(String::new());
+    Effect::new(move || {
+        set_selected(user_timezone.get_untracked().unwrap_or_else(|| {
+            get_user_timezone(&state.server_settings().get_untracked()).to_string()
+        }));
+    });
+
+    let (error, set_error) = signal(None::<String>);
+
+    // handle ActionForm
+    let set_timezone = ServerAction::<SetProfileTimezone>::new();
+    let server_res = set_timezone.value();
+    Effect::new(move || match server_res() {
+        Some(Ok(tz)) => {
+            set_error(None);
+            user_timezone.set(Some(tz.clone()));
+            // the user's own participant entries pick up the new timezone straight away
+            state.calendar_events().update(|v| {
+                let user_id = state.url_params().user_id().get_untracked();
+                for session in v.iter_mut() {
+                    for p in session
+                        .participants
+                        .iter_mut()
+                        .filter(|p| p.get_name() == user_id)
+                    {
+                        p.timezone = Some(tz.clone());
+                    }
+                }
+            });
+        }
+        Some(Err(e)) => {
+            log!("{:?}", e);
+            set_error(Some(error_message(&e)));
+        }
+        None => {}
+    });
+
+    view! {
+        <div class="dropdown dropdown-end">
+            <div tabindex="0" role="button" class="btn m-1">
+                { move || user_timezone.get().unwrap_or("Timezone".to_string()) }
+            </div>
+            <div tabindex="0" class="dropdown-content bg-base-300 rounded-box z-1 w-64 p-3 shadow-2xl">
+                <ActionForm action=set_timezone>
+                    <input type="text" class="hidden invisible" name="link" value={link}/>
+                    <select class="select select-sm w-full" name="timezone">
+                        {
+                            TZ_VARIANTS.iter().map(|tz| view! {
+                                <option value={tz.name()} selected=move || selected() == tz.name()>{ tz.name() }</option>
+                            }).collect_view()
+                        }
+                    </select>
+                    <button type="submit" class="btn btn-sm btn-neutral w-full mt-2">Save</button>
+                    {
+                        move || error().map(|msg| view! { <p class="text-error text-xs mt-2">{msg}</p> })
+                    }
+                </ActionForm>
+            </div>
+        </div>
+    }
+}
+
+#[server]
+#[cfg_attr(
+    feature = "ssr",
+    tracing::instrument(
+        name = "set_profile_timezone",
+        skip_all,
+        fields(user_id = tracing::field::Empty),
+        err
+    )
+)]
+pub async fn set_profile_timezone(
+    link: String,
+    timezone: String,
+) -> Result<String, ServerFnError<AppError>> {
+    use crate::{config::pool, dao::sqlite_util::SqliteClient, obf_util::verify_caller};
+    use chrono_tz::Tz;
+
+    let user_id = verify_caller(&link)?.get_user_id();
+
+    let pool = pool()?;
+    let client = SqliteClient::from_pool(pool).await;
+
+    if timezone.parse::<Tz>().is_err() {
+        return Err(AppError::validation(format!("Unknown timezone: {timezone}")).into());
+    }
+
+    match client.upsert_profile(&user_id, &timezone).await {
+        Ok(profile) => Ok(profile.timezone),
+        Err(e) => Err(e.into()),
+    }
+}
@@ -0,0 +1,365 @@
+use chrono::{DateTime, Duration, Utc};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+
+use super::time_util::convert_simple_time;
+use crate::error::FieldErrors;
+
+pub const MAX_TITLE_CHARS: usize = 30;
+pub const MAX_GAME_CHARS: usize = 30;
+pub const MAX_DESCRIPTION_CHARS: usize = 1000;
+pub const MAX_COMMENT_CHARS: usize = 500;
+pub const MAX_PARTICIPANTS: usize = 100;
+pub const MAX_DURATION_HOURS: i64 = 12;
+// events created right as they begin may start a little in the past
+pub const PAST_GRACE_MINUTES: i64 = 15;
+// late users still have to arrive within a typical session
+pub const MAX_MINUTES_LATE: i64 = 240;
+
+/**
+ * Event form values as posted by the create and edit forms. Times are simple html times
+ * (XX:XX) on the day of `baseline`
+ */
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct EventForm {
+    pub title: String,
+    pub start: String,
+    pub end: String,
+    pub game: String,
+    pub max_participants: String,
+    #[serde(default)]
+    pub description: String,
+}
+
+/**
+ * Event values that passed validation
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub struct ValidEvent {
+    pub title: String,
+    pub start: DateTime<Tz>,
+    pub end: DateTime<Tz>,
+    pub game: Option<String>,
+    pub max_participants: Option<usize>,
+    // markdown
+    pub description: Option<String>,
+}
+
+impl EventForm {
+    /**
+     * Checks every field, collecting all problems by input name. Events may not start
+     * before `not_before`
+     */
+    pub fn validate(
+        &self,
+        baseline: DateTime<Tz>,
+        offset: usize,
+        not_before: DateTime<Utc>,
+    ) -> Result<ValidEvent, FieldErrors> {
+        let mut errors = FieldErrors::default();
+
+        let title = validate_title(&self.title)
+            .map_err(|e| errors.add("title", e))
+            .ok();
+        let game = validate_game(&self.game)
+            .map_err(|e| errors.add("game", e))
+            .ok();
+        let max_participants = validate_max_participants(&self.max_participants)
+            .map_err(|e| errors.add("max_participants", e))
+            .ok();
+        let description = validate_description(&self.description)
+            .map_err(|e| errors.add("description", e))
+            .ok();
+
+        let start = convert_simple_time(self.start.clone(), baseline, offset)
+            .map_err(|_| errors.add("start", "Start time must look like 20:30"))
+            .ok();
+        let end = convert_simple_time(self.end.clone(), baseline, offset)
+            .map_err(|_| errors.add("end", "End time must look like 23:00"))
+            .ok();
+        if let (Some(start), Some(end)) = (start, end) {
+            if let Err((field, e)) = validate_times(start.to_utc(), end.to_utc(), not_before) {
+                errors.add(field, e);
+            }
+        }
+
+        match (title, start, end, game, max_participants, description) {
+            (
+                Some(title),
+                Some(start),
+                Some(end),
+                Some(game),
+                Some(max_participants),
+                Some(description),
+            ) if errors.is_empty() => Ok(ValidEvent {
+                title,
+                start,
+                end,
+                game,
+                max_participants,
+                description,
+            }),
+            _ => Err(errors),
+        }
+    }
+}
+
+// earliest start allowed for events created at `now`
+pub fn earliest_start(now: DateTime<Utc>) -> DateTime<Utc> {
+    now - Duration::minutes(PAST_GRACE_MINUTES)
+}
+
+pub fn validate_title(title: &str) -> Result<String, String> {
+    let title = title.trim();
+    if title.is_empty() {
+        Err("Title is required".to_string())
+    } else if title.chars().count() > MAX_TITLE_CHARS {
+        Err(format!("Title can be at most {MAX_TITLE_CHARS} characters"))
+    } else if title.chars().any(char::is_control) {
+        Err("Title can't contain control characters".to_string())
+    } else {
+        Ok(title.to_string())
+    }
+}
+
+pub fn validate_game(game: &str) -> Result<Option<String>, String> {
+    let game = game.trim();
+    if game.is_empty() {
+        Ok(None)
+    } else if game.chars().count() > MAX_GAME_CHARS {
+        Err(format!("Game can be at most {MAX_GAME_CHARS} characters"))
+    } else if game.chars().any(char::is_control) {
+        Err("Game can't contain control characters".to_string())
+    } else {
+        Ok(Some(game.to_string()))
+    }
+}
+
+pub fn validate_max_participants(max: &str) -> Result<Option<usize>, String> {
+    let max = max.trim();
+    if max.is_empty() {
+        return Ok(None);
+    }
+    match max.parse::<usize>() {
+        Ok(max) if (1..=MAX_PARTICIPANTS).contains(&max) => Ok(Some(max)),
+        _ => Err(format!(
+            "Max players must be between 1 and {MAX_PARTICIPANTS}"
+        )),
+    }
+}
+
+#[cfg(feature = "ssr")]
+pub fn validate_minutes_late(minutes: &str) -> Result<i64, String> {
+    match minutes.trim().parse::<i64>() {
+        Ok(minutes) if (0..=MAX_MINUTES_LATE).contains(&minutes) => Ok(minutes),
+        _ => Err(format!(
+            "Minutes late must be between 0 and {MAX_MINUTES_LATE}"
+        )),
+    }
+}
+
+pub fn validate_description(description: &str) -> Result<Option<String>, String> {
+    let description = description.trim();
+    if description.is_empty() {
+        Ok(None)
+    } else if description.chars().count() > MAX_DESCRIPTION_CHARS {
+        Err(format!(
+            "Description can be at most {MAX_DESCRIPTION_CHARS} characters"
+        ))
+    } else {
+        Ok(Some(description.to_string()))
+    }
+}
+
+pub fn validate_comment(body: &str) -> Result<String, String> {
+    let body = body.trim();
+    if body.is_empty() {
+        Err("Comment can't be empty".to_string())
+    } else if body.chars().count() > MAX_COMMENT_CHARS {
+        Err(format!(
+            "Comments can be at most {MAX_COMMENT_CHARS} characters"
+        ))
+    } else if body.chars().any(|c| c.is_control() && c != '\n') {
+        Err("Comment can't contain control characters".to_string())
+    } else {
+        Ok(body.to_string())
+    }
+}
+
+// the field at fault and why
+pub fn validate_times(
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    not_before: DateTime<Utc>,
+) -> Result<(), (&'static str, String)> {
+    if start < not_before {
+        Err(("start", "Start time is in the past".to_string()))
+    } else if end <= start {
+        Err(("end", "End time must be after the start time".to_string()))
+    } else if end - start > Duration::hours(MAX_DURATION_HOURS) {
+        Err((
+            "end",
+            format!("Events can last at most {MAX_DURATION_HOURS} hours"),
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+// stored hours are integers, the calendar day has to start within a day
+#[cfg(feature = "ssr")]
+pub fn validate_day_start_hour(hour: i64) -> Result<usize, String> {
+    usize::try_from(hour)
+        .ok()
+        .filter(|h| *h < 24)
+        .ok_or_else(|| format!("Day start hour must be between 0 and 23, got {hour}"))
+}
+
+#[cfg(feature = "ssr")]
+pub fn validate_timezone(timezone: &str) -> Result<Tz, String> {
+    timezone
+        .parse()
+        .map_err(|_| format!("Unknown timezone: {timezone}"))
+}
+
+/**
+ * Timezone the times of a new event are entered in. Only the creator's profile timezone and the
+ * server's are accepted, the form offers no other
+ */
+#[cfg(feature = "ssr")]
+pub fn validate_entry_timezone(
+    timezone: &str,
+    profile_timezone: Option<&str>,
+    server_tz: Tz,
+) -> Result<Tz, String> {
+    let tz = validate_timezone(timezone)?;
+    if tz != server_tz && profile_timezone != Some(tz.name()) {
+        return Err(format!(
+            "Times can be entered in your profile's or the server's timezone, not {tz}"
+        ));
+    }
+    Ok(tz)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono_tz::America::New_York;
+
+    fn baseline() -> DateTime<Tz> {
+        DateTime::parse_from_rfc3339("2025-03-08T00:00:00-05:00")
+            .unwrap()
+            .with_timezone(&New_York)
+    }
+
+    fn form(title: &str, start: &str, end: &str, game: &str) -> EventForm {
+        EventForm {
+            title: title.to_string(),
+            start: start.to_string(),
+            end: end.to_string(),
+            game: game.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_valid_event() {
+        let event = form("  Raid night ", "20:00", "02:00", "")
+            .validate(baseline(), 6, baseline().to_utc())
+            .unwrap();
+        assert_eq!("Raid night", event.title);
+        assert_eq!(Duration::hours(6), event.end - event.start);
+        assert_eq!(None, event.game);
+        assert_eq!(None, event.description);
+    }
+
+    #[test]
+    fn test_collects_every_field() {
+        let long = "x".repeat(MAX_GAME_CHARS + 1);
+        let errors = EventForm {
+            max_participants: "0".to_string(),
+            description: "x".repeat(MAX_DESCRIPTION_CHARS + 1),
+            ..form(" ", "7pm", "21:00", &long)
+        }
+        .validate(baseline(), 6, baseline().to_utc())
+        .unwrap_err();
+        assert!(errors.get("title").is_some());
+        assert!(errors.get("start").is_some());
+        assert!(errors.get("game").is_some());
+        assert!(errors.get("max_participants").is_some());
+        assert!(errors.get("description").is_some());
+        // end can't be compared without a start
+        assert!(errors.get("end").is_none());
+    }
+
+    #[cfg(feature = "ssr")]
+    #[test]
+    fn test_server_settings() {
+        assert_eq!(Ok(0), validate_day_start_hour(0));
+        assert_eq!(Ok(23), validate_day_start_hour(23));
+        assert!(validate_day_start_hour(24).is_err());
+        assert!(validate_day_start_hour(-1).is_err());
+        assert!(validate_timezone("Europe/Berlin").is_ok());
+        assert!(validate_timezone("Mars/Olympus").is_err());
+    }
+
+    #[cfg(feature = "ssr")]
+    #[test]
+    fn test_entry_timezone() {
+        let berlin = Some("Europe/Berlin");
+        assert_eq!(
+            Ok(New_York),
+            validate_entry_timezone("America/New_York", berlin, New_York)
+        );
+        assert_eq!(
+            Ok(chrono_tz::Europe::Berlin),
+            validate_entry_timezone("Europe/Berlin", berlin, New_York)
+        );
+        assert!(validate_entry_timezone("Asia/Tokyo", berlin, New_York).is_err());
+        assert!(validate_entry_timezone("Asia/Tokyo", None, New_York).is_err());
+        assert!(validate_entry_timezone("Mars/Olympus", None, New_York).is_err());
+    }
+
+    #[test]
+    fn test_comment() {
+        assert_eq!(
+            Ok("running\n10 min late".to_string()),
+            validate_comment(" running\n10 min late ")
+        );
+        assert!(validate_comment("  ").is_err());
+        assert!(validate_comment("bell\u{7}").is_err());
+        assert!(validate_comment(&"x".repeat(MAX_COMMENT_CHARS + 1)).is_err());
+    }
+
+    #[cfg(feature = "ssr")]
+    #[test]
+    fn test_minutes_late() {
+        assert_eq!(Ok(0), validate_minutes_late("0"));
+        assert_eq!(Ok(15), validate_minutes_late(" 15 "));
+        assert_eq!(Ok(MAX_MINUTES_LATE), validate_minutes_late("240"));
+        assert!(validate_minutes_late("241").is_err());
+        assert!(validate_minutes_late("-5").is_err());
+        assert!(validate_minutes_late("soon").is_err());
+        assert!(validate_minutes_late("").is_err());
+    }
+
+    #[test]
+    fn test_times() {
+        let now = baseline().to_utc() + Duration::hours(20);
+        let check = |start: &str, end: &str| {
+            form("Raid", start, end, "")
+                .validate(baseline(), 6, earliest_start(now))
+                .map_err(|e| (e.get("start"), e.get("end")))
+        };
+        assert!(check("19:50", "21:00").is_ok());
+        assert!(matches!(check("19:30", "21:00"), Err((Some(_), None))));
+        assert!(matches!(check("21:00", "21:00"), Err((None, Some(_)))));
+        assert!(matches!(check("22:00", "21:00"), Err((None, Some(_)))));
+
+        // too long
+        let errors = form("Raid", "08:00", "21:00", "")
+            .validate(baseline(), 6, baseline().to_utc())
+            .unwrap_err();
+        assert!(errors.get("end").unwrap().contains("at most"));
+    }
+}
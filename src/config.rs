@@ -0,0 +1,385 @@
+use std::{env, fs, path::PathBuf, time::Duration};
+
+use chrono_tz::Tz;
+use serde::Deserialize;
+use sqlx::{Pool, Sqlite};
+use thiserror::Error;
+
+use crate::{
+    component::model::ServerSettings,
+    error::AppError,
+    retention::{RetentionMode, RetentionPolicy},
+    telemetry::LogFormat,
+};
+
+const DEFAULT_CONFIG_FILE: &str = "gametonite.toml";
+
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    #[error("could not read config file {path}: {source}")]
+    Read {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("invalid config file {path}: {source}")]
+    Parse {
+        path: PathBuf,
+        source: toml::de::Error,
+    },
+    #[error("invalid {key}: {reason}")]
+    Invalid { key: &'static str, reason: String },
+}
+
+/**
+ * Server configuration. Values come from the environment (and a .env file) first, then from
+ * the TOML file named by GAMETONITE_CONFIG or ./gametonite.toml if it exists, then defaults
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub struct Config {
+    pub database_url: String,
+    pub pool_size: u32,
+    // secret used to sign user links
+    pub link_secret: Option<String>,
+    pub webhook_url: Option<String>,
+    pub retention: Option<RetentionPolicy>,
+    // settings for servers that haven't saved their own
+    pub defaults: ServerSettings,
+    // tracing filter, e.g. "info" or "info,gaming_calendar_website=debug"
+    pub log_level: String,
+    pub log_format: LogFormat,
+    // limit how often server functions can be called
+    pub rate_limit: bool,
+    // trust X-Forwarded-For for client addresses, only behind a reverse proxy
+    pub trust_proxy: bool,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FileConfig {
+    database_url: Option<String>,
+    pool_size: Option<u32>,
+    link_secret: Option<String>,
+    webhook_url: Option<String>,
+    log_level: Option<String>,
+    log_format: Option<String>,
+    rate_limit: Option<bool>,
+    trust_proxy: Option<bool>,
+    #[serde(default)]
+    retention: FileRetention,
+    #[serde(default)]
+    defaults: FileDefaults,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FileRetention {
+    days: Option<u32>,
+    mode: Option<String>,
+    interval_secs: Option<u64>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FileDefaults {
+    day_start_hour: Option<usize>,
+    timezone: Option<String>,
+    use_24h: Option<bool>,
+}
+
+impl Config {
+    pub fn load() -> Result<Self, ConfigError> {
+        dotenv::dotenv().ok();
+
+        let (path, required) = match env::var("GAMETONITE_CONFIG") {
+            Ok(path) => (PathBuf::from(path), true),
+            Err(_) => (PathBuf::from(DEFAULT_CONFIG_FILE), false),
+        };
+        let file = if required || path.exists() {
+            let text = fs::read_to_string(&path).map_err(|source| ConfigError::Read {
+                path: path.clone(),
+                source,
+            })?;
+            Some((path, text))
+        } else {
+            None
+        };
+
+        Self::from_sources(
+            file.as_ref()
+                .map(|(path, text)| (path.clone(), text.as_str())),
+            |key| env::var(key).ok(),
+        )
+    }
+
+    // builds the config from a TOML file's contents and an environment lookup
+    fn from_sources(
+        file: Option<(PathBuf, &str)>,
+        env: impl Fn(&str) -> Option<String>,
+    ) -> Result<Self, ConfigError> {
+        let file = match file {
+            Some((path, text)) => {
+                toml::from_str(text).map_err(|source| ConfigError::Parse { path, source })?
+            }
+            None => FileConfig::default(),
+        };
+        let env = |key: &str| env(key).filter(|v| !v.trim().is_empty());
+
+        let database_url = env("DATABASE_URL")
+            .or(file.database_url)
+            .unwrap_or("sqlite://sessions.db".to_string());
+        if !database_url.starts_with("sqlite:") {
+            return Err(invalid(
+                "DATABASE_URL",
+                "only sqlite databases are supported",
+            ));
+        }
+
+        let pool_size = match env("DATABASE_POOL_SIZE") {
+            Some(size) => parse("DATABASE_POOL_SIZE", &size)?,
+            None => file.pool_size.unwrap_or(5),
+        };
+        if pool_size == 0 {
+            return Err(invalid("DATABASE_POOL_SIZE", "must be at least 1"));
+        }
+
+        let link_secret = env("LINK_SECRET").or(file.link_secret);
+        if link_secret.as_ref().is_some_and(|s| s.len() < 16) {
+            return Err(invalid("LINK_SECRET", "must be at least 16 characters"));
+        }
+
+        let webhook_url = env("WEBHOOK_URL").or(file.webhook_url);
+        if webhook_url
+            .as_ref()
+            .is_some_and(|url| !url.starts_with("https://") && !url.starts_with("http://"))
+        {
+            return Err(invalid("WEBHOOK_URL", "must be an http(s) URL"));
+        }
+
+        let retention_days = match env("RETENTION_DAYS") {
+            Some(days) => Some(parse("RETENTION_DAYS", &days)?),
+            None => file.retention.days,
+        };
+        let retention = match retention_days {
+            Some(days) => {
+                let mode = match env("RETENTION_MODE").or(file.retention.mode) {
+                    Some(mode) => mode
+                        .parse()
+                        .map_err(|e: anyhow::Error| invalid("RETENTION_MODE", e))?,
+                    None => RetentionMode::Archive,
+                };
+                let interval = match env("RETENTION_INTERVAL_SECS") {
+                    Some(secs) => parse("RETENTION_INTERVAL_SECS", &secs)?,
+                    None => file.retention.interval_secs.unwrap_or(3600),
+                };
+                if interval == 0 {
+                    return Err(invalid("RETENTION_INTERVAL_SECS", "must be at least 1"));
+                }
+                Some(RetentionPolicy {
+                    mode,
+                    days,
+                    interval: Duration::from_secs(interval),
+                })
+            }
+            None => None,
+        };
+
+        let fallback = ServerSettings::default();
+        let day_start_hour = match env("DAY_START_HOUR") {
+            Some(hour) => parse("DAY_START_HOUR", &hour)?,
+            None => file
+                .defaults
+                .day_start_hour
+                .unwrap_or(fallback.day_start_hour),
+        };
+        if day_start_hour > 23 {
+            return Err(invalid("DAY_START_HOUR", "must be between 0 and 23"));
+        }
+        let timezone = env("DEFAULT_TIMEZONE")
+            .or(file.defaults.timezone)
+            .unwrap_or(fallback.timezone);
+        if timezone.parse::<Tz>().is_err() {
+            return Err(invalid(
+                "DEFAULT_TIMEZONE",
+                format!("{timezone} is not an IANA timezone"),
+            ));
+        }
+        let use_24h = match env("USE_24H") {
+            Some(use_24h) => parse("USE_24H", &use_24h)?,
+            None => file.defaults.use_24h.unwrap_or(fallback.use_24h),
+        };
+
+        let log_level = env("LOG_LEVEL")
+            .or(file.log_level)
+            .unwrap_or("info".to_string());
+        if tracing_subscriber::EnvFilter::try_new(&log_level).is_err() {
+            return Err(invalid(
+                "LOG_LEVEL",
+                format!("{log_level} is not a log filter"),
+            ));
+        }
+        let log_format = match env("LOG_FORMAT").or(file.log_format) {
+            Some(format) => format
+                .parse()
+                .map_err(|e: anyhow::Error| invalid("LOG_FORMAT", e))?,
+            None => LogFormat::Json,
+        };
+
+        let rate_limit = match env("RATE_LIMIT") {
+            Some(enabled) => parse("RATE_LIMIT", &enabled)?,
+            None => file.rate_limit.unwrap_or(true),
+        };
+        let trust_proxy = match env("TRUST_PROXY") {
+            Some(trust) => parse("TRUST_PROXY", &trust)?,
+            None => file.trust_proxy.unwrap_or(false),
+        };
+
+        Ok(Config {
+            database_url,
+            pool_size,
+            link_secret,
+            webhook_url,
+            retention,
+            defaults: ServerSettings {
+                day_start_hour,
+                timezone,
+                use_24h,
+            },
+            log_level,
+            log_format,
+            rate_limit,
+            trust_proxy,
+        })
+    }
+}
+
+/**
+ * Configured settings for servers that haven't saved their own. Only available inside
+ * server functions, where the config is provided as context
+ */
+pub fn default_settings() -> ServerSettings {
+    leptos::prelude::use_context::<ServerSettings>().unwrap_or_default()
+}
+
+/// Secret signing user links, provided as context when one is configured
+#[derive(Clone, Debug)]
+pub struct LinkSecret(pub String);
+
+/**
+ * Configured link secret. Only available inside server functions, like `default_settings`
+ */
+pub fn link_secret() -> Option<String> {
+    leptos::prelude::use_context::<LinkSecret>().map(|secret| secret.0)
+}
+
+/**
+ * Database pool. Only available inside server functions, like `default_settings`
+ */
+pub fn pool() -> Result<Pool<Sqlite>, AppError> {
+    leptos::prelude::use_context::<Pool<Sqlite>>()
+        .ok_or_else(|| AppError::storage("pool not found"))
+}
+
+fn invalid(key: &'static str, reason: impl ToString) -> ConfigError {
+    ConfigError::Invalid {
+        key,
+        reason: reason.to_string(),
+    }
+}
+
+fn parse<T: std::str::FromStr>(key: &'static str, value: &str) -> Result<T, ConfigError> {
+    value
+        .trim()
+        .parse()
+        .map_err(|_| invalid(key, format!("could not parse {value:?}")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn load(file: Option<&str>, env: &[(&str, &str)]) -> Result<Config, ConfigError> {
+        let env: HashMap<String, String> = env
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        Config::from_sources(file.map(|text| (PathBuf::from("test.toml"), text)), |key| {
+            env.get(key).cloned()
+        })
+    }
+
+    #[test]
+    fn test_defaults() {
+        let config = load(None, &[]).unwrap();
+        assert_eq!("sqlite://sessions.db", config.database_url);
+        assert_eq!(5, config.pool_size);
+        assert!(config.retention.is_none());
+        assert_eq!(ServerSettings::default(), config.defaults);
+        assert_eq!("info", config.log_level);
+        assert_eq!(LogFormat::Json, config.log_format);
+        assert!(config.rate_limit);
+        assert!(!config.trust_proxy);
+    }
+
+    #[test]
+    fn test_env_overrides_file() {
+        let file = r#"
+            database_url = "sqlite://file.db"
+            pool_size = 2
+
+            [retention]
+            days = 30
+            mode = "delete"
+
+            [defaults]
+            timezone = "Europe/Berlin"
+        "#;
+        let config = load(Some(file), &[("DATABASE_URL", "sqlite://env.db")]).unwrap();
+        assert_eq!("sqlite://env.db", config.database_url);
+        assert_eq!(2, config.pool_size);
+        assert_eq!("Europe/Berlin", config.defaults.timezone);
+        let retention = config.retention.unwrap();
+        assert_eq!(30, retention.days);
+        assert_eq!(RetentionMode::Delete, retention.mode);
+        assert_eq!(Duration::from_secs(3600), retention.interval);
+    }
+
+    #[test]
+    fn test_invalid_values() {
+        assert!(matches!(
+            load(None, &[("DATABASE_POOL_SIZE", "lots")]),
+            Err(ConfigError::Invalid {
+                key: "DATABASE_POOL_SIZE",
+                ..
+            })
+        ));
+        assert!(matches!(
+            load(None, &[("DEFAULT_TIMEZONE", "Mars/Olympus")]),
+            Err(ConfigError::Invalid {
+                key: "DEFAULT_TIMEZONE",
+                ..
+            })
+        ));
+        assert!(matches!(
+            load(
+                None,
+                &[("RETENTION_DAYS", "7"), ("RETENTION_MODE", "shred")]
+            ),
+            Err(ConfigError::Invalid {
+                key: "RETENTION_MODE",
+                ..
+            })
+        ));
+        assert!(matches!(
+            load(None, &[("LOG_FORMAT", "xml")]),
+            Err(ConfigError::Invalid {
+                key: "LOG_FORMAT",
+                ..
+            })
+        ));
+        assert!(matches!(
+            load(Some("pool_sise = 3"), &[]),
+            Err(ConfigError::Parse { .. })
+        ));
+    }
+}
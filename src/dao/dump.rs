@@ -0,0 +1,364 @@
+use serde::{Deserialize, Serialize};
+use sqlx::prelude::FromRow;
+use tracing::instrument;
+
+use crate::{
+    component::validation::{validate_day_start_hour, validate_timezone},
+    dao::sqlite_util::{
+        CommentRecord, OwnershipRecord, PollRecord, RoleRecord, ServerRecord, SessionRecord,
+        SqliteClient, UserRecord, WaitlistRecord,
+    },
+    error::AppError,
+};
+
+type Result<T> = std::result::Result<T, AppError>;
+
+#[derive(Clone, FromRow, Debug, Serialize, Deserialize)]
+pub struct AvailabilityRecord {
+    pub poll_id: i64,
+    pub user_id: String,
+    pub slot_start: String,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SessionDump {
+    pub session: SessionRecord,
+    pub users: Vec<UserRecord>,
+    pub waitlist: Vec<WaitlistRecord>,
+    pub ownership_history: Vec<OwnershipRecord>,
+    // dumps made before comments existed have none
+    #[serde(default)]
+    pub comments: Vec<CommentRecord>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PollDump {
+    pub poll: PollRecord,
+    pub availability: Vec<AvailabilityRecord>,
+}
+
+/**
+ * Everything stored for one server. Ids are only used to link rows inside the dump,
+ * restoring creates new ones
+ */
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ServerDump {
+    pub server_id: String,
+    pub settings: Option<ServerRecord>,
+    pub roles: Vec<RoleRecord>,
+    pub sessions: Vec<SessionDump>,
+    pub polls: Vec<PollDump>,
+}
+
+impl SqliteClient {
+    // every table -- READ a server's data
+    #[instrument(level = "debug", skip(self), err)]
+    pub async fn dump_server(&self, server_id: &str) -> Result<ServerDump> {
+        let mut sessions = vec![];
+        for session in self.get_sessions(server_id).await? {
+            let session_id = session
+                .session_id
+                .ok_or(AppError::storage("session has no id"))?;
+            sessions.push(SessionDump {
+                users: self.get_session_users(session_id).await?,
+                waitlist: self.get_session_waitlist(session_id).await?,
+                ownership_history: self.get_ownership_history(session_id).await?,
+                comments: self.get_comments(session_id).await?,
+                session,
+            });
+        }
+
+        let mut polls = vec![];
+        for poll in sqlx::query_as!(
+            PollRecord,
+            "SELECT * FROM polls WHERE server_id=? ORDER BY poll_id",
+            server_id
+        )
+        .fetch_all(&self.client)
+        .await?
+        {
+            let availability = sqlx::query_as!(
+                AvailabilityRecord,
+                "SELECT * FROM availability WHERE poll_id=? ORDER BY user_id, slot_start",
+                poll.poll_id
+            )
+            .fetch_all(&self.client)
+            .await?;
+            polls.push(PollDump { poll, availability });
+        }
+
+        Ok(ServerDump {
+            server_id: server_id.to_string(),
+            settings: self.get_server(server_id).await?,
+            roles: self.get_roles(server_id).await?,
+            sessions,
+            polls,
+        })
+    }
+
+    /**
+     * every table -- CREATE a server's data from a dump, in one transaction.
+     * Refuses to merge into a server that already has data unless `replace` is set,
+     * which deletes the existing data first
+     */
+    #[instrument(level = "debug", skip(self, dump), fields(server_id = %dump.server_id), err)]
+    pub async fn restore_server(&self, dump: &ServerDump, replace: bool) -> Result<()> {
+        let server_id = &dump.server_id;
+        if let Some(settings) = &dump.settings {
+            validate_day_start_hour(settings.day_start_hour).map_err(AppError::validation)?;
+            validate_timezone(&settings.timezone).map_err(AppError::validation)?;
+        }
+        let mut tx = self.client.begin().await?;
+
+        let existing = sqlx::query_scalar!(
+            r#"SELECT (SELECT COUNT(*) FROM sessions WHERE server_id=?)
+            + (SELECT COUNT(*) FROM polls WHERE server_id=?)
+            + (SELECT COUNT(*) FROM roles WHERE server_id=?)
+            + (SELECT COUNT(*) FROM servers WHERE server_id=?) AS "count!: i64""#,
+            server_id,
+            server_id,
+            server_id,
+            server_id
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+        if existing > 0 && !replace {
+            return Err(AppError::conflict(format!(
+                "server {server_id} already has data, pass --replace to overwrite it"
+            )));
+        }
+
+        // users, waitlist, history, comments and availability cascade
+        sqlx::query!("DELETE FROM sessions WHERE server_id=?", server_id)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query!("DELETE FROM polls WHERE server_id=?", server_id)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query!("DELETE FROM roles WHERE server_id=?", server_id)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query!("DELETE FROM servers WHERE server_id=?", server_id)
+            .execute(&mut *tx)
+            .await?;
+
+        if let Some(settings) = &dump.settings {
+            sqlx::query!(
+                "INSERT INTO servers (server_id, day_start_hour, timezone, use_24h) VALUES (?, ?, ?, ?)",
+                server_id,
+                settings.day_start_hour,
+                settings.timezone,
+                settings.use_24h
+            )
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        for role in &dump.roles {
+            sqlx::query!(
+                "INSERT INTO roles (server_id, user_id, role) VALUES (?, ?, ?)",
+                server_id,
+                role.user_id,
+                role.role
+            )
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        for s in &dump.sessions {
+            let session = &s.session;
+            let session_id = sqlx::query_scalar!(
+                "INSERT INTO sessions (server_id, title, start_time, end_time, owner, game, max_participants, locked, description)
+                VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?) RETURNING session_id",
+                server_id,
+                session.title,
+                session.start_time,
+                session.end_time,
+                session.owner,
+                session.game,
+                session.max_participants,
+                session.locked,
+                session.description
+            )
+            .fetch_one(&mut *tx)
+            .await?;
+
+            for user in &s.users {
+                sqlx::query!(
+                    "INSERT INTO users (user_id, session_id, user_photo, joined_at, status, eta) VALUES (?, ?, ?, ?, ?, ?)",
+                    user.user_id,
+                    session_id,
+                    user.user_photo,
+                    user.joined_at,
+                    user.status,
+                    user.eta
+                )
+                .execute(&mut *tx)
+                .await?;
+            }
+            // waitlist ids keep the order people joined in
+            for user in &s.waitlist {
+                sqlx::query!(
+                    "INSERT INTO waitlist (session_id, user_id, user_photo) VALUES (?, ?, ?)",
+                    session_id,
+                    user.user_id,
+                    user.user_photo
+                )
+                .execute(&mut *tx)
+                .await?;
+            }
+            for change in &s.ownership_history {
+                sqlx::query!(
+                    "INSERT INTO ownership_history (session_id, from_owner, to_owner, reason, changed_at) VALUES (?, ?, ?, ?, ?)",
+                    session_id,
+                    change.from_owner,
+                    change.to_owner,
+                    change.reason,
+                    change.changed_at
+                )
+                .execute(&mut *tx)
+                .await?;
+            }
+            for comment in &s.comments {
+                sqlx::query!(
+                    "INSERT INTO comments (session_id, user_id, body, created_at) VALUES (?, ?, ?, ?)",
+                    session_id,
+                    comment.user_id,
+                    comment.body,
+                    comment.created_at
+                )
+                .execute(&mut *tx)
+                .await?;
+            }
+        }
+
+        for p in &dump.polls {
+            let poll = &p.poll;
+            let poll_id = sqlx::query_scalar!(
+                "INSERT INTO polls (server_id, title, owner, start_time, end_time) VALUES (?, ?, ?, ?, ?) RETURNING poll_id",
+                server_id,
+                poll.title,
+                poll.owner,
+                poll.start_time,
+                poll.end_time
+            )
+            .fetch_one(&mut *tx)
+            .await?;
+
+            for slot in &p.availability {
+                sqlx::query!(
+                    "INSERT INTO availability (poll_id, user_id, slot_start) VALUES (?, ?, ?)",
+                    poll_id,
+                    slot.user_id,
+                    slot.slot_start
+                )
+                .execute(&mut *tx)
+                .await?;
+            }
+        }
+
+        tx.commit().await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::dao::sqlite_util::{SessionDetails, SqliteClient};
+    use chrono::DateTime;
+    use sqlx::sqlite::SqlitePoolOptions;
+
+    const SERVER_ID: &str = "server_id";
+
+    async fn setup_client() -> SqliteClient {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        sqlx::migrate!().run(&pool).await.unwrap();
+        SqliteClient::from_pool(pool).await
+    }
+
+    #[tokio::test]
+    async fn test_dump_round_trip() {
+        let client = setup_client().await;
+        client
+            .upsert_server(SERVER_ID, 8, "Europe/Berlin", false)
+            .await
+            .unwrap();
+        client
+            .upsert_role(SERVER_ID, "alice", "moderator")
+            .await
+            .unwrap();
+        let id = client
+            .create_session(
+                SERVER_ID,
+                "owner",
+                &SessionDetails {
+                    title: "title".to_string(),
+                    start_time: "1996-12-19T16:00:00Z".to_string(),
+                    end_time: "1996-12-19T18:00:00Z".to_string(),
+                    max_participants: Some(1),
+                    description: Some("bring **mics**".to_string()),
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap()
+            .session_id
+            .unwrap();
+        client
+            .add_session_user("owner", id, "photo", "going", None)
+            .await
+            .unwrap();
+        client
+            .add_session_user("alice", id, "photo", "going", None)
+            .await
+            .unwrap();
+        client.create_comment(id, "owner", "gg").await.unwrap();
+        let poll = client
+            .create_poll(
+                SERVER_ID,
+                "poll",
+                "owner",
+                "1996-12-19T00:00:00Z",
+                "1996-12-20T00:00:00Z",
+            )
+            .await
+            .unwrap();
+        client
+            .add_availability(
+                poll.poll_id.unwrap(),
+                "alice",
+                DateTime::parse_from_rfc3339("1996-12-19T16:00:00Z")
+                    .unwrap()
+                    .to_utc(),
+            )
+            .await
+            .unwrap();
+
+        let dump = client.dump_server(SERVER_ID).await.unwrap();
+        let json = serde_json::to_string(&dump).unwrap();
+
+        // existing data is only overwritten on request
+        assert!(client.restore_server(&dump, false).await.is_err());
+        client
+            .restore_server(&serde_json::from_str(&json).unwrap(), true)
+            .await
+            .unwrap();
+
+        let restored = client.dump_server(SERVER_ID).await.unwrap();
+        assert_eq!(1, restored.sessions.len());
+        assert_eq!(1, restored.sessions[0].users.len());
+        assert_eq!(
+            Some("bring **mics**"),
+            restored.sessions[0].session.description.as_deref()
+        );
+        assert_eq!("alice", restored.sessions[0].waitlist[0].user_id);
+        assert_eq!("gg", restored.sessions[0].comments[0].body);
+        assert_eq!(1, restored.polls[0].availability.len());
+        assert_eq!("moderator", restored.roles[0].role);
+        assert_eq!(8, restored.settings.unwrap().day_start_hour);
+    }
+}
@@ -1 +1,3 @@
+#[cfg(feature = "ssr")]
+pub mod dump;
 pub mod sqlite_util;
@@ -1,13 +1,13 @@
-#[cfg(feature = "ssr")]
-use chrono::Utc;
-use serde::{Deserialize, Serialize};
-
 cfg_if::cfg_if! {
     if #[cfg(feature = "ssr")] {
-        use anyhow::Result;
+        use chrono::Utc;
+        use serde::{Deserialize, Serialize};
+        use crate::component::validation::{validate_day_start_hour, validate_timezone, ValidEvent};
+        use crate::error::AppError;
         use sqlx::prelude::FromRow;
-        use sqlx::{Pool, Sqlite, SqlitePool};
+        use sqlx::{Pool, Sqlite, SqliteConnection, SqlitePool};
         use chrono::DateTime;
+        use tracing::instrument;
     }
 }
 
@@ -21,14 +21,142 @@ pub struct SessionRecord {
     pub end_time: String,
     pub owner: String,
     pub game: Option<String>,
+    pub max_participants: Option<i64>,
+    pub locked: bool,
+    // markdown, rendered when the session is loaded
+    pub description: Option<String>,
 }
 
+/// The details of a session that are set when it's created and changed when it's edited
 #[cfg(feature = "ssr")]
-#[derive(Clone, FromRow, Debug)]
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SessionDetails {
+    pub title: String,
+    pub start_time: String,
+    pub end_time: String,
+    pub game: Option<String>,
+    pub max_participants: Option<i64>,
+    // markdown
+    pub description: Option<String>,
+}
+
+#[cfg(feature = "ssr")]
+impl From<&ValidEvent> for SessionDetails {
+    fn from(event: &ValidEvent) -> Self {
+        Self {
+            title: event.title.clone(),
+            start_time: event.start.to_rfc3339(),
+            end_time: event.end.to_rfc3339(),
+            game: event.game.clone(),
+            max_participants: event.max_participants.map(|m| m as i64),
+            description: event.description.clone(),
+        }
+    }
+}
+
+#[cfg(feature = "ssr")]
+impl From<&SessionRecord> for SessionDetails {
+    fn from(record: &SessionRecord) -> Self {
+        Self {
+            title: record.title.clone(),
+            start_time: record.start_time.clone(),
+            end_time: record.end_time.clone(),
+            game: record.game.clone(),
+            max_participants: record.max_participants,
+            description: record.description.clone(),
+        }
+    }
+}
+
+#[cfg(feature = "ssr")]
+#[derive(Clone, FromRow, Debug, Serialize, Deserialize)]
 pub struct UserRecord {
     pub session_id: i64,
     pub user_id: String,
     pub user_photo: String,
+    pub joined_at: Option<String>,
+    pub status: String,
+    pub eta: Option<String>,
+    pub timezone: Option<String>,
+}
+
+#[cfg(feature = "ssr")]
+#[derive(Clone, FromRow, Debug, Serialize, Deserialize)]
+pub struct WaitlistRecord {
+    pub waitlist_id: Option<i64>,
+    pub session_id: i64,
+    pub user_id: String,
+    pub user_photo: String,
+}
+
+#[cfg(feature = "ssr")]
+#[derive(Clone, FromRow, Debug, Serialize, Deserialize)]
+pub struct CommentRecord {
+    pub comment_id: Option<i64>,
+    pub session_id: i64,
+    pub user_id: String,
+    pub body: String,
+    pub created_at: String,
+}
+
+/// Outcome of asking to join a session that may be full
+#[cfg(feature = "ssr")]
+#[derive(Clone, Debug, PartialEq)]
+pub enum JoinOutcome {
+    Joined,
+    Waitlisted,
+}
+
+#[cfg(feature = "ssr")]
+#[derive(Clone, FromRow, Debug, Serialize, Deserialize)]
+pub struct OwnershipRecord {
+    pub history_id: Option<i64>,
+    pub session_id: i64,
+    pub from_owner: String,
+    pub to_owner: String,
+    pub reason: String,
+    pub changed_at: String,
+}
+
+/// Who took over when a user left a session
+#[cfg(feature = "ssr")]
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct LeaveOutcome {
+    pub promoted: Option<String>,
+    pub new_owner: Option<String>,
+}
+
+/// A session moved out of the sessions table by the retention policy
+#[cfg(feature = "ssr")]
+#[derive(Clone, FromRow, Debug, Serialize, Deserialize)]
+pub struct SessionHistoryRecord {
+    pub session_id: i64,
+    pub server_id: String,
+    pub title: String,
+    pub start_time: String,
+    pub end_time: String,
+    pub owner: String,
+    pub game: Option<String>,
+    pub max_participants: Option<i64>,
+    pub archived_at: String,
+    pub description: Option<String>,
+}
+
+/// Row counts for the metrics endpoint
+#[cfg(feature = "ssr")]
+#[derive(Clone, FromRow, Debug)]
+pub struct TotalsRecord {
+    pub sessions: i64,
+    pub users: i64,
+    pub waitlisted: i64,
+    pub archived: i64,
+}
+
+#[cfg(feature = "ssr")]
+#[derive(Clone, FromRow, Debug, Serialize, Deserialize)]
+pub struct ProfileRecord {
+    pub user_id: String,
+    pub timezone: String,
 }
 
 #[cfg(feature = "ssr")]
@@ -41,11 +169,53 @@ pub struct GamePreferenceRecord {
     pub is_selected: bool,
 }
 
+#[cfg(feature = "ssr")]
+#[derive(Clone, FromRow, Debug, Serialize, Deserialize)]
+pub struct ServerRecord {
+    pub server_id: String,
+    pub day_start_hour: i64,
+    pub timezone: String,
+    pub use_24h: bool,
+}
+
+#[cfg(feature = "ssr")]
+#[derive(Clone, FromRow, Debug, Serialize, Deserialize)]
+pub struct RoleRecord {
+    pub server_id: String,
+    pub user_id: String,
+    pub role: String,
+}
+
+#[cfg(feature = "ssr")]
+#[derive(Clone, FromRow, Debug, Serialize, Deserialize)]
+pub struct PollRecord {
+    pub poll_id: Option<i64>,
+    pub server_id: String,
+    pub title: String,
+    pub owner: String,
+    pub start_time: String,
+    pub end_time: String,
+}
+
+#[cfg(feature = "ssr")]
+#[derive(Clone, FromRow, Debug)]
+pub struct SlotCountRecord {
+    pub slot_start: String,
+    pub count: i64,
+}
+
 #[cfg(feature = "ssr")]
 pub struct SqliteClient {
-    client: Pool<Sqlite>,
+    pub(super) client: Pool<Sqlite>,
 }
 
+#[cfg(feature = "ssr")]
+type Result<T> = std::result::Result<T, AppError>;
+
+// target of the query spans, which the metrics endpoint times
+#[cfg(feature = "ssr")]
+pub const QUERY_TARGET: &str = module_path!();
+
 #[cfg(feature = "ssr")]
 impl SqliteClient {
     pub async fn new(db_url: &str) -> Self {
@@ -59,32 +229,33 @@ impl SqliteClient {
     }
 
     // session table -- CREATE
+    #[instrument(level = "debug", skip(self), err)]
     pub async fn create_session(
         &self,
         server_id: &str,
-        title: &str,
-        start_time: &str,
-        end_time: &str,
         owner: &str,
-        game: Option<String>,
+        details: &SessionDetails,
     ) -> Result<SessionRecord> {
         let record = sqlx::query_as!(SessionRecord,
-            "INSERT INTO sessions (server_id, title, start_time, end_time, owner, game) VALUES (?, ?, ?, ?, ?, ?) RETURNING *",
+            "INSERT INTO sessions (server_id, title, start_time, end_time, owner, game, max_participants, description) VALUES (?, ?, ?, ?, ?, ?, ?, ?) RETURNING *",
             server_id,
-            title,
-            start_time,
-            end_time,
+            details.title,
+            details.start_time,
+            details.end_time,
             owner,
-            game
+            details.game,
+            details.max_participants,
+            details.description
         ).fetch_optional(&self.client).await;
 
         match record {
-            Ok(o) => Ok(o.unwrap()),
+            Ok(o) => o.ok_or(AppError::storage("session was not created")),
             Err(e) => Err(e.into()),
         }
     }
 
     // session table -- READ multiple
+    #[instrument(level = "debug", skip(self), err)]
     pub async fn get_sessions(&self, server_id: &str) -> Result<Vec<SessionRecord>> {
         Ok(sqlx::query_as!(
             SessionRecord,
@@ -95,7 +266,13 @@ impl SqliteClient {
         .await?)
     }
 
-    // session table -- read, only for one day
+    /**
+     * session table -- READ every session intersecting the window [start_time, end_time).
+     * Sessions that start before the window or end after it are included, so long sessions
+     * show up on every day they touch. Timestamps are compared with julianday() because
+     * stored times may carry different utc offsets.
+     */
+    #[instrument(level = "debug", skip(self), err)]
     pub async fn get_sessions_in_range(
         &self,
         server_id: &str,
@@ -106,16 +283,86 @@ impl SqliteClient {
         let end = end_time.to_rfc3339();
         Ok(sqlx::query_as!(
             SessionRecord,
-            "SELECT * FROM sessions WHERE server_id=? AND start_time BETWEEN ? AND ?",
+            "SELECT * FROM sessions WHERE server_id=? AND julianday(start_time) < julianday(?) AND julianday(end_time) > julianday(?)",
+            server_id,
+            end,
+            start
+        )
+        .fetch_all(&self.client)
+        .await?)
+    }
+
+    /**
+     * session and users tables -- READ the sessions on a server the user attends that overlap
+     * [start_time, end_time). Users that aren't going don't count
+     */
+    #[instrument(level = "debug", skip(self), err)]
+    pub async fn get_user_sessions_overlapping(
+        &self,
+        server_id: &str,
+        user_id: &str,
+        start_time: DateTime<Utc>,
+        end_time: DateTime<Utc>,
+    ) -> Result<Vec<SessionRecord>> {
+        let start = start_time.to_rfc3339();
+        let end = end_time.to_rfc3339();
+        Ok(sqlx::query_as!(
+            SessionRecord,
+            r#"SELECT sessions.* FROM sessions
+            JOIN users ON users.session_id = sessions.session_id
+            WHERE sessions.server_id=? AND users.user_id=? AND users.status != 'not_going'
+            AND julianday(sessions.start_time) < julianday(?) AND julianday(sessions.end_time) > julianday(?)
+            ORDER BY julianday(sessions.start_time)"#,
             server_id,
-            start,
-            end
+            user_id,
+            end,
+            start
+        )
+        .fetch_all(&self.client)
+        .await?)
+    }
+
+    /**
+     * session and users tables -- READ the sessions on every server the user attends that
+     * haven't ended by `after`, soonest first. Users that aren't going don't count
+     */
+    #[instrument(level = "debug", skip(self), err)]
+    pub async fn get_user_upcoming_sessions(
+        &self,
+        user_id: &str,
+        after: DateTime<Utc>,
+    ) -> Result<Vec<SessionRecord>> {
+        let after = after.to_rfc3339();
+        Ok(sqlx::query_as!(
+            SessionRecord,
+            r#"SELECT sessions.* FROM sessions
+            JOIN users ON users.session_id = sessions.session_id
+            WHERE users.user_id=? AND users.status != 'not_going'
+            AND julianday(sessions.end_time) > julianday(?)
+            ORDER BY julianday(sessions.start_time), sessions.session_id"#,
+            user_id,
+            after
+        )
+        .fetch_all(&self.client)
+        .await?)
+    }
+
+    // session and servers tables -- READ every known server id
+    #[instrument(level = "debug", skip(self), err)]
+    pub async fn get_server_ids(&self) -> Result<Vec<String>> {
+        Ok(sqlx::query_scalar!(
+            r#"SELECT server_id AS "server_id!" FROM sessions
+            UNION SELECT server_id FROM servers
+            UNION SELECT server_id FROM polls
+            UNION SELECT server_id FROM roles
+            ORDER BY 1"#
         )
         .fetch_all(&self.client)
         .await?)
     }
 
     // session table -- READ one
+    #[instrument(level = "debug", skip(self), err)]
     pub async fn get_session(&self, session_id: i64) -> Result<Option<SessionRecord>> {
         Ok(sqlx::query_as!(
             SessionRecord,
@@ -126,7 +373,44 @@ impl SqliteClient {
         .await?)
     }
 
+    // session table -- UPDATE details
+    #[instrument(level = "debug", skip(self), err)]
+    pub async fn update_session(
+        &self,
+        session_id: i64,
+        details: &SessionDetails,
+    ) -> Result<Option<SessionRecord>> {
+        Ok(sqlx::query_as!(
+            SessionRecord,
+            "UPDATE sessions SET title=?, start_time=?, end_time=?, game=?, max_participants=?, description=? WHERE session_id=? RETURNING *",
+            details.title,
+            details.start_time,
+            details.end_time,
+            details.game,
+            details.max_participants,
+            details.description,
+            session_id
+        )
+        .fetch_optional(&self.client)
+        .await?)
+    }
+
+    // session table -- UPDATE locked
+    #[instrument(level = "debug", skip(self), err)]
+    pub async fn set_session_locked(&self, session_id: i64, locked: bool) -> Result<()> {
+        let _ = sqlx::query!(
+            "UPDATE sessions SET locked=? WHERE session_id=?",
+            locked,
+            session_id
+        )
+        .execute(&self.client)
+        .await?;
+
+        Ok(())
+    }
+
     // session table -- DELETE
+    #[instrument(level = "debug", skip(self), err)]
     pub async fn delete_session(&self, session_id: i64) -> Result<()> {
         let _ = sqlx::query!("DELETE FROM sessions WHERE session_id=?", session_id)
             .execute(&self.client)
@@ -135,18 +419,37 @@ impl SqliteClient {
         Ok(())
     }
 
-    // user table -- CREATE
+    // session table -- DELETE every session that ended before the cutoff, returns how many
+    #[instrument(level = "debug", skip(self), err)]
+    pub async fn delete_sessions_ended_before(&self, cutoff: DateTime<Utc>) -> Result<u64> {
+        let cutoff = cutoff.to_rfc3339();
+        let res = sqlx::query!(
+            "DELETE FROM sessions WHERE julianday(end_time) < julianday(?)",
+            cutoff
+        )
+        .execute(&self.client)
+        .await?;
+
+        Ok(res.rows_affected())
+    }
+
+    // user table -- CREATE, ignoring the session's capacity
+    #[instrument(level = "debug", skip(self), err)]
     pub async fn create_session_user(
         &self,
         user_id: &str,
         session_id: i64,
         user_photo: &str,
+        status: &str,
     ) -> Result<()> {
+        let joined_at = Utc::now().to_rfc3339();
         let _ = sqlx::query!(
-            "INSERT INTO users (user_id, session_id, user_photo) VALUES (?, ?, ?)",
+            "INSERT INTO users (user_id, session_id, user_photo, joined_at, status) VALUES (?, ?, ?, ?, ?)",
             user_id,
             session_id,
-            user_photo
+            user_photo,
+            joined_at,
+            status
         )
         .execute(&self.client)
         .await?;
@@ -154,18 +457,107 @@ impl SqliteClient {
         Ok(())
     }
 
-    // user table -- READ
+    /**
+     * user table -- CREATE if the session has room, otherwise waitlist table -- CREATE.
+     * Users who are not going don't take a spot. Fails with not found if there's no session.
+     * The capacity check and the insert are one statement, so concurrent joins can't overfill.
+     */
+    #[instrument(level = "debug", skip(self), err)]
+    pub async fn add_session_user(
+        &self,
+        user_id: &str,
+        session_id: i64,
+        user_photo: &str,
+        status: &str,
+        eta: Option<String>,
+    ) -> Result<JoinOutcome> {
+        let joined_at = Utc::now().to_rfc3339();
+        let res = sqlx::query!(
+            "INSERT INTO users (user_id, session_id, user_photo, joined_at, status, eta)
+            SELECT ?, ?, ?, ?, ?, ? FROM sessions
+            WHERE session_id=? AND (? = 'not_going' OR max_participants IS NULL OR max_participants > (SELECT COUNT(*) FROM users WHERE session_id=? AND status != 'not_going'))",
+            user_id,
+            session_id,
+            user_photo,
+            joined_at,
+            status,
+            eta,
+            session_id,
+            status,
+            session_id
+        )
+        .execute(&self.client)
+        .await?;
+        if res.rows_affected() == 1 {
+            return Ok(JoinOutcome::Joined);
+        }
+        if self.get_session(session_id).await?.is_none() {
+            return Err(AppError::not_found("session"));
+        }
+
+        let _ = sqlx::query!(
+            "INSERT OR IGNORE INTO waitlist (session_id, user_id, user_photo) VALUES (?, ?, ?)",
+            session_id,
+            user_id,
+            user_photo
+        )
+        .execute(&self.client)
+        .await?;
+
+        Ok(JoinOutcome::Waitlisted)
+    }
+
+    // user table -- READ, with each user's profile timezone if they set one
+    #[instrument(level = "debug", skip(self), err)]
     pub async fn get_session_users(&self, session_id: i64) -> Result<Vec<UserRecord>> {
         Ok(sqlx::query_as!(
             UserRecord,
-            "SELECT * FROM users WHERE session_id=?",
+            r#"SELECT users.session_id, users.user_id, users.user_photo, users.joined_at, users.status, users.eta, profiles.timezone AS "timezone?"
+            FROM users LEFT JOIN profiles ON users.user_id = profiles.user_id
+            WHERE users.session_id=?
+            ORDER BY users.joined_at, users.rowid"#,
             session_id
         )
         .fetch_all(&self.client)
         .await?)
     }
 
+    /**
+     * user table -- UPDATE a participant's RSVP.
+     * Coming back from "not going" needs a free spot, so this returns false if the user is
+     * not a participant or the session is full.
+     */
+    #[instrument(level = "debug", skip(self), err)]
+    pub async fn update_session_user_status(
+        &self,
+        session_id: i64,
+        user_id: &str,
+        status: &str,
+        eta: Option<String>,
+    ) -> Result<bool> {
+        let res = sqlx::query!(
+            "UPDATE users SET status=?, eta=? WHERE session_id=? AND user_id=? AND (
+                ? = 'not_going' OR status != 'not_going'
+                OR (SELECT max_participants FROM sessions WHERE session_id=?) IS NULL
+                OR (SELECT max_participants FROM sessions WHERE session_id=?) > (SELECT COUNT(*) FROM users WHERE session_id=? AND status != 'not_going')
+            )",
+            status,
+            eta,
+            session_id,
+            user_id,
+            status,
+            session_id,
+            session_id,
+            session_id
+        )
+        .execute(&self.client)
+        .await?;
+
+        Ok(res.rows_affected() == 1)
+    }
+
     // user table -- DELETE
+    #[instrument(level = "debug", skip(self), err)]
     pub async fn delete_session_user(&self, session_id: i64, user_id: &str) -> Result<()> {
         let _ = sqlx::query!(
             "DELETE FROM users WHERE session_id=? AND user_id=?",
@@ -177,4 +569,1554 @@ impl SqliteClient {
 
         Ok(())
     }
+
+    // comments table -- CREATE
+    #[instrument(level = "debug", skip(self, body), err)]
+    pub async fn create_comment(
+        &self,
+        session_id: i64,
+        user_id: &str,
+        body: &str,
+    ) -> Result<CommentRecord> {
+        let created_at = Utc::now().to_rfc3339();
+        Ok(sqlx::query_as!(
+            CommentRecord,
+            "INSERT INTO comments (session_id, user_id, body, created_at) VALUES (?, ?, ?, ?) RETURNING *",
+            session_id,
+            user_id,
+            body,
+            created_at
+        )
+        .fetch_one(&self.client)
+        .await?)
+    }
+
+    // comments table -- READ a session's thread, oldest first
+    #[instrument(level = "debug", skip(self), err)]
+    pub async fn get_comments(&self, session_id: i64) -> Result<Vec<CommentRecord>> {
+        Ok(sqlx::query_as!(
+            CommentRecord,
+            "SELECT * FROM comments WHERE session_id=? ORDER BY comment_id",
+            session_id
+        )
+        .fetch_all(&self.client)
+        .await?)
+    }
+
+    // comments table -- READ one
+    #[instrument(level = "debug", skip(self), err)]
+    pub async fn get_comment(&self, comment_id: i64) -> Result<Option<CommentRecord>> {
+        Ok(sqlx::query_as!(
+            CommentRecord,
+            "SELECT * FROM comments WHERE comment_id=?",
+            comment_id
+        )
+        .fetch_optional(&self.client)
+        .await?)
+    }
+
+    // comments table -- READ how many a session has
+    #[instrument(level = "debug", skip(self), err)]
+    pub async fn get_comment_count(&self, session_id: i64) -> Result<i64> {
+        Ok(sqlx::query_scalar!(
+            r#"SELECT COUNT(*) AS "count!: i64" FROM comments WHERE session_id=?"#,
+            session_id
+        )
+        .fetch_one(&self.client)
+        .await?)
+    }
+
+    // comments table -- DELETE
+    #[instrument(level = "debug", skip(self), err)]
+    pub async fn delete_comment(&self, comment_id: i64) -> Result<()> {
+        let _ = sqlx::query!("DELETE FROM comments WHERE comment_id=?", comment_id)
+            .execute(&self.client)
+            .await?;
+
+        Ok(())
+    }
+
+    // waitlist table -- READ, first in line first
+    #[instrument(level = "debug", skip(self), err)]
+    pub async fn get_session_waitlist(&self, session_id: i64) -> Result<Vec<WaitlistRecord>> {
+        Ok(sqlx::query_as!(
+            WaitlistRecord,
+            "SELECT * FROM waitlist WHERE session_id=? ORDER BY waitlist_id",
+            session_id
+        )
+        .fetch_all(&self.client)
+        .await?)
+    }
+
+    // waitlist table -- DELETE
+    #[instrument(level = "debug", skip(self), err)]
+    pub async fn delete_waitlist_user(&self, session_id: i64, user_id: &str) -> Result<()> {
+        let _ = sqlx::query!(
+            "DELETE FROM waitlist WHERE session_id=? AND user_id=?",
+            session_id,
+            user_id
+        )
+        .execute(&self.client)
+        .await?;
+
+        Ok(())
+    }
+
+    /**
+     * Moves the first waitlisted user into the session if there is room.
+     * Returns the promoted user's id.
+     */
+    #[instrument(level = "debug", skip(self), err)]
+    pub async fn promote_waitlist(&self, session_id: i64) -> Result<Option<String>> {
+        let mut tx = self.client.begin().await?;
+        let promoted = promote_waitlist(&mut tx, session_id).await?;
+        tx.commit().await?;
+
+        Ok(promoted)
+    }
+
+    /**
+     * sessions table -- UPDATE owner, and ownership_history table -- CREATE.
+     * The new owner has to be a participant, returns false if they aren't or `from_owner` no
+     * longer owns the session.
+     */
+    #[instrument(level = "debug", skip(self), err)]
+    pub async fn transfer_ownership(
+        &self,
+        session_id: i64,
+        from_owner: &str,
+        to_owner: &str,
+        reason: &str,
+    ) -> Result<bool> {
+        let mut tx = self.client.begin().await?;
+        let transferred =
+            transfer_ownership(&mut tx, session_id, from_owner, to_owner, reason).await?;
+        tx.commit().await?;
+
+        Ok(transferred)
+    }
+
+    // ownership_history table -- READ, oldest first
+    #[instrument(level = "debug", skip(self), err)]
+    pub async fn get_ownership_history(&self, session_id: i64) -> Result<Vec<OwnershipRecord>> {
+        Ok(sqlx::query_as!(
+            OwnershipRecord,
+            "SELECT * FROM ownership_history WHERE session_id=? ORDER BY history_id",
+            session_id
+        )
+        .fetch_all(&self.client)
+        .await?)
+    }
+
+    /**
+     * user table -- READ the participant that should take over hosting: the earliest joiner,
+     * preferring anyone who is still coming over those who said they're not going
+     */
+    #[instrument(level = "debug", skip(self), err)]
+    pub async fn get_successor(&self, session_id: i64, owner: &str) -> Result<Option<String>> {
+        Ok(sqlx::query_scalar!(
+            "SELECT user_id FROM users WHERE session_id=? AND user_id != ?
+            ORDER BY status = 'not_going', joined_at, rowid LIMIT 1",
+            session_id,
+            owner
+        )
+        .fetch_optional(&self.client)
+        .await?)
+    }
+
+    /**
+     * sessions, users and comments tables -- MOVE every session that ended before the cutoff,
+     * with its participants and comment thread, into session_history, user_history and
+     * comment_history. Returns how many were archived
+     */
+    #[instrument(level = "debug", skip(self), err)]
+    pub async fn archive_sessions_ended_before(&self, cutoff: DateTime<Utc>) -> Result<u64> {
+        let cutoff = cutoff.to_rfc3339();
+        let archived_at = Utc::now().to_rfc3339();
+        let mut tx = self.client.begin().await?;
+
+        let _ = sqlx::query!(
+            "INSERT OR REPLACE INTO session_history (session_id, server_id, title, start_time, end_time, owner, game, max_participants, description, archived_at)
+            SELECT session_id, server_id, title, start_time, end_time, owner, game, max_participants, description, ? FROM sessions
+            WHERE julianday(end_time) < julianday(?)",
+            archived_at,
+            cutoff
+        )
+        .execute(&mut *tx)
+        .await?;
+        let _ = sqlx::query!(
+            "INSERT OR REPLACE INTO user_history (session_id, user_id, status, joined_at)
+            SELECT users.session_id, users.user_id, users.status, users.joined_at FROM users
+            JOIN sessions ON users.session_id = sessions.session_id
+            WHERE julianday(sessions.end_time) < julianday(?)",
+            cutoff
+        )
+        .execute(&mut *tx)
+        .await?;
+        let _ = sqlx::query!(
+            "INSERT OR REPLACE INTO comment_history (comment_id, session_id, user_id, body, created_at)
+            SELECT comments.comment_id, comments.session_id, comments.user_id, comments.body, comments.created_at FROM comments
+            JOIN sessions ON comments.session_id = sessions.session_id
+            WHERE julianday(sessions.end_time) < julianday(?)",
+            cutoff
+        )
+        .execute(&mut *tx)
+        .await?;
+        let res = sqlx::query!(
+            "DELETE FROM sessions WHERE julianday(end_time) < julianday(?)",
+            cutoff
+        )
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        Ok(res.rows_affected())
+    }
+
+    // session_history table -- READ multiple, oldest first
+    #[instrument(level = "debug", skip(self), err)]
+    pub async fn get_session_history(&self, server_id: &str) -> Result<Vec<SessionHistoryRecord>> {
+        Ok(sqlx::query_as!(
+            SessionHistoryRecord,
+            r#"SELECT session_id AS "session_id!", server_id, title, start_time, end_time, owner, game, max_participants, archived_at, description
+            FROM session_history WHERE server_id=? ORDER BY julianday(start_time)"#,
+            server_id
+        )
+        .fetch_all(&self.client)
+        .await?)
+    }
+
+    // user_history table -- READ the users that were in an archived session
+    #[instrument(level = "debug", skip(self), err)]
+    pub async fn get_user_history(&self, session_id: i64) -> Result<Vec<String>> {
+        Ok(sqlx::query_scalar!(
+            "SELECT user_id FROM user_history WHERE session_id=? ORDER BY joined_at",
+            session_id
+        )
+        .fetch_all(&self.client)
+        .await?)
+    }
+
+    // comment_history table -- READ an archived session's thread, oldest first
+    #[instrument(level = "debug", skip(self), err)]
+    pub async fn get_comment_history(&self, session_id: i64) -> Result<Vec<CommentRecord>> {
+        Ok(sqlx::query_as!(
+            CommentRecord,
+            r#"SELECT comment_id AS "comment_id?", session_id, user_id, body, created_at
+            FROM comment_history WHERE session_id=? ORDER BY comment_id"#,
+            session_id
+        )
+        .fetch_all(&self.client)
+        .await?)
+    }
+
+    /**
+     * user and waitlist tables -- DELETE a user from a session. The first in line takes the freed
+     * spot, and if the user owned the session the earliest joiner takes over hosting.
+     * All in one transaction, so a failure can't leave a free spot or the session without a host
+     */
+    #[instrument(level = "debug", skip(self), err)]
+    pub async fn remove_participant(&self, session_id: i64, user_id: &str) -> Result<LeaveOutcome> {
+        let mut tx = self.client.begin().await?;
+        let is_owner =
+            sqlx::query_scalar!("SELECT owner FROM sessions WHERE session_id=?", session_id)
+                .fetch_optional(&mut *tx)
+                .await?
+                .is_some_and(|owner| owner == user_id);
+
+        let _ = sqlx::query!(
+            "DELETE FROM waitlist WHERE session_id=? AND user_id=?",
+            session_id,
+            user_id
+        )
+        .execute(&mut *tx)
+        .await?;
+        let _ = sqlx::query!(
+            "DELETE FROM users WHERE session_id=? AND user_id=?",
+            session_id,
+            user_id
+        )
+        .execute(&mut *tx)
+        .await?;
+        let promoted = promote_waitlist(&mut tx, session_id).await?;
+
+        let mut new_owner = None;
+        if is_owner {
+            let successor = sqlx::query_scalar!(
+                "SELECT user_id FROM users WHERE session_id=? AND user_id != ?
+                ORDER BY status = 'not_going', joined_at, rowid LIMIT 1",
+                session_id,
+                user_id
+            )
+            .fetch_optional(&mut *tx)
+            .await?;
+            if let Some(successor) = successor {
+                if transfer_ownership(&mut tx, session_id, user_id, &successor, "owner_left")
+                    .await?
+                {
+                    new_owner = Some(successor);
+                }
+            }
+        }
+        tx.commit().await?;
+
+        Ok(LeaveOutcome {
+            promoted,
+            new_owner,
+        })
+    }
+
+    // profile table -- READ one
+    #[instrument(level = "debug", skip(self), err)]
+    pub async fn get_profile(&self, user_id: &str) -> Result<Option<ProfileRecord>> {
+        Ok(sqlx::query_as!(
+            ProfileRecord,
+            "SELECT * FROM profiles WHERE user_id=?",
+            user_id
+        )
+        .fetch_optional(&self.client)
+        .await?)
+    }
+
+    // profile table -- CREATE or UPDATE
+    #[instrument(level = "debug", skip(self), err)]
+    pub async fn upsert_profile(&self, user_id: &str, timezone: &str) -> Result<ProfileRecord> {
+        Ok(sqlx::query_as!(
+            ProfileRecord,
+            "INSERT INTO profiles (user_id, timezone) VALUES (?, ?)
+            ON CONFLICT (user_id) DO UPDATE SET timezone=excluded.timezone
+            RETURNING *",
+            user_id,
+            timezone
+        )
+        .fetch_one(&self.client)
+        .await?)
+    }
+
+    // poll table -- CREATE
+    #[instrument(level = "debug", skip(self), err)]
+    pub async fn create_poll(
+        &self,
+        server_id: &str,
+        title: &str,
+        owner: &str,
+        start_time: &str,
+        end_time: &str,
+    ) -> Result<PollRecord> {
+        Ok(sqlx::query_as!(
+            PollRecord,
+            "INSERT INTO polls (server_id, title, owner, start_time, end_time) VALUES (?, ?, ?, ?, ?) RETURNING *",
+            server_id,
+            title,
+            owner,
+            start_time,
+            end_time
+        )
+        .fetch_one(&self.client)
+        .await?)
+    }
+
+    // poll table -- READ every poll intersecting the window [start_time, end_time)
+    #[instrument(level = "debug", skip(self), err)]
+    pub async fn get_polls_in_range(
+        &self,
+        server_id: &str,
+        start_time: DateTime<Utc>,
+        end_time: DateTime<Utc>,
+    ) -> Result<Vec<PollRecord>> {
+        let start = start_time.to_rfc3339();
+        let end = end_time.to_rfc3339();
+        Ok(sqlx::query_as!(
+            PollRecord,
+            "SELECT * FROM polls WHERE server_id=? AND julianday(start_time) < julianday(?) AND julianday(end_time) > julianday(?)",
+            server_id,
+            end,
+            start
+        )
+        .fetch_all(&self.client)
+        .await?)
+    }
+
+    // poll table -- READ one
+    #[instrument(level = "debug", skip(self), err)]
+    pub async fn get_poll(&self, poll_id: i64) -> Result<Option<PollRecord>> {
+        Ok(
+            sqlx::query_as!(PollRecord, "SELECT * FROM polls WHERE poll_id=?", poll_id)
+                .fetch_optional(&self.client)
+                .await?,
+        )
+    }
+
+    // poll table -- DELETE, availability is removed by cascade
+    #[instrument(level = "debug", skip(self), err)]
+    pub async fn delete_poll(&self, poll_id: i64) -> Result<()> {
+        let _ = sqlx::query!("DELETE FROM polls WHERE poll_id=?", poll_id)
+            .execute(&self.client)
+            .await?;
+
+        Ok(())
+    }
+
+    // availability table -- CREATE. Slots are hour long and keyed by their utc start
+    #[instrument(level = "debug", skip(self), err)]
+    pub async fn add_availability(
+        &self,
+        poll_id: i64,
+        user_id: &str,
+        slot_start: DateTime<Utc>,
+    ) -> Result<()> {
+        let slot = slot_start.to_rfc3339();
+        let _ = sqlx::query!(
+            "INSERT OR IGNORE INTO availability (poll_id, user_id, slot_start) VALUES (?, ?, ?)",
+            poll_id,
+            user_id,
+            slot
+        )
+        .execute(&self.client)
+        .await?;
+
+        Ok(())
+    }
+
+    // availability table -- DELETE
+    #[instrument(level = "debug", skip(self), err)]
+    pub async fn delete_availability(
+        &self,
+        poll_id: i64,
+        user_id: &str,
+        slot_start: DateTime<Utc>,
+    ) -> Result<()> {
+        let slot = slot_start.to_rfc3339();
+        let _ = sqlx::query!(
+            "DELETE FROM availability WHERE poll_id=? AND user_id=? AND slot_start=?",
+            poll_id,
+            user_id,
+            slot
+        )
+        .execute(&self.client)
+        .await?;
+
+        Ok(())
+    }
+
+    // availability table -- READ number of available users per slot
+    #[instrument(level = "debug", skip(self), err)]
+    pub async fn get_poll_heatmap(&self, poll_id: i64) -> Result<Vec<SlotCountRecord>> {
+        Ok(sqlx::query_as!(
+            SlotCountRecord,
+            r#"SELECT slot_start, COUNT(*) AS "count!: i64" FROM availability WHERE poll_id=? GROUP BY slot_start"#,
+            poll_id
+        )
+        .fetch_all(&self.client)
+        .await?)
+    }
+
+    // availability table -- READ number of users who answered a poll
+    #[instrument(level = "debug", skip(self), err)]
+    pub async fn get_poll_respondent_count(&self, poll_id: i64) -> Result<i64> {
+        Ok(sqlx::query_scalar!(
+            r#"SELECT COUNT(DISTINCT user_id) AS "count!: i64" FROM availability WHERE poll_id=?"#,
+            poll_id
+        )
+        .fetch_one(&self.client)
+        .await?)
+    }
+
+    // availability table -- READ one user's slots
+    #[instrument(level = "debug", skip(self), err)]
+    pub async fn get_user_availability(&self, poll_id: i64, user_id: &str) -> Result<Vec<String>> {
+        Ok(sqlx::query_scalar!(
+            "SELECT slot_start FROM availability WHERE poll_id=? AND user_id=?",
+            poll_id,
+            user_id
+        )
+        .fetch_all(&self.client)
+        .await?)
+    }
+
+    // server table -- READ one. Servers without a row use the default settings
+    #[instrument(level = "debug", skip(self), err)]
+    pub async fn get_server(&self, server_id: &str) -> Result<Option<ServerRecord>> {
+        Ok(sqlx::query_as!(
+            ServerRecord,
+            "SELECT * FROM servers WHERE server_id=?",
+            server_id
+        )
+        .fetch_optional(&self.client)
+        .await?)
+    }
+
+    // server table -- CREATE or UPDATE
+    #[instrument(level = "debug", skip(self), err)]
+    pub async fn upsert_server(
+        &self,
+        server_id: &str,
+        day_start_hour: i64,
+        timezone: &str,
+        use_24h: bool,
+    ) -> Result<ServerRecord> {
+        validate_day_start_hour(day_start_hour).map_err(AppError::validation)?;
+        validate_timezone(timezone).map_err(AppError::validation)?;
+        Ok(sqlx::query_as!(
+            ServerRecord,
+            "INSERT INTO servers (server_id, day_start_hour, timezone, use_24h) VALUES (?, ?, ?, ?)
+            ON CONFLICT (server_id) DO UPDATE SET day_start_hour=excluded.day_start_hour, timezone=excluded.timezone, use_24h=excluded.use_24h
+            RETURNING *",
+            server_id,
+            day_start_hour,
+            timezone,
+            use_24h
+        )
+        .fetch_one(&self.client)
+        .await?)
+    }
+
+    // roles table -- READ one
+    #[instrument(level = "debug", skip(self), err)]
+    pub async fn get_role(&self, server_id: &str, user_id: &str) -> Result<Option<RoleRecord>> {
+        Ok(sqlx::query_as!(
+            RoleRecord,
+            "SELECT * FROM roles WHERE server_id=? AND user_id=?",
+            server_id,
+            user_id
+        )
+        .fetch_optional(&self.client)
+        .await?)
+    }
+
+    // roles table -- READ multiple
+    #[instrument(level = "debug", skip(self), err)]
+    pub async fn get_roles(&self, server_id: &str) -> Result<Vec<RoleRecord>> {
+        Ok(sqlx::query_as!(
+            RoleRecord,
+            "SELECT * FROM roles WHERE server_id=? ORDER BY user_id",
+            server_id
+        )
+        .fetch_all(&self.client)
+        .await?)
+    }
+
+    // roles table -- CREATE or UPDATE
+    #[instrument(level = "debug", skip(self), err)]
+    pub async fn upsert_role(
+        &self,
+        server_id: &str,
+        user_id: &str,
+        role: &str,
+    ) -> Result<RoleRecord> {
+        Ok(sqlx::query_as!(
+            RoleRecord,
+            "INSERT INTO roles (server_id, user_id, role) VALUES (?, ?, ?)
+            ON CONFLICT (server_id, user_id) DO UPDATE SET role=excluded.role
+            RETURNING *",
+            server_id,
+            user_id,
+            role
+        )
+        .fetch_one(&self.client)
+        .await?)
+    }
+
+    // roles table -- DELETE, the user falls back to a member
+    #[instrument(level = "debug", skip(self), err)]
+    pub async fn delete_role(&self, server_id: &str, user_id: &str) -> Result<()> {
+        let _ = sqlx::query!(
+            "DELETE FROM roles WHERE server_id=? AND user_id=?",
+            server_id,
+            user_id
+        )
+        .execute(&self.client)
+        .await?;
+
+        Ok(())
+    }
+
+    // every table -- READ row counts
+    #[instrument(level = "debug", skip(self), err)]
+    pub async fn get_totals(&self) -> Result<TotalsRecord> {
+        Ok(sqlx::query_as!(
+            TotalsRecord,
+            r#"SELECT (SELECT COUNT(*) FROM sessions) AS "sessions!: i64",
+            (SELECT COUNT(*) FROM users) AS "users!: i64",
+            (SELECT COUNT(*) FROM waitlist) AS "waitlisted!: i64",
+            (SELECT COUNT(*) FROM session_history) AS "archived!: i64""#
+        )
+        .fetch_one(&self.client)
+        .await?)
+    }
+
+    // _sqlx_migrations table -- READ versions that ran successfully
+    #[instrument(level = "debug", skip(self), err)]
+    pub async fn get_applied_migrations(&self) -> Result<Vec<i64>> {
+        Ok(
+            sqlx::query_scalar("SELECT version FROM _sqlx_migrations WHERE success")
+                .fetch_all(&self.client)
+                .await?,
+        )
+    }
+}
+
+// waitlist and user tables -- `SqliteClient::promote_waitlist` inside a caller's transaction
+#[cfg(feature = "ssr")]
+async fn promote_waitlist(conn: &mut SqliteConnection, session_id: i64) -> Result<Option<String>> {
+    let next = sqlx::query_as!(
+        WaitlistRecord,
+        "SELECT * FROM waitlist WHERE session_id=? ORDER BY waitlist_id LIMIT 1",
+        session_id
+    )
+    .fetch_optional(&mut *conn)
+    .await?;
+    let Some(next) = next else {
+        return Ok(None);
+    };
+
+    let joined_at = Utc::now().to_rfc3339();
+    let res = sqlx::query!(
+        "INSERT INTO users (user_id, session_id, user_photo, joined_at)
+        SELECT ?, ?, ?, ? FROM sessions
+        WHERE session_id=? AND (max_participants IS NULL OR max_participants > (SELECT COUNT(*) FROM users WHERE session_id=? AND status != 'not_going'))",
+        next.user_id,
+        session_id,
+        next.user_photo,
+        joined_at,
+        session_id,
+        session_id
+    )
+    .execute(&mut *conn)
+    .await?;
+    if res.rows_affected() == 0 {
+        return Ok(None);
+    }
+
+    let _ = sqlx::query!("DELETE FROM waitlist WHERE waitlist_id=?", next.waitlist_id)
+        .execute(&mut *conn)
+        .await?;
+
+    Ok(Some(next.user_id))
+}
+
+// sessions and ownership_history tables -- `SqliteClient::transfer_ownership` inside a caller's
+// transaction
+#[cfg(feature = "ssr")]
+async fn transfer_ownership(
+    conn: &mut SqliteConnection,
+    session_id: i64,
+    from_owner: &str,
+    to_owner: &str,
+    reason: &str,
+) -> Result<bool> {
+    let res = sqlx::query!(
+        "UPDATE sessions SET owner=? WHERE session_id=? AND owner=?
+        AND EXISTS (SELECT 1 FROM users WHERE session_id=? AND user_id=?)",
+        to_owner,
+        session_id,
+        from_owner,
+        session_id,
+        to_owner
+    )
+    .execute(&mut *conn)
+    .await?;
+    if res.rows_affected() == 0 {
+        return Ok(false);
+    }
+
+    let changed_at = Utc::now().to_rfc3339();
+    let _ = sqlx::query!(
+        "INSERT INTO ownership_history (session_id, from_owner, to_owner, reason, changed_at) VALUES (?, ?, ?, ?, ?)",
+        session_id,
+        from_owner,
+        to_owner,
+        reason,
+        changed_at
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(true)
+}
+
+#[cfg(all(test, feature = "ssr"))]
+mod tests {
+    use crate::{
+        dao::sqlite_util::{JoinOutcome, SessionDetails, SqliteClient},
+        error::AppError,
+    };
+    use chrono::{DateTime, Utc};
+    use sqlx::sqlite::SqlitePoolOptions;
+
+    const SERVER_ID: &str = "server_id";
+
+    // in-memory databases are per connection, so the pool is limited to one
+    async fn setup_client() -> SqliteClient {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        sqlx::migrate!().run(&pool).await.unwrap();
+        SqliteClient::from_pool(pool).await
+    }
+
+    fn time(s: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(s).unwrap().to_utc()
+    }
+
+    fn details(start: &str, end: &str) -> SessionDetails {
+        SessionDetails {
+            title: "title".to_string(),
+            start_time: start.to_string(),
+            end_time: end.to_string(),
+            ..Default::default()
+        }
+    }
+
+    async fn create(client: &SqliteClient, server_id: &str, start: &str, end: &str) -> i64 {
+        client
+            .create_session(server_id, "owner", &details(start, end))
+            .await
+            .unwrap()
+            .session_id
+            .unwrap()
+    }
+
+    async fn sessions_in_window(client: &SqliteClient) -> Vec<i64> {
+        let mut ids: Vec<i64> = client
+            .get_sessions_in_range(
+                SERVER_ID,
+                time("1996-12-19T06:00:00Z"),
+                time("1996-12-20T06:00:00Z"),
+            )
+            .await
+            .unwrap()
+            .iter()
+            .map(|s| s.session_id.unwrap())
+            .collect();
+        ids.sort();
+        ids
+    }
+
+    #[tokio::test]
+    async fn test_user_sessions_overlapping() {
+        let client = setup_client().await;
+        let evening = create(
+            &client,
+            SERVER_ID,
+            "1996-12-19T18:00:00Z",
+            "1996-12-19T21:00:00Z",
+        )
+        .await;
+        let night = create(
+            &client,
+            SERVER_ID,
+            "1996-12-19T21:00:00Z",
+            "1996-12-19T23:00:00Z",
+        )
+        .await;
+        let skipped = create(
+            &client,
+            SERVER_ID,
+            "1996-12-19T20:00:00Z",
+            "1996-12-19T22:00:00Z",
+        )
+        .await;
+        let other_server = create(
+            &client,
+            "other",
+            "1996-12-19T20:00:00Z",
+            "1996-12-19T22:00:00Z",
+        )
+        .await;
+        for id in [evening, night, other_server] {
+            client
+                .create_session_user("alice", id, "", "going")
+                .await
+                .unwrap();
+        }
+        client
+            .create_session_user("alice", skipped, "", "not_going")
+            .await
+            .unwrap();
+
+        let overlapping = |start: &str, end: &str| {
+            let client = &client;
+            let (start, end) = (time(start), time(end));
+            async move {
+                client
+                    .get_user_sessions_overlapping(SERVER_ID, "alice", start, end)
+                    .await
+                    .unwrap()
+                    .iter()
+                    .map(|s| s.session_id.unwrap())
+                    .collect::<Vec<_>>()
+            }
+        };
+        assert_eq!(
+            vec![evening, night],
+            overlapping("1996-12-19T20:30:00Z", "1996-12-19T21:30:00Z").await
+        );
+        // touching isn't overlapping
+        assert!(overlapping("1996-12-19T23:00:00Z", "1996-12-20T01:00:00Z")
+            .await
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_comments() {
+        let client = setup_client().await;
+        let id = create(
+            &client,
+            SERVER_ID,
+            "1996-12-19T16:00:00Z",
+            "1996-12-19T18:00:00Z",
+        )
+        .await;
+        let other = create(
+            &client,
+            SERVER_ID,
+            "1996-12-19T16:00:00Z",
+            "1996-12-19T18:00:00Z",
+        )
+        .await;
+
+        let first = client
+            .create_comment(id, "alice", "bringing snacks")
+            .await
+            .unwrap();
+        client
+            .create_comment(id, "bob", "running 10 min late")
+            .await
+            .unwrap();
+        client.create_comment(other, "bob", "hi").await.unwrap();
+
+        let thread = client.get_comments(id).await.unwrap();
+        assert_eq!(
+            vec!["bringing snacks", "running 10 min late"],
+            thread.iter().map(|c| c.body.as_str()).collect::<Vec<_>>()
+        );
+        assert_eq!(2, client.get_comment_count(id).await.unwrap());
+
+        let first_id = first.comment_id.unwrap();
+        client.delete_comment(first_id).await.unwrap();
+        assert!(client.get_comment(first_id).await.unwrap().is_none());
+        assert_eq!(1, client.get_comment_count(id).await.unwrap());
+
+        // comments go with their session
+        client.delete_session(id).await.unwrap();
+        assert!(client.get_comments(id).await.unwrap().is_empty());
+        assert_eq!(1, client.get_comment_count(other).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_user_upcoming_sessions() {
+        let client = setup_client().await;
+        let ended = create(
+            &client,
+            SERVER_ID,
+            "1996-12-19T10:00:00Z",
+            "1996-12-19T12:00:00Z",
+        )
+        .await;
+        let running = create(
+            &client,
+            SERVER_ID,
+            "1996-12-19T16:00:00Z",
+            "1996-12-19T20:00:00Z",
+        )
+        .await;
+        let later = create(
+            &client,
+            "other",
+            "1996-12-20T14:00:00-05:00",
+            "1996-12-20T16:00:00-05:00",
+        )
+        .await;
+        let tomorrow = create(
+            &client,
+            SERVER_ID,
+            "1996-12-20T18:00:00Z",
+            "1996-12-20T19:00:00Z",
+        )
+        .await;
+        let skipped = create(
+            &client,
+            "other",
+            "1996-12-20T20:00:00Z",
+            "1996-12-20T21:00:00Z",
+        )
+        .await;
+        let someone_else = create(
+            &client,
+            "third",
+            "1996-12-20T20:00:00Z",
+            "1996-12-20T21:00:00Z",
+        )
+        .await;
+        for id in [ended, running, later, tomorrow] {
+            client
+                .create_session_user("alice", id, "", "going")
+                .await
+                .unwrap();
+        }
+        client
+            .create_session_user("alice", skipped, "", "not_going")
+            .await
+            .unwrap();
+        client
+            .create_session_user("bob", someone_else, "", "going")
+            .await
+            .unwrap();
+
+        let upcoming: Vec<_> = client
+            .get_user_upcoming_sessions("alice", time("1996-12-19T18:00:00Z"))
+            .await
+            .unwrap()
+            .iter()
+            .map(|s| s.session_id.unwrap())
+            .collect();
+        // ordered by the actual instant, not the stored text
+        assert_eq!(vec![running, tomorrow, later], upcoming);
+    }
+
+    #[tokio::test]
+    async fn test_range_contained() {
+        let client = setup_client().await;
+        let id = create(
+            &client,
+            SERVER_ID,
+            "1996-12-19T16:00:00Z",
+            "1996-12-19T18:00:00Z",
+        )
+        .await;
+        assert_eq!(vec![id], sessions_in_window(&client).await);
+    }
+
+    #[tokio::test]
+    async fn test_range_started_previous_day() {
+        let client = setup_client().await;
+        let id = create(
+            &client,
+            SERVER_ID,
+            "1996-12-19T02:00:00Z",
+            "1996-12-19T07:00:00Z",
+        )
+        .await;
+        assert_eq!(vec![id], sessions_in_window(&client).await);
+    }
+
+    #[tokio::test]
+    async fn test_range_continues_next_day() {
+        let client = setup_client().await;
+        let id = create(
+            &client,
+            SERVER_ID,
+            "1996-12-20T05:00:00Z",
+            "1996-12-20T09:00:00Z",
+        )
+        .await;
+        assert_eq!(vec![id], sessions_in_window(&client).await);
+    }
+
+    #[tokio::test]
+    async fn test_range_spans_whole_window() {
+        let client = setup_client().await;
+        let id = create(
+            &client,
+            SERVER_ID,
+            "1996-12-18T12:00:00Z",
+            "1996-12-21T12:00:00Z",
+        )
+        .await;
+        assert_eq!(vec![id], sessions_in_window(&client).await);
+    }
+
+    #[tokio::test]
+    async fn test_range_touching_edges_excluded() {
+        let client = setup_client().await;
+        create(
+            &client,
+            SERVER_ID,
+            "1996-12-19T04:00:00Z",
+            "1996-12-19T06:00:00Z",
+        )
+        .await;
+        create(
+            &client,
+            SERVER_ID,
+            "1996-12-20T06:00:00Z",
+            "1996-12-20T08:00:00Z",
+        )
+        .await;
+        assert!(sessions_in_window(&client).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_range_mixed_offsets() {
+        let client = setup_client().await;
+        // 1996-12-20T05:30:00Z, written with a local offset
+        let id = create(
+            &client,
+            SERVER_ID,
+            "1996-12-19T21:30:00-08:00",
+            "1996-12-19T23:00:00-08:00",
+        )
+        .await;
+        assert_eq!(vec![id], sessions_in_window(&client).await);
+    }
+
+    #[tokio::test]
+    async fn test_range_other_server_excluded() {
+        let client = setup_client().await;
+        create(
+            &client,
+            "other_server",
+            "1996-12-19T16:00:00Z",
+            "1996-12-19T18:00:00Z",
+        )
+        .await;
+        assert!(sessions_in_window(&client).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_upsert_server() {
+        let client = setup_client().await;
+        assert!(client.get_server(SERVER_ID).await.unwrap().is_none());
+
+        client
+            .upsert_server(SERVER_ID, 6, "UTC", true)
+            .await
+            .unwrap();
+        let record = client
+            .upsert_server(SERVER_ID, 8, "Europe/Berlin", false)
+            .await
+            .unwrap();
+        assert_eq!(8, record.day_start_hour);
+        assert_eq!("Europe/Berlin", record.timezone);
+        assert!(!record.use_24h);
+
+        // bad settings are refused and the stored ones kept
+        assert!(client
+            .upsert_server(SERVER_ID, 24, "UTC", true)
+            .await
+            .is_err());
+        assert!(client
+            .upsert_server(SERVER_ID, -1, "UTC", true)
+            .await
+            .is_err());
+        assert!(client
+            .upsert_server(SERVER_ID, 6, "Mars/Olympus", true)
+            .await
+            .is_err());
+        let stored = client.get_server(SERVER_ID).await.unwrap().unwrap();
+        assert_eq!(8, stored.day_start_hour);
+    }
+
+    #[tokio::test]
+    async fn test_session_users_with_profile() {
+        let client = setup_client().await;
+        let id = create(
+            &client,
+            SERVER_ID,
+            "1996-12-19T16:00:00Z",
+            "1996-12-19T18:00:00Z",
+        )
+        .await;
+        client
+            .create_session_user("alice", id, "photo", "going")
+            .await
+            .unwrap();
+        client
+            .create_session_user("bob", id, "photo", "going")
+            .await
+            .unwrap();
+        client.upsert_profile("alice", "UTC").await.unwrap();
+        client
+            .upsert_profile("alice", "Europe/Berlin")
+            .await
+            .unwrap();
+
+        let mut users = client.get_session_users(id).await.unwrap();
+        users.sort_by(|a, b| a.user_id.cmp(&b.user_id));
+        assert_eq!(Some("Europe/Berlin".to_string()), users[0].timezone);
+        assert_eq!(None, users[1].timezone);
+    }
+
+    #[tokio::test]
+    async fn test_poll_heatmap() {
+        let client = setup_client().await;
+        let poll_id = client
+            .create_poll(
+                SERVER_ID,
+                "title",
+                "alice",
+                "1996-12-19T06:00:00+00:00",
+                "1996-12-21T06:00:00+00:00",
+            )
+            .await
+            .unwrap()
+            .poll_id
+            .unwrap();
+        let slot_1 = time("1996-12-19T18:00:00Z");
+        let slot_2 = time("1996-12-19T19:00:00Z");
+        client
+            .add_availability(poll_id, "alice", slot_1)
+            .await
+            .unwrap();
+        client
+            .add_availability(poll_id, "alice", slot_2)
+            .await
+            .unwrap();
+        client
+            .add_availability(poll_id, "bob", slot_2)
+            .await
+            .unwrap();
+        // adding twice is a no-op
+        client
+            .add_availability(poll_id, "bob", slot_2)
+            .await
+            .unwrap();
+
+        let mut heatmap = client.get_poll_heatmap(poll_id).await.unwrap();
+        heatmap.sort_by(|a, b| a.slot_start.cmp(&b.slot_start));
+        assert_eq!(
+            vec![1, 2],
+            heatmap.iter().map(|h| h.count).collect::<Vec<_>>()
+        );
+        assert_eq!(2, client.get_poll_respondent_count(poll_id).await.unwrap());
+
+        client
+            .delete_availability(poll_id, "bob", slot_2)
+            .await
+            .unwrap();
+        assert_eq!(1, client.get_poll_respondent_count(poll_id).await.unwrap());
+        assert!(client
+            .get_user_availability(poll_id, "bob")
+            .await
+            .unwrap()
+            .is_empty());
+
+        // deleting the poll cascades to its availability
+        client.delete_poll(poll_id).await.unwrap();
+        assert!(client.get_poll_heatmap(poll_id).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_polls_in_range() {
+        let client = setup_client().await;
+        client
+            .create_poll(
+                SERVER_ID,
+                "title",
+                "alice",
+                "1996-12-18T06:00:00+00:00",
+                "1996-12-20T06:00:00+00:00",
+            )
+            .await
+            .unwrap();
+        client
+            .create_poll(
+                SERVER_ID,
+                "title",
+                "alice",
+                "1996-12-20T06:00:00+00:00",
+                "1996-12-21T06:00:00+00:00",
+            )
+            .await
+            .unwrap();
+
+        let polls = client
+            .get_polls_in_range(
+                SERVER_ID,
+                time("1996-12-19T06:00:00Z"),
+                time("1996-12-20T06:00:00Z"),
+            )
+            .await
+            .unwrap();
+        assert_eq!(1, polls.len());
+    }
+
+    #[tokio::test]
+    async fn test_waitlist_and_promotion() {
+        let client = setup_client().await;
+        let id = client
+            .create_session(
+                SERVER_ID,
+                "alice",
+                &SessionDetails {
+                    max_participants: Some(2),
+                    ..details("1996-12-19T16:00:00Z", "1996-12-19T18:00:00Z")
+                },
+            )
+            .await
+            .unwrap()
+            .session_id
+            .unwrap();
+
+        assert_eq!(
+            JoinOutcome::Joined,
+            client
+                .add_session_user("alice", id, "photo", "going", None)
+                .await
+                .unwrap()
+        );
+        assert_eq!(
+            JoinOutcome::Joined,
+            client
+                .add_session_user("bob", id, "photo", "going", None)
+                .await
+                .unwrap()
+        );
+        assert_eq!(
+            JoinOutcome::Waitlisted,
+            client
+                .add_session_user("carol", id, "photo", "going", None)
+                .await
+                .unwrap()
+        );
+        assert_eq!(
+            JoinOutcome::Waitlisted,
+            client
+                .add_session_user("dave", id, "photo", "going", None)
+                .await
+                .unwrap()
+        );
+
+        // full sessions don't promote
+        assert_eq!(None, client.promote_waitlist(id).await.unwrap());
+
+        client.delete_session_user(id, "bob").await.unwrap();
+        assert_eq!(
+            Some("carol".to_string()),
+            client.promote_waitlist(id).await.unwrap()
+        );
+        let users: Vec<String> = client
+            .get_session_users(id)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|u| u.user_id)
+            .collect();
+        assert_eq!(vec!["alice".to_string(), "carol".to_string()], users);
+        let waitlist = client.get_session_waitlist(id).await.unwrap();
+        assert_eq!(1, waitlist.len());
+        assert_eq!("dave", waitlist[0].user_id);
+    }
+
+    #[tokio::test]
+    async fn test_no_capacity_never_waitlists() {
+        let client = setup_client().await;
+        let id = create(
+            &client,
+            SERVER_ID,
+            "1996-12-19T16:00:00Z",
+            "1996-12-19T18:00:00Z",
+        )
+        .await;
+        for user in ["alice", "bob", "carol"] {
+            assert_eq!(
+                JoinOutcome::Joined,
+                client
+                    .add_session_user(user, id, "photo", "going", None)
+                    .await
+                    .unwrap()
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_not_going_frees_a_spot() {
+        let client = setup_client().await;
+        let id = client
+            .create_session(
+                SERVER_ID,
+                "alice",
+                &SessionDetails {
+                    max_participants: Some(1),
+                    ..details("1996-12-19T16:00:00Z", "1996-12-19T18:00:00Z")
+                },
+            )
+            .await
+            .unwrap()
+            .session_id
+            .unwrap();
+        client
+            .create_session_user("alice", id, "photo", "going")
+            .await
+            .unwrap();
+
+        // not going never needs a spot
+        assert_eq!(
+            JoinOutcome::Joined,
+            client
+                .add_session_user("bob", id, "photo", "not_going", None)
+                .await
+                .unwrap()
+        );
+        // but coming back does
+        assert!(!client
+            .update_session_user_status(id, "bob", "going", None)
+            .await
+            .unwrap());
+
+        assert!(client
+            .update_session_user_status(id, "alice", "not_going", None)
+            .await
+            .unwrap());
+        assert!(client
+            .update_session_user_status(
+                id,
+                "bob",
+                "late",
+                Some("1996-12-19T16:30:00+00:00".to_string())
+            )
+            .await
+            .unwrap());
+        let users = client.get_session_users(id).await.unwrap();
+        assert_eq!("not_going", users[0].status);
+        assert_eq!("late", users[1].status);
+        assert_eq!(Some("1996-12-19T16:30:00+00:00".to_string()), users[1].eta);
+    }
+
+    #[tokio::test]
+    async fn test_transfer_ownership() {
+        let client = setup_client().await;
+        let id = create(
+            &client,
+            SERVER_ID,
+            "1996-12-19T16:00:00Z",
+            "1996-12-19T18:00:00Z",
+        )
+        .await;
+        for user in ["owner", "alice", "bob"] {
+            client
+                .create_session_user(user, id, "photo", "going")
+                .await
+                .unwrap();
+        }
+        client
+            .update_session_user_status(id, "alice", "not_going", None)
+            .await
+            .unwrap();
+
+        // still coming beats joining first
+        assert_eq!(
+            Some("bob".to_string()),
+            client.get_successor(id, "owner").await.unwrap()
+        );
+
+        // only participants can take over, and only from the current owner
+        assert!(!client
+            .transfer_ownership(id, "owner", "carol", "transfer")
+            .await
+            .unwrap());
+        assert!(client
+            .transfer_ownership(id, "owner", "bob", "transfer")
+            .await
+            .unwrap());
+        assert!(!client
+            .transfer_ownership(id, "owner", "alice", "transfer")
+            .await
+            .unwrap());
+
+        let session = client.get_session(id).await.unwrap().unwrap();
+        assert_eq!("bob", session.owner);
+        let history = client.get_ownership_history(id).await.unwrap();
+        assert_eq!(1, history.len());
+        assert_eq!("owner", history[0].from_owner);
+        assert_eq!("bob", history[0].to_owner);
+    }
+
+    #[tokio::test]
+    async fn test_roles() {
+        let client = setup_client().await;
+        assert!(client.get_role(SERVER_ID, "alice").await.unwrap().is_none());
+
+        client
+            .upsert_role(SERVER_ID, "alice", "moderator")
+            .await
+            .unwrap();
+        client
+            .upsert_role(SERVER_ID, "alice", "admin")
+            .await
+            .unwrap();
+        client
+            .upsert_role("other_server", "bob", "moderator")
+            .await
+            .unwrap();
+
+        let roles = client.get_roles(SERVER_ID).await.unwrap();
+        assert_eq!(1, roles.len());
+        assert_eq!("admin", roles[0].role);
+
+        client.delete_role(SERVER_ID, "alice").await.unwrap();
+        assert!(client.get_role(SERVER_ID, "alice").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_update_session() {
+        let client = setup_client().await;
+        let id = create(
+            &client,
+            SERVER_ID,
+            "1996-12-19T16:00:00Z",
+            "1996-12-19T18:00:00Z",
+        )
+        .await;
+
+        let session = client
+            .update_session(
+                id,
+                &SessionDetails {
+                    title: "new title".to_string(),
+                    game: Some("game".to_string()),
+                    max_participants: Some(4),
+                    description: Some("ranked only".to_string()),
+                    ..details("1996-12-19T17:00:00Z", "1996-12-19T19:00:00Z")
+                },
+            )
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!("new title", session.title);
+        assert_eq!(Some("ranked only"), session.description.as_deref());
+        assert_eq!("1996-12-19T17:00:00Z", session.start_time);
+        assert_eq!(Some(4), session.max_participants);
+        assert!(!session.locked);
+
+        client.set_session_locked(id, true).await.unwrap();
+        assert!(client.get_session(id).await.unwrap().unwrap().locked);
+        assert!(client
+            .update_session(id, &details("a", "b"))
+            .await
+            .unwrap()
+            .is_some());
+        assert!(client
+            .update_session(id + 1, &details("a", "b"))
+            .await
+            .unwrap()
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn test_remove_owner() {
+        let client = setup_client().await;
+        let id = client
+            .create_session(
+                SERVER_ID,
+                "owner",
+                &SessionDetails {
+                    max_participants: Some(2),
+                    ..details("1996-12-19T16:00:00Z", "1996-12-19T18:00:00Z")
+                },
+            )
+            .await
+            .unwrap()
+            .session_id
+            .unwrap();
+        for user in ["owner", "alice", "bob"] {
+            client
+                .add_session_user(user, id, "photo", "going", None)
+                .await
+                .unwrap();
+        }
+
+        let outcome = client.remove_participant(id, "owner").await.unwrap();
+        assert_eq!(Some("bob".to_string()), outcome.promoted);
+        assert_eq!(Some("alice".to_string()), outcome.new_owner);
+        assert_eq!(
+            "alice",
+            client.get_session(id).await.unwrap().unwrap().owner
+        );
+        let history = client.get_ownership_history(id).await.unwrap();
+        assert_eq!(1, history.len());
+        assert_eq!("owner_left", history[0].reason);
+    }
+
+    #[tokio::test]
+    async fn test_join_missing_session() {
+        let client = setup_client().await;
+        assert!(matches!(
+            client
+                .add_session_user("alice", 1, "photo", "going", None)
+                .await,
+            Err(AppError::NotFound(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_delete_sessions_ended_before() {
+        let client = setup_client().await;
+        let old = create(
+            &client,
+            SERVER_ID,
+            "1996-12-19T16:00:00Z",
+            "1996-12-19T18:00:00Z",
+        )
+        .await;
+        let ongoing = create(
+            &client,
+            SERVER_ID,
+            "1996-12-19T23:00:00Z",
+            "1996-12-20T01:00:00-02:00",
+        )
+        .await;
+
+        assert_eq!(
+            1,
+            client
+                .delete_sessions_ended_before(time("1996-12-20T00:00:00Z"))
+                .await
+                .unwrap()
+        );
+        assert!(client.get_session(old).await.unwrap().is_none());
+        assert!(client.get_session(ongoing).await.unwrap().is_some());
+        assert_eq!(
+            vec![SERVER_ID.to_string()],
+            client.get_server_ids().await.unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_archive_sessions_ended_before() {
+        let client = setup_client().await;
+        let old = create(
+            &client,
+            SERVER_ID,
+            "1996-12-19T16:00:00Z",
+            "1996-12-19T18:00:00Z",
+        )
+        .await;
+        let ongoing = create(
+            &client,
+            SERVER_ID,
+            "1996-12-19T23:00:00Z",
+            "1996-12-20T01:00:00Z",
+        )
+        .await;
+        client
+            .create_session_user("alice", old, "photo", "going")
+            .await
+            .unwrap();
+        client.create_comment(old, "alice", "gg").await.unwrap();
+
+        assert_eq!(
+            1,
+            client
+                .archive_sessions_ended_before(time("1996-12-20T00:00:00Z"))
+                .await
+                .unwrap()
+        );
+        assert!(client.get_session(old).await.unwrap().is_none());
+        assert!(client.get_session(ongoing).await.unwrap().is_some());
+
+        let history = client.get_session_history(SERVER_ID).await.unwrap();
+        assert_eq!(1, history.len());
+        assert_eq!(old, history[0].session_id);
+        assert_eq!(
+            vec!["alice".to_string()],
+            client.get_user_history(old).await.unwrap()
+        );
+        let comments = client.get_comment_history(old).await.unwrap();
+        assert_eq!(1, comments.len());
+        assert_eq!("gg", comments[0].body);
+    }
 }
@@ -0,0 +1,361 @@
+use std::{fmt::Display, str::FromStr};
+
+use leptos::prelude::ServerFnError;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use thiserror::Error;
+
+use crate::component::model::Clash;
+
+/**
+ * Errors from the DAO and server functions. Display is also the wire format server functions
+ * use to send the error to the client, so every variant is "<kind>: <detail>"
+ */
+#[derive(Clone, Debug, Error, PartialEq, Eq)]
+pub enum AppError {
+    // bad input from the user, the detail is shown to them
+    #[error("invalid input: {0}")]
+    Validation(String),
+    // bad input in specific form fields, shown next to each field
+    #[error("invalid fields: {0}")]
+    InvalidFields(FieldErrors),
+    // what couldn't be found, e.g. "session"
+    #[error("not found: {0}")]
+    NotFound(String),
+    #[error("forbidden: {0}")]
+    Forbidden(String),
+    // the request clashes with the current state, e.g. a locked or full session
+    #[error("conflict: {0}")]
+    Conflict(String),
+    // the user already attends these sessions at the requested time, they may go ahead anyway
+    #[error("double booked: {}", json(.0))]
+    DoubleBooked(Vec<Clash>),
+    #[error("too many requests: {0}")]
+    TooManyRequests(String),
+    // database failures, the detail is logged but never shown to users
+    #[error("storage error: {0}")]
+    Storage(String),
+}
+
+impl AppError {
+    pub fn validation(msg: impl Display) -> Self {
+        AppError::Validation(msg.to_string())
+    }
+
+    pub fn not_found(what: impl Display) -> Self {
+        AppError::NotFound(what.to_string())
+    }
+
+    pub fn forbidden(msg: impl Display) -> Self {
+        AppError::Forbidden(msg.to_string())
+    }
+
+    pub fn conflict(msg: impl Display) -> Self {
+        AppError::Conflict(msg.to_string())
+    }
+
+    pub fn storage(e: impl Display) -> Self {
+        AppError::Storage(e.to_string())
+    }
+
+    // HTTP status the error is sent with
+    pub fn status(&self) -> u16 {
+        match self {
+            AppError::Validation(_) | AppError::InvalidFields(_) => 400,
+            AppError::NotFound(_) => 404,
+            AppError::Forbidden(_) => 403,
+            AppError::Conflict(_) | AppError::DoubleBooked(_) => 409,
+            AppError::TooManyRequests(_) => 429,
+            AppError::Storage(_) => 500,
+        }
+    }
+
+    // what to tell the user
+    pub fn message(&self) -> String {
+        match self {
+            AppError::Validation(msg)
+            | AppError::Forbidden(msg)
+            | AppError::Conflict(msg)
+            | AppError::TooManyRequests(msg) => msg.clone(),
+            AppError::InvalidFields(errors) => errors.messages().join(". "),
+            AppError::DoubleBooked(clashes) => format!(
+                "You're already in {} at that time",
+                clashes
+                    .iter()
+                    .map(|c| format!("\"{}\"", c.title))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            AppError::NotFound(what) => format!("That {what} doesn't exist anymore"),
+            AppError::Storage(_) => "Something went wrong, please try again".to_string(),
+        }
+    }
+
+    // the same error without internal details, for sending to the client
+    pub fn public(self) -> Self {
+        match self {
+            AppError::Storage(_) => AppError::Storage("internal error".to_string()),
+            e => e,
+        }
+    }
+}
+
+/**
+ * Problems with a form, by the name of the field's input
+ */
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FieldErrors(Vec<(String, String)>);
+
+impl FieldErrors {
+    pub fn add(&mut self, field: &str, msg: impl Display) {
+        self.0.push((field.to_string(), msg.to_string()));
+    }
+
+    // the first problem with a field
+    pub fn get(&self, field: &str) -> Option<String> {
+        self.0
+            .iter()
+            .find(|(f, _)| f == field)
+            .map(|(_, msg)| msg.clone())
+    }
+
+    pub fn messages(&self) -> Vec<String> {
+        self.0.iter().map(|(_, msg)| msg.clone()).collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl Display for FieldErrors {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&json(&self.0))
+    }
+}
+
+// structured details are written as JSON, so they can hold any text and still parse back
+fn json<T: Serialize>(value: &T) -> String {
+    serde_json::to_string(value).unwrap_or_default()
+}
+
+impl From<FieldErrors> for AppError {
+    fn from(errors: FieldErrors) -> Self {
+        AppError::InvalidFields(errors)
+    }
+}
+
+impl FromStr for AppError {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (kind, detail) = s.split_once(": ").ok_or(())?;
+        let detail = detail.to_string();
+        match kind {
+            "invalid input" => Ok(AppError::Validation(detail)),
+            "invalid fields" => Ok(AppError::InvalidFields(
+                serde_json::from_str(&detail).map_err(|_| ())?,
+            )),
+            "not found" => Ok(AppError::NotFound(detail)),
+            "forbidden" => Ok(AppError::Forbidden(detail)),
+            "conflict" => Ok(AppError::Conflict(detail)),
+            "double booked" => Ok(AppError::DoubleBooked(
+                serde_json::from_str(&detail).map_err(|_| ())?,
+            )),
+            "too many requests" => Ok(AppError::TooManyRequests(detail)),
+            "storage error" => Ok(AppError::Storage(detail)),
+            _ => Err(()),
+        }
+    }
+}
+
+// serialized like the wire format, e.g. for resources sent along with server rendered pages,
+// so storage details never leave the server
+impl Serialize for AppError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.clone().public().to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for AppError {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse()
+            .map_err(|_| serde::de::Error::custom(format!("unknown error: {s}")))
+    }
+}
+
+#[cfg(feature = "ssr")]
+impl From<sqlx::Error> for AppError {
+    fn from(e: sqlx::Error) -> Self {
+        AppError::Storage(e.to_string())
+    }
+}
+
+#[cfg(feature = "ssr")]
+impl From<sqlx::migrate::MigrateError> for AppError {
+    fn from(e: sqlx::migrate::MigrateError) -> Self {
+        AppError::Storage(e.to_string())
+    }
+}
+
+/**
+ * Middleware giving failed server function calls the status of their AppError instead of 500,
+ * and dropping storage details from the body
+ */
+#[cfg(feature = "ssr")]
+pub async fn error_status(
+    req: axum::extract::Request,
+    next: axum::middleware::Next,
+) -> axum::response::Response {
+    use axum::{
+        body::{to_bytes, Body},
+        http::StatusCode,
+        response::Response,
+    };
+    use leptos::server_fn::error::SERVER_FN_ERROR_HEADER;
+
+    let res = next.run(req).await;
+    if !res.headers().contains_key(SERVER_FN_ERROR_HEADER) {
+        return res;
+    }
+
+    let (mut parts, body) = res.into_parts();
+    let bytes = match to_bytes(body, usize::MAX).await {
+        Ok(bytes) => bytes,
+        Err(_) => return Response::from_parts(parts, Body::empty()),
+    };
+    let error = std::str::from_utf8(&bytes)
+        .ok()
+        .and_then(|body| body.strip_prefix("WrappedServerFn|"))
+        .and_then(|e| e.parse::<AppError>().ok());
+    match error {
+        Some(e) => {
+            parts.status = StatusCode::from_u16(e.status()).unwrap_or(parts.status);
+            parts.headers.remove(axum::http::header::CONTENT_LENGTH);
+            let body = format!("WrappedServerFn|{}", e.public());
+            Response::from_parts(parts, Body::from(body))
+        }
+        None => Response::from_parts(parts, Body::from(bytes)),
+    }
+}
+
+/**
+ * Message to show for a failed server function call
+ */
+pub fn error_message(e: &ServerFnError<AppError>) -> String {
+    match e {
+        ServerFnError::WrappedServerError(e) => e.message(),
+        ServerFnError::ServerError(msg) => msg.clone(),
+        _ => "Couldn't reach the server, please try again".to_string(),
+    }
+}
+
+/**
+ * Per-field problems of a failed server function call, empty for any other failure
+ */
+pub fn field_errors(e: &ServerFnError<AppError>) -> FieldErrors {
+    match e {
+        ServerFnError::WrappedServerError(AppError::InvalidFields(errors)) => errors.clone(),
+        _ => FieldErrors::default(),
+    }
+}
+
+/**
+ * Sessions a failed call would have double booked the user into, empty for any other failure
+ */
+pub fn clashes(e: &ServerFnError<AppError>) -> Vec<Clash> {
+    match e {
+        ServerFnError::WrappedServerError(AppError::DoubleBooked(clashes)) => clashes.clone(),
+        _ => vec![],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::DateTime;
+
+    #[test]
+    fn test_round_trip() {
+        for e in [
+            AppError::validation("end time must be after the start time"),
+            AppError::not_found("session"),
+            AppError::forbidden("only the owner can do this"),
+            AppError::conflict("session is locked: ask a moderator"),
+            AppError::TooManyRequests("try again in 5 minutes".to_string()),
+            AppError::InvalidFields(FieldErrors(vec![
+                ("title".to_string(), "Title is required".to_string()),
+                (
+                    "end".to_string(),
+                    "End: must be \"after\"; the start".to_string(),
+                ),
+            ])),
+            AppError::DoubleBooked(vec![Clash {
+                session_id: 4,
+                title: "Raid: \"night\"".to_string(),
+                start_time: DateTime::UNIX_EPOCH,
+                end_time: DateTime::UNIX_EPOCH,
+            }]),
+            AppError::storage("database is locked"),
+        ] {
+            assert_eq!(Ok(e.clone()), e.to_string().parse());
+        }
+        assert!("nonsense".parse::<AppError>().is_err());
+    }
+
+    #[cfg(feature = "ssr")]
+    #[tokio::test]
+    async fn test_error_status() {
+        use axum::{
+            body::{to_bytes, Body},
+            extract::Request,
+            http::StatusCode,
+            middleware,
+            routing::post,
+            Router,
+        };
+        use leptos::server_fn::error::SERVER_FN_ERROR_HEADER;
+        use tower::ServiceExt;
+
+        let fail = |e: AppError| {
+            move || async move {
+                (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    [(SERVER_FN_ERROR_HEADER, "/api/f")],
+                    format!("WrappedServerFn|{e}"),
+                )
+            }
+        };
+        let app = Router::new()
+            .route(
+                "/locked",
+                post(fail(AppError::conflict("This session is locked"))),
+            )
+            .route("/storage", post(fail(AppError::storage("disk I/O error"))))
+            .route("/ok", post(|| async { "ok" }))
+            .layer(middleware::from_fn(error_status));
+        let send = |path: &str| {
+            app.clone()
+                .oneshot(Request::post(path).body(Body::empty()).unwrap())
+        };
+
+        let res = send("/locked").await.unwrap();
+        assert_eq!(StatusCode::CONFLICT, res.status());
+        let body = to_bytes(res.into_body(), usize::MAX).await.unwrap();
+        assert_eq!("WrappedServerFn|conflict: This session is locked", body);
+
+        let res = send("/storage").await.unwrap();
+        assert_eq!(StatusCode::INTERNAL_SERVER_ERROR, res.status());
+        let body = to_bytes(res.into_body(), usize::MAX).await.unwrap();
+        assert!(!String::from_utf8_lossy(&body).contains("disk"));
+
+        assert_eq!(StatusCode::OK, send("/ok").await.unwrap().status());
+    }
+
+    #[test]
+    fn test_storage_details_stay_private() {
+        let e = AppError::storage("no such table: sessions").public();
+        assert!(!e.to_string().contains("sessions"));
+        assert_eq!(500, e.status());
+    }
+}
@@ -1,8 +1,21 @@
 #![recursion_limit = "256"]
 pub mod app;
 mod component;
+#[cfg(feature = "ssr")]
+pub mod config;
 pub mod dao;
-mod obf_util;
+pub mod error;
+#[cfg(feature = "ssr")]
+pub mod markdown;
+#[cfg(feature = "ssr")]
+pub mod metrics;
+pub mod obf_util;
+#[cfg(feature = "ssr")]
+pub mod rate_limit;
+#[cfg(feature = "ssr")]
+pub mod retention;
+#[cfg(feature = "ssr")]
+pub mod telemetry;
 
 #[cfg(feature = "hydrate")]
 #[wasm_bindgen::prelude::wasm_bindgen]
@@ -15,15 +15,36 @@ pub struct AppState {
 #[cfg(feature = "ssr")]
 #[tokio::main]
 async fn main() {
-    use axum::Router;
+    use axum::{middleware, routing::get, Router};
     use gaming_calendar_website::app::*;
-    use leptos::logging::log;
+    use gaming_calendar_website::metrics::{healthz, metrics_handler, readyz, track_requests};
+    use gaming_calendar_website::{
+        config::{Config, LinkSecret},
+        dao::sqlite_util::SqliteClient,
+        error::error_status,
+        rate_limit::{rate_limit, Quota, RateLimiter, SystemClock},
+        telemetry,
+    };
     use leptos::prelude::*;
     use leptos_axum::{generate_route_list, LeptosRoutes};
     use sqlx::sqlite::SqlitePoolOptions;
+    use std::{net::SocketAddr, sync::Arc};
+    use tower::ServiceBuilder;
+    use tower_http::{
+        request_id::{MakeRequestUuid, PropagateRequestIdLayer, SetRequestIdLayer},
+        trace::{DefaultOnResponse, TraceLayer},
+    };
+    use tracing::{info, Level};
+
+    let config = match Config::load() {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("invalid configuration: {e}");
+            std::process::exit(1);
+        }
+    };
+    telemetry::init(&config.log_level, config.log_format);
 
-    // load sql client
-    //let sql = SqliteClient::new(DB_URL);*/
     let conf = get_configuration(None).unwrap();
     let addr = conf.leptos_options.site_addr;
     let leptos_options = conf.leptos_options;
@@ -32,7 +53,8 @@ async fn main() {
 
     //setup db pool
     let pool = SqlitePoolOptions::new()
-        .connect("sqlite://sessions.db")
+        .max_connections(config.pool_size)
+        .connect(&config.database_url)
         .await
         .expect("Could not make pool.");
 
@@ -41,19 +63,49 @@ async fn main() {
         .await
         .expect("could not run SQLx migrations");
 
+    // clean up old sessions in the background
+    match config.retention.clone() {
+        Some(policy) => {
+            info!(days = policy.days, mode = ?policy.mode, "retention policy enabled");
+            policy.spawn(SqliteClient::from_pool(pool.clone()).await);
+        }
+        None => info!("no retention policy configured, keeping all sessions"),
+    }
+
     let state = AppState {
         leptos_options: leptos_options,
         pool: pool,
     };
 
+    let quotas = if config.rate_limit {
+        Quota::defaults()
+    } else {
+        vec![]
+    };
+    let limiter = Arc::new(RateLimiter::new(
+        quotas,
+        Arc::new(SystemClock),
+        config.trust_proxy,
+        config.link_secret.clone(),
+    ));
+
     let app = Router::new()
+        .route("/healthz", get(healthz))
+        .route("/readyz", get(readyz))
+        .route("/metrics", get(metrics_handler))
         .leptos_routes_with_context(
             &state,
             routes,
             {
                 let pool = state.pool.clone();
+                let defaults = config.defaults.clone();
+                let link_secret = config.link_secret.clone().map(LinkSecret);
                 move || {
                     provide_context(pool.clone());
+                    provide_context(defaults.clone());
+                    if let Some(secret) = link_secret.clone() {
+                        provide_context(secret);
+                    }
                 }
             },
             {
@@ -64,15 +116,32 @@ async fn main() {
         .fallback(leptos_axum::file_and_error_handler::<LeptosOptions, _>(
             shell,
         ))
+        // the request id is set first so the trace span and the response both carry it
+        .layer(
+            ServiceBuilder::new()
+                .layer(SetRequestIdLayer::x_request_id(MakeRequestUuid))
+                .layer(
+                    TraceLayer::new_for_http()
+                        .make_span_with(telemetry::request_span)
+                        .on_response(DefaultOnResponse::new().level(Level::INFO)),
+                )
+                .layer(PropagateRequestIdLayer::x_request_id())
+                .layer(middleware::from_fn(track_requests))
+                .layer(middleware::from_fn_with_state(limiter, rate_limit))
+                .layer(middleware::from_fn(error_status)),
+        )
         .with_state(state);
 
     // run our app with hyper
     // `axum::Server` is a re-export of `hyper::Server`
-    log!("listening on http://{}", &addr);
+    info!("listening on http://{}", &addr);
     let listener = tokio::net::TcpListener::bind(&addr).await.unwrap();
-    axum::serve(listener, app.into_make_service())
-        .await
-        .unwrap();
+    axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .await
+    .unwrap();
 }
 
 #[cfg(not(feature = "ssr"))]
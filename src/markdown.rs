@@ -0,0 +1,52 @@
+use ammonia::Builder;
+use pulldown_cmark::{html, Options, Parser};
+
+/**
+ * Renders a session description to HTML that is safe to insert into the page. Raw HTML in
+ * the markdown is sanitized away with anything else a user could abuse, e.g. scripts,
+ * event handlers and javascript: links. Images are dropped so descriptions can't load
+ * content from elsewhere
+ */
+pub fn render_markdown(text: &str) -> String {
+    let options = Options::ENABLE_STRIKETHROUGH | Options::ENABLE_TASKLISTS;
+    let mut unsafe_html = String::new();
+    html::push_html(&mut unsafe_html, Parser::new_ext(text, options));
+
+    Builder::default()
+        .rm_tags(["img"])
+        .url_schemes(["http", "https", "mailto"].into())
+        .link_rel(Some("noopener noreferrer nofollow"))
+        .set_tag_attribute_value("a", "target", "_blank")
+        .clean(&unsafe_html)
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_renders_markdown() {
+        let html = render_markdown("bring **mics**\n\n- ranked only\n- need a ~~tank~~ healer");
+        assert!(html.contains("<strong>mics</strong>"));
+        assert!(html.contains("<li>ranked only</li>"));
+        assert!(html.contains("<del>tank</del>"));
+    }
+
+    #[test]
+    fn test_sanitizes() {
+        let html = render_markdown(
+            "<script>alert(1)</script><b onclick=\"alert(2)\">hi</b>\n\n\
+             [link](javascript:alert(3)) ![img](https://example.com/x.png)",
+        );
+        assert!(!html.contains("<script"));
+        assert!(!html.contains("onclick"));
+        assert!(!html.contains("javascript"));
+        assert!(!html.contains("<img"));
+        assert!(html.contains("<b>hi</b>"));
+
+        let html = render_markdown("[discord](https://discord.com)");
+        assert!(html.contains("href=\"https://discord.com\""));
+        assert!(html.contains("rel=\"noopener noreferrer nofollow\""));
+    }
+}
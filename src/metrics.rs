@@ -0,0 +1,336 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    sync::{Mutex, OnceLock},
+    time::{Duration, Instant},
+};
+
+use axum::{
+    extract::{Request, State},
+    http::{header, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use leptos::server_fn::error::SERVER_FN_ERROR_HEADER;
+use sqlx::SqlitePool;
+use tracing::{
+    span::{Attributes, Id},
+    Event, Level, Subscriber,
+};
+use tracing_subscriber::{filter::Targets, layer::Context, registry::LookupSpan, Layer};
+
+use crate::{
+    dao::sqlite_util::{SqliteClient, QUERY_TARGET},
+    rate_limit::endpoint,
+};
+
+// upper bounds in seconds, Prometheus' default buckets
+const BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+#[derive(Default)]
+struct Histogram {
+    // observations per bucket, not cumulative
+    buckets: [u64; BUCKETS.len()],
+    count: u64,
+    sum: f64,
+}
+
+impl Histogram {
+    fn observe(&mut self, elapsed: Duration) {
+        let secs = elapsed.as_secs_f64();
+        if let Some(i) = BUCKETS.iter().position(|le| secs <= *le) {
+            self.buckets[i] += 1;
+        }
+        self.count += 1;
+        self.sum += secs;
+    }
+
+    fn render(&self, out: &mut String, name: &str, labels: &str) {
+        let mut cumulative = 0;
+        for (le, n) in BUCKETS.iter().zip(self.buckets) {
+            cumulative += n;
+            let _ = writeln!(out, "{name}_bucket{{{labels},le=\"{le}\"}} {cumulative}");
+        }
+        let _ = writeln!(out, "{name}_bucket{{{labels},le=\"+Inf\"}} {}", self.count);
+        let _ = writeln!(out, "{name}_sum{{{labels}}} {}", self.sum);
+        let _ = writeln!(out, "{name}_count{{{labels}}} {}", self.count);
+    }
+}
+
+/**
+ * In-process counters exposed on /metrics
+ */
+#[derive(Default)]
+struct Metrics {
+    // (server function, status) -> count
+    requests: BTreeMap<(String, u16), u64>,
+    request_seconds: BTreeMap<String, Histogram>,
+    // (query, ok) -> count
+    queries: BTreeMap<(&'static str, bool), u64>,
+    query_seconds: BTreeMap<&'static str, Histogram>,
+}
+
+fn metrics() -> &'static Mutex<Metrics> {
+    static METRICS: OnceLock<Mutex<Metrics>> = OnceLock::new();
+    METRICS.get_or_init(Default::default)
+}
+
+pub fn observe_request(function: &str, status: u16, elapsed: Duration) {
+    let mut m = metrics().lock().unwrap();
+    *m.requests
+        .entry((function.to_string(), status))
+        .or_default() += 1;
+    m.request_seconds
+        .entry(function.to_string())
+        .or_default()
+        .observe(elapsed);
+}
+
+pub fn observe_query(query: &'static str, elapsed: Duration, ok: bool) {
+    let mut m = metrics().lock().unwrap();
+    *m.queries.entry((query, ok)).or_default() += 1;
+    m.query_seconds.entry(query).or_default().observe(elapsed);
+}
+
+// when a query span opened, how long it ran until it was last left and whether it failed
+struct QuerySpan {
+    start: Instant,
+    elapsed: Duration,
+    ok: bool,
+    // opened by another query, which is timed as a whole
+    nested: bool,
+}
+
+/**
+ * Times the spans `#[instrument]` opens around every `SqliteClient` method. Queries run by
+ * another query, like the ones `remove_participant` makes, count as part of it.
+ * Spans close once sqlx's connection worker lets go of them too, so a query is timed until
+ * its span was last left rather than until it closes
+ */
+struct QueryTimer;
+
+impl<S> Layer<S> for QueryTimer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, _: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        let nested = span
+            .scope()
+            .skip(1)
+            .any(|outer| outer.extensions().get::<QuerySpan>().is_some());
+        span.extensions_mut().insert(QuerySpan {
+            start: Instant::now(),
+            elapsed: Duration::ZERO,
+            ok: true,
+            nested,
+        });
+    }
+
+    fn on_exit(&self, id: &Id, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(id) {
+            if let Some(query) = span.extensions_mut().get_mut::<QuerySpan>() {
+                query.elapsed = query.start.elapsed();
+            }
+        }
+    }
+
+    // `err` on `#[instrument]` reports the returned error as an event in the span
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        if *event.metadata().level() != Level::ERROR {
+            return;
+        }
+        if let Some(span) = ctx.event_span(event) {
+            if let Some(query) = span.extensions_mut().get_mut::<QuerySpan>() {
+                query.ok = false;
+            }
+        }
+    }
+
+    fn on_close(&self, id: Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(&id) else {
+            return;
+        };
+        let extensions = span.extensions();
+        if let Some(query) = extensions.get::<QuerySpan>().filter(|q| !q.nested) {
+            observe_query(span.name(), query.elapsed, query.ok);
+        }
+    }
+}
+
+/// Layer recording query metrics, whatever the log level is
+pub fn query_layer<S>() -> impl Layer<S>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    QueryTimer.with_filter(Targets::new().with_target(QUERY_TARGET, Level::DEBUG))
+}
+
+/**
+ * Middleware timing server function calls. Pages and unknown paths are each counted
+ * together so arbitrary paths can't blow up the number of series
+ */
+pub async fn track_requests(req: Request, next: Next) -> Response {
+    let function = endpoint(req.uri().path()).unwrap_or("page").to_string();
+    let start = Instant::now();
+    let res = next.run(req).await;
+    // server functions can answer 404 too, for rows that don't exist
+    let function = if res.status() == StatusCode::NOT_FOUND
+        && !res.headers().contains_key(SERVER_FN_ERROR_HEADER)
+    {
+        "unknown"
+    } else {
+        &function
+    };
+    observe_request(function, res.status().as_u16(), start.elapsed());
+    res
+}
+
+// process is up
+pub async fn healthz() -> &'static str {
+    "ok"
+}
+
+// database reachable and every migration applied
+pub async fn readyz(State(pool): State<SqlitePool>) -> (StatusCode, String) {
+    let client = SqliteClient::from_pool(pool).await;
+    match client.get_applied_migrations().await {
+        Ok(applied) => {
+            let missing = sqlx::migrate!()
+                .iter()
+                .filter(|m| !applied.contains(&m.version))
+                .count();
+            if missing == 0 {
+                (StatusCode::OK, "ok".to_string())
+            } else {
+                (
+                    StatusCode::SERVICE_UNAVAILABLE,
+                    format!("{missing} migrations not applied"),
+                )
+            }
+        }
+        Err(e) => (
+            StatusCode::SERVICE_UNAVAILABLE,
+            format!("database unreachable: {e}"),
+        ),
+    }
+}
+
+pub async fn metrics_handler(State(pool): State<SqlitePool>) -> Response {
+    let client = SqliteClient::from_pool(pool).await;
+    let mut out = render();
+    match client.get_totals().await {
+        Ok(totals) => {
+            out.push_str("# TYPE gametonite_sessions gauge\n");
+            let _ = writeln!(out, "gametonite_sessions {}", totals.sessions);
+            out.push_str("# TYPE gametonite_session_users gauge\n");
+            let _ = writeln!(out, "gametonite_session_users {}", totals.users);
+            out.push_str("# TYPE gametonite_waitlisted_users gauge\n");
+            let _ = writeln!(out, "gametonite_waitlisted_users {}", totals.waitlisted);
+            out.push_str("# TYPE gametonite_archived_sessions gauge\n");
+            let _ = writeln!(out, "gametonite_archived_sessions {}", totals.archived);
+        }
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+
+    ([(header::CONTENT_TYPE, "text/plain; version=0.0.4")], out).into_response()
+}
+
+// counters and histograms in Prometheus' text format
+fn render() -> String {
+    let m = metrics().lock().unwrap();
+    let mut out = String::new();
+
+    out.push_str("# TYPE gametonite_requests_total counter\n");
+    for ((function, status), n) in &m.requests {
+        let _ = writeln!(
+            out,
+            "gametonite_requests_total{{function=\"{function}\",status=\"{status}\"}} {n}"
+        );
+    }
+    out.push_str("# TYPE gametonite_request_duration_seconds histogram\n");
+    for (function, h) in &m.request_seconds {
+        h.render(
+            &mut out,
+            "gametonite_request_duration_seconds",
+            &format!("function=\"{function}\""),
+        );
+    }
+
+    out.push_str("# TYPE gametonite_db_queries_total counter\n");
+    for ((query, ok), n) in &m.queries {
+        let _ = writeln!(
+            out,
+            "gametonite_db_queries_total{{query=\"{query}\",result=\"{}\"}} {n}",
+            if *ok { "ok" } else { "error" }
+        );
+    }
+    out.push_str("# TYPE gametonite_db_query_duration_seconds histogram\n");
+    for (query, h) in &m.query_seconds {
+        h.render(
+            &mut out,
+            "gametonite_db_query_duration_seconds",
+            &format!("query=\"{query}\""),
+        );
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_histogram_is_cumulative() {
+        let mut h = Histogram::default();
+        h.observe(Duration::from_millis(1));
+        h.observe(Duration::from_millis(20));
+        h.observe(Duration::from_secs(60));
+
+        let mut out = String::new();
+        h.render(&mut out, "t", "f=\"x\"");
+        assert!(out.contains("t_bucket{f=\"x\",le=\"0.005\"} 1\n"));
+        assert!(out.contains("t_bucket{f=\"x\",le=\"0.025\"} 2\n"));
+        assert!(out.contains("t_bucket{f=\"x\",le=\"10\"} 2\n"));
+        assert!(out.contains("t_bucket{f=\"x\",le=\"+Inf\"} 3\n"));
+        assert!(out.contains("t_count{f=\"x\"} 3\n"));
+    }
+
+    #[test]
+    fn test_query_layer() {
+        use tracing_subscriber::layer::SubscriberExt;
+
+        let _guard =
+            tracing::subscriber::set_default(tracing_subscriber::registry().with(query_layer()));
+        tracing::debug_span!(target: QUERY_TARGET, "test_outer").in_scope(|| {
+            tracing::debug_span!(target: QUERY_TARGET, "test_inner").in_scope(|| {});
+        });
+        tracing::debug_span!(target: QUERY_TARGET, "test_failing").in_scope(|| {
+            tracing::error!(target: QUERY_TARGET, "query failed");
+        });
+        tracing::debug_span!("test_other").in_scope(|| {});
+
+        let out = render();
+        assert!(out.contains("gametonite_db_queries_total{query=\"test_outer\",result=\"ok\"} 1\n"));
+        // queries made by another query only count as part of it
+        assert!(!out.contains("query=\"test_inner\""));
+        assert!(out
+            .contains("gametonite_db_queries_total{query=\"test_failing\",result=\"error\"} 1\n"));
+        assert!(!out.contains("query=\"test_other\""));
+    }
+
+    #[test]
+    fn test_render_requests() {
+        observe_request("get_events", 200, Duration::from_millis(3));
+        observe_request("get_events", 200, Duration::from_millis(3));
+        let out = render();
+        assert!(
+            out.contains("gametonite_requests_total{function=\"get_events\",status=\"200\"} 2\n")
+        );
+    }
+}
@@ -3,15 +3,20 @@ use base64::{engine::general_purpose::STANDARD, Engine as _};
 use reactive_stores::Store;
 use serde::{Deserialize, Serialize};
 
+#[cfg(feature = "ssr")]
+use crate::error::AppError;
+
 #[derive(Clone, Debug, Store, Serialize, Deserialize, Default)]
 pub struct UrlParams {
     server_id: String,
     user_id: String,
+    /// signature of signed links, see `sign_url`
+    signature: Option<String>,
 }
 
 impl UrlParams {
     /**
-     * Converts url string from base64 (server_id:user_id) to server_id and user_id
+     * Converts url string from base64 (server_id:user_id[:signature]) to server_id and user_id
      */
     pub fn decode_url(url: String) -> Result<Self> {
         let st = String::from_utf8(STANDARD.decode(url)?)?;
@@ -19,9 +24,56 @@ impl UrlParams {
         Ok(Self {
             server_id: s.next().context("params decode failed")?.to_string(),
             user_id: s.next().context("params decode failed")?.to_string(),
+            signature: s.next().map(|sig| sig.to_string()),
         })
     }
 
+    /**
+     * Creates the url string for a user, signed with HMAC-SHA256 so links can't be made up by
+     * swapping in another user id
+     */
+    #[cfg(feature = "ssr")]
+    pub fn sign_url(server_id: &str, user_id: &str, secret: &str) -> String {
+        let signature = sign(&format!("{server_id}:{user_id}"), secret);
+        STANDARD.encode(format!("{server_id}:{user_id}:{signature}"))
+    }
+
+    /// Whether the url was signed with `secret`
+    #[cfg(feature = "ssr")]
+    pub fn verify(&self, secret: &str) -> bool {
+        use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+        use hmac::Mac;
+
+        let Some(signature) = self
+            .signature
+            .as_ref()
+            .and_then(|sig| URL_SAFE_NO_PAD.decode(sig).ok())
+        else {
+            return false;
+        };
+        mac(&format!("{}:{}", self.server_id, self.user_id), secret)
+            .verify_slice(&signature)
+            .is_ok()
+    }
+
+    /**
+     * The url string again. Server functions take it instead of a user id, so they can check
+     * who is calling with `verify_caller`
+     */
+    pub fn encode(&self) -> String {
+        match &self.signature {
+            Some(signature) => {
+                STANDARD.encode(format!("{}:{}:{}", self.server_id, self.user_id, signature))
+            }
+            None => Self::encode_url(&self.server_id, &self.user_id),
+        }
+    }
+
+    /// Unsigned url string, for servers without a link secret
+    pub fn encode_url(server_id: &str, user_id: &str) -> String {
+        STANDARD.encode(format!("{server_id}:{user_id}"))
+    }
+
     pub fn get_server_id(&self) -> String {
         self.server_id.clone()
     }
@@ -30,3 +82,159 @@ impl UrlParams {
         self.user_id.clone()
     }
 }
+
+/**
+ * Identifies a user on every server, for pages that aren't about a single server like the
+ * agenda. The url string is base64 of user_id[:signature]
+ */
+#[derive(Clone, Debug, Serialize, Deserialize, Default, PartialEq)]
+pub struct UserLink {
+    user_id: String,
+    signature: Option<String>,
+}
+
+impl UserLink {
+    pub fn decode_url(url: String) -> Result<Self> {
+        let st = String::from_utf8(STANDARD.decode(url)?)?;
+        let mut s = st.split(":");
+        let user_id = s.next().filter(|id| !id.is_empty());
+        Ok(Self {
+            user_id: user_id.context("params decode failed")?.to_string(),
+            signature: s.next().map(|sig| sig.to_string()),
+        })
+    }
+
+    /**
+     * Creates the url string for a user. Server links sign "server_id:user_id" while this
+     * signs the bare user id, which can't contain ':', so neither signature works for the other
+     */
+    #[cfg(feature = "ssr")]
+    pub fn sign_url(user_id: &str, secret: &str) -> String {
+        STANDARD.encode(format!("{}:{}", user_id, sign(user_id, secret)))
+    }
+
+    /// Whether the url was signed with `secret`
+    #[cfg(feature = "ssr")]
+    pub fn verify(&self, secret: &str) -> bool {
+        use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+        use hmac::Mac;
+
+        let Some(signature) = self
+            .signature
+            .as_ref()
+            .and_then(|sig| URL_SAFE_NO_PAD.decode(sig).ok())
+        else {
+            return false;
+        };
+        mac(&self.user_id, secret).verify_slice(&signature).is_ok()
+    }
+
+    pub fn get_user_id(&self) -> String {
+        self.user_id.clone()
+    }
+}
+
+/**
+ * The server and user a link was made for. With a secret, only links signed with it are
+ * accepted. Without one, links are trusted as they are
+ */
+#[cfg(feature = "ssr")]
+pub fn verify_link(link: &str, secret: Option<&str>) -> Result<UrlParams, AppError> {
+    let params = UrlParams::decode_url(link.to_string())
+        .map_err(|_| AppError::validation("Invalid link"))?;
+    match secret {
+        Some(secret) if !params.verify(secret) => Err(AppError::forbidden(
+            "This link is not valid, ask for a new one",
+        )),
+        _ => Ok(params),
+    }
+}
+
+/**
+ * Who is calling a server function, from the link they opened the calendar with.
+ * Only available inside server functions, like `config::link_secret`. The caller is recorded
+ * on the current span
+ */
+#[cfg(feature = "ssr")]
+pub fn verify_caller(link: &str) -> Result<UrlParams, AppError> {
+    let caller = verify_link(link, crate::config::link_secret().as_deref())?;
+    // spans of server functions leave the caller empty until it is known
+    let span = tracing::Span::current();
+    span.record("server_id", caller.get_server_id());
+    span.record("user_id", caller.get_user_id());
+    Ok(caller)
+}
+
+#[cfg(feature = "ssr")]
+fn mac(message: &str, secret: &str) -> hmac::Hmac<sha2::Sha256> {
+    use hmac::Mac;
+
+    let mut mac = hmac::Hmac::<sha2::Sha256>::new_from_slice(secret.as_bytes())
+        .expect("hmac takes keys of any size");
+    mac.update(message.as_bytes());
+    mac
+}
+
+// url safe base64 never contains the ':' separator
+#[cfg(feature = "ssr")]
+fn sign(message: &str, secret: &str) -> String {
+    use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+    use hmac::Mac;
+
+    URL_SAFE_NO_PAD.encode(mac(message, secret).finalize().into_bytes())
+}
+
+#[cfg(all(test, feature = "ssr"))]
+mod tests {
+    use super::*;
+
+    const SECRET: &str = "0123456789abcdef";
+
+    #[test]
+    fn test_verify_link() {
+        let signed = UrlParams::sign_url("1", "alice", SECRET);
+        let caller = verify_link(&signed, Some(SECRET)).unwrap();
+        assert_eq!("1", caller.get_server_id());
+        assert_eq!("alice", caller.get_user_id());
+        assert_eq!(signed, caller.encode());
+
+        // swapping in another user or leaving out the signature is refused
+        let forged = STANDARD.encode(format!(
+            "1:admin:{}",
+            caller.signature.clone().unwrap_or_default()
+        ));
+        assert!(matches!(
+            verify_link(&forged, Some(SECRET)),
+            Err(AppError::Forbidden(_))
+        ));
+        let unsigned = UrlParams::encode_url("1", "alice");
+        assert!(verify_link(&unsigned, Some(SECRET)).is_err());
+        assert!(matches!(
+            verify_link("not a link", Some(SECRET)),
+            Err(AppError::Validation(_))
+        ));
+
+        // without a secret any link goes
+        assert_eq!("alice", verify_link(&unsigned, None).unwrap().get_user_id());
+    }
+
+    #[test]
+    fn test_user_link() {
+        let link = UserLink::decode_url(UserLink::sign_url("alice", SECRET)).unwrap();
+        assert_eq!("alice", link.get_user_id());
+        assert!(link.verify(SECRET));
+        assert!(!link.verify("fedcba9876543210"));
+
+        // unsigned links and server links don't identify the user
+        let unsigned = UserLink::decode_url(STANDARD.encode("alice")).unwrap();
+        assert!(!unsigned.verify(SECRET));
+        let server_link = UrlParams::decode_url(UrlParams::sign_url("1", "alice", SECRET)).unwrap();
+        assert!(server_link.verify(SECRET));
+        let forged = STANDARD.encode(format!(
+            "alice:{}",
+            server_link.signature.unwrap_or_default()
+        ));
+        assert!(!UserLink::decode_url(forged).unwrap().verify(SECRET));
+        assert!(UserLink::decode_url(STANDARD.encode("")).is_err());
+    }
+}
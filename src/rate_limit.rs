@@ -0,0 +1,437 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use axum::{
+    body::{to_bytes, Body},
+    extract::{ConnectInfo, Request, State},
+    http::{header, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+use crate::{error::AppError, obf_util::verify_link};
+
+// server function arguments are small forms, anything bigger is rejected
+const MAX_BODY_BYTES: usize = 64 * 1024;
+// forget idle keys once this many are tracked
+const MAX_KEYS: usize = 10_000;
+
+pub trait Clock: Send + Sync {
+    fn now(&self) -> Instant;
+}
+
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/**
+ * What a quota counts calls by. Users and servers come from the caller's link, which can
+ * only be trusted when links are signed. Without a link secret anyone can make up links,
+ * so only the IP quotas actually limit anything
+ */
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum QuotaKey {
+    Ip,
+    User,
+    // the same user on one server
+    UserAndServer,
+}
+
+/**
+ * At most `limit` calls per `window` to a server function, counted per key.
+ * No endpoint means every server function
+ */
+#[derive(Clone, Debug)]
+pub struct Quota {
+    pub endpoint: Option<&'static str>,
+    pub key: QuotaKey,
+    pub limit: usize,
+    pub window: Duration,
+}
+
+impl Quota {
+    const fn new(endpoint: Option<&'static str>, key: QuotaKey, limit: usize, secs: u64) -> Self {
+        Quota {
+            endpoint,
+            key,
+            limit,
+            window: Duration::from_secs(secs),
+        }
+    }
+
+    pub fn defaults() -> Vec<Quota> {
+        vec![
+            Quota::new(None, QuotaKey::Ip, 600, 60),
+            Quota::new(Some("create_event"), QuotaKey::UserAndServer, 10, 3600),
+            Quota::new(Some("create_event"), QuotaKey::Ip, 30, 3600),
+            Quota::new(Some("create_poll"), QuotaKey::UserAndServer, 10, 3600),
+            Quota::new(Some("add_user"), QuotaKey::User, 30, 60),
+            Quota::new(Some("add_user"), QuotaKey::Ip, 60, 60),
+            Quota::new(Some("remove_user"), QuotaKey::User, 30, 60),
+            Quota::new(Some("remove_user"), QuotaKey::Ip, 60, 60),
+            Quota::new(Some("post_comment"), QuotaKey::User, 20, 60),
+            Quota::new(Some("post_comment"), QuotaKey::Ip, 60, 60),
+        ]
+    }
+}
+
+/// Who is calling, as far as quotas care
+#[derive(Clone, Debug, Default)]
+pub struct Caller {
+    pub ip: Option<String>,
+    pub user_id: Option<String>,
+    pub server_id: Option<String>,
+}
+
+/**
+ * Sliding window limiter over the timestamps of recent calls
+ */
+pub struct RateLimiter {
+    quotas: Vec<Quota>,
+    clock: Arc<dyn Clock>,
+    // honour X-Forwarded-For, only safe behind a reverse proxy that sets it
+    trust_proxy: bool,
+    // callers are only known from links signed with it
+    link_secret: Option<String>,
+    hits: Mutex<HashMap<String, VecDeque<Instant>>>,
+}
+
+impl RateLimiter {
+    pub fn new(
+        quotas: Vec<Quota>,
+        clock: Arc<dyn Clock>,
+        trust_proxy: bool,
+        link_secret: Option<String>,
+    ) -> Self {
+        RateLimiter {
+            quotas,
+            clock,
+            trust_proxy,
+            link_secret,
+            hits: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /**
+     * Records a call to `endpoint`, or returns how long to wait if any quota is used up.
+     * Nothing is recorded for rejected calls, so waiting always helps
+     */
+    pub fn check(&self, endpoint: &str, caller: &Caller) -> Result<(), Duration> {
+        let now = self.clock.now();
+        let mut hits = self.hits.lock().unwrap();
+        if hits.len() > MAX_KEYS {
+            let longest = self
+                .quotas
+                .iter()
+                .map(|q| q.window)
+                .max()
+                .unwrap_or_default();
+            hits.retain(|_, times| times.back().is_some_and(|t| now - *t < longest));
+        }
+
+        let keys: Vec<(String, &Quota)> = self
+            .quotas
+            .iter()
+            .filter(|q| q.endpoint.is_none_or(|e| e == endpoint))
+            .filter_map(|q| {
+                let who = match q.key {
+                    QuotaKey::Ip => caller.ip.clone()?,
+                    QuotaKey::User => caller.user_id.clone()?,
+                    QuotaKey::UserAndServer => {
+                        format!(
+                            "{}@{}",
+                            caller.user_id.as_ref()?,
+                            caller.server_id.as_ref()?
+                        )
+                    }
+                };
+                let scope = q.endpoint.unwrap_or("*");
+                Some((format!("{}:{:?}:{}:{}", scope, q.key, q.limit, who), q))
+            })
+            .collect();
+
+        let mut retry_after = None;
+        for (key, quota) in &keys {
+            let times = hits.entry(key.clone()).or_default();
+            while times.front().is_some_and(|t| now - *t >= quota.window) {
+                times.pop_front();
+            }
+            if times.len() >= quota.limit {
+                let wait = quota.window - (now - times[times.len() - quota.limit]);
+                retry_after = retry_after.max(Some(wait));
+            }
+        }
+        if let Some(wait) = retry_after {
+            return Err(wait);
+        }
+
+        for (key, _) in keys {
+            hits.entry(key).or_default().push_back(now);
+        }
+        Ok(())
+    }
+}
+
+// "/api/add_user1234" -> "add_user"
+pub(crate) fn endpoint(path: &str) -> Option<&str> {
+    path.strip_prefix("/api/")
+        .map(|name| name.trim_end_matches(|c: char| c.is_ascii_digit()))
+}
+
+fn client_ip(req: &Request, trust_proxy: bool) -> Option<String> {
+    let forwarded = req
+        .headers()
+        .get("x-forwarded-for")
+        .and_then(|v| v.to_str().ok())
+        // the proxy appends the address it saw last
+        .and_then(|v| v.rsplit(',').next())
+        .map(|ip| ip.trim().to_string());
+    match forwarded {
+        Some(ip) if trust_proxy => Some(ip),
+        _ => req
+            .extensions()
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(addr)| addr.ip().to_string()),
+    }
+}
+
+fn too_many_requests(wait: Duration) -> Response {
+    let secs = wait.as_secs().max(1);
+    let when = if secs < 60 {
+        format!("{secs} seconds")
+    } else {
+        format!("{} minutes", secs.div_ceil(60))
+    };
+    // server functions read error bodies as a serialized ServerFnError
+    let e = AppError::TooManyRequests(format!("Too many requests, try again in {when}"));
+    (
+        StatusCode::TOO_MANY_REQUESTS,
+        [(header::RETRY_AFTER, secs.to_string())],
+        format!("WrappedServerFn|{e}"),
+    )
+        .into_response()
+}
+
+/**
+ * Middleware applying the quotas to server function calls. The user and server are read
+ * from the link in the form body or query, the body is put back for the server function
+ * afterwards. Links that don't verify leave the caller unknown, the server function
+ * refuses them anyway
+ */
+pub async fn rate_limit(
+    State(limiter): State<Arc<RateLimiter>>,
+    req: Request,
+    next: Next,
+) -> Response {
+    let endpoint = match endpoint(req.uri().path()) {
+        Some(endpoint) if !limiter.quotas.is_empty() => endpoint.to_string(),
+        _ => return next.run(req).await,
+    };
+    let mut caller = Caller {
+        ip: client_ip(&req, limiter.trust_proxy),
+        ..Default::default()
+    };
+
+    let (parts, body) = req.into_parts();
+    let bytes = match to_bytes(body, MAX_BODY_BYTES).await {
+        Ok(bytes) => bytes,
+        Err(_) => return StatusCode::PAYLOAD_TOO_LARGE.into_response(),
+    };
+    let query = parts.uri.query().unwrap_or_default().as_bytes();
+    for (name, value) in form_urlencoded::parse(&bytes).chain(form_urlencoded::parse(query)) {
+        if name == "link" {
+            if let Ok(params) = verify_link(&value, limiter.link_secret.as_deref()) {
+                caller.user_id = Some(params.get_user_id());
+                caller.server_id = Some(params.get_server_id());
+            }
+        }
+    }
+
+    if let Err(wait) = limiter.check(&endpoint, &caller) {
+        tracing::warn!(endpoint, ?caller, "rate limited");
+        return too_many_requests(wait);
+    }
+    next.run(Request::from_parts(parts, Body::from(bytes)))
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::obf_util::UrlParams;
+    use axum::{middleware, routing::post, Router};
+    use tower::ServiceExt;
+
+    const SECRET: &str = "0123456789abcdef";
+
+    struct MockClock(Mutex<Instant>);
+
+    impl MockClock {
+        fn advance(&self, by: Duration) {
+            *self.0.lock().unwrap() += by;
+        }
+    }
+
+    impl Clock for MockClock {
+        fn now(&self) -> Instant {
+            *self.0.lock().unwrap()
+        }
+    }
+
+    fn limiter(quotas: Vec<Quota>) -> (Arc<RateLimiter>, Arc<MockClock>) {
+        let clock = Arc::new(MockClock(Mutex::new(Instant::now())));
+        (
+            Arc::new(RateLimiter::new(
+                quotas,
+                clock.clone(),
+                false,
+                Some(SECRET.to_string()),
+            )),
+            clock,
+        )
+    }
+
+    fn caller(user_id: &str, server_id: &str) -> Caller {
+        Caller {
+            ip: Some("127.0.0.1".to_string()),
+            user_id: Some(user_id.to_string()),
+            server_id: Some(server_id.to_string()),
+        }
+    }
+
+    #[test]
+    fn test_window_slides() {
+        let (limiter, clock) = limiter(vec![Quota::new(
+            Some("create_event"),
+            QuotaKey::UserAndServer,
+            2,
+            3600,
+        )]);
+        let alice = caller("alice", "server");
+
+        assert!(limiter.check("create_event", &alice).is_ok());
+        clock.advance(Duration::from_secs(600));
+        assert!(limiter.check("create_event", &alice).is_ok());
+        assert_eq!(
+            Err(Duration::from_secs(3000)),
+            limiter.check("create_event", &alice)
+        );
+
+        // other users, servers and endpoints have their own quota
+        assert!(limiter
+            .check("create_event", &caller("bob", "server"))
+            .is_ok());
+        assert!(limiter
+            .check("create_event", &caller("alice", "other"))
+            .is_ok());
+        assert!(limiter.check("add_user", &alice).is_ok());
+
+        // the first call drops out of the window
+        clock.advance(Duration::from_secs(3000));
+        assert!(limiter.check("create_event", &alice).is_ok());
+        assert!(limiter.check("create_event", &alice).is_err());
+    }
+
+    #[test]
+    fn test_longest_wait_wins() {
+        let (limiter, _) = limiter(vec![
+            Quota::new(Some("add_user"), QuotaKey::User, 1, 60),
+            Quota::new(None, QuotaKey::Ip, 1, 600),
+        ]);
+        assert!(limiter
+            .check("add_user", &caller("alice", "server"))
+            .is_ok());
+        assert_eq!(
+            Err(Duration::from_secs(600)),
+            limiter.check("add_user", &caller("alice", "server"))
+        );
+    }
+
+    fn form(pairs: &[(&str, &str)]) -> String {
+        form_urlencoded::Serializer::new(String::new())
+            .extend_pairs(pairs)
+            .finish()
+    }
+
+    async fn post_form(app: &Router, body: String) -> Response {
+        let req = Request::post("/api/add_user123")
+            .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+            .body(Body::from(body))
+            .unwrap();
+        app.clone().oneshot(req).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_user_comes_from_signed_link() {
+        let (limiter, _) = limiter(vec![Quota::new(Some("add_user"), QuotaKey::User, 1, 60)]);
+        let app = Router::new()
+            .route("/api/add_user123", post(|| async {}))
+            .layer(middleware::from_fn_with_state(limiter, rate_limit));
+        let alice = UrlParams::sign_url("s", "alice", SECRET);
+
+        let res = post_form(&app, form(&[("link", &alice)])).await;
+        assert_eq!(StatusCode::OK, res.status());
+        // a user id in the body doesn't move the call to another quota
+        let res = post_form(&app, form(&[("link", &alice), ("user_id", "bob")])).await;
+        assert_eq!(StatusCode::TOO_MANY_REQUESTS, res.status());
+
+        // unsigned links name nobody, the server function refuses them
+        let unsigned = UrlParams::encode_url("s", "alice");
+        let res = post_form(&app, form(&[("link", &unsigned)])).await;
+        assert_eq!(StatusCode::OK, res.status());
+    }
+
+    #[tokio::test]
+    async fn test_middleware_returns_429() {
+        let (limiter, clock) = limiter(vec![Quota::new(
+            Some("create_event"),
+            QuotaKey::UserAndServer,
+            1,
+            60,
+        )]);
+        let app = Router::new()
+            .route("/api/create_event123", post(|body: String| async { body }))
+            .layer(middleware::from_fn_with_state(limiter, rate_limit));
+        let body = form(&[
+            ("link", &UrlParams::sign_url("s", "alice", SECRET)),
+            ("title", "t"),
+        ]);
+        let send = || {
+            app.clone().oneshot(
+                Request::post("/api/create_event123")
+                    .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+                    .body(Body::from(body.clone()))
+                    .unwrap(),
+            )
+        };
+
+        let res = send().await.unwrap();
+        assert_eq!(StatusCode::OK, res.status());
+        // the handler still gets the whole body
+        let body = to_bytes(res.into_body(), MAX_BODY_BYTES).await.unwrap();
+        assert_eq!(
+            form(&[
+                ("link", &UrlParams::sign_url("s", "alice", SECRET)),
+                ("title", "t")
+            ]),
+            body
+        );
+
+        let res = send().await.unwrap();
+        assert_eq!(StatusCode::TOO_MANY_REQUESTS, res.status());
+        assert_eq!("60", res.headers()[header::RETRY_AFTER]);
+        let body = to_bytes(res.into_body(), MAX_BODY_BYTES).await.unwrap();
+        assert!(body.starts_with(b"WrappedServerFn|too many requests: "));
+
+        clock.advance(Duration::from_secs(60));
+        assert_eq!(StatusCode::OK, send().await.unwrap().status());
+    }
+}
@@ -0,0 +1,66 @@
+use std::{str::FromStr, time::Duration};
+
+use anyhow::{anyhow, Result};
+use chrono::Utc;
+use tracing::{error, info};
+
+use crate::{dao::sqlite_util::SqliteClient, error::AppError};
+
+/**
+ * What happens to sessions once they are older than the retention period
+ */
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RetentionMode {
+    // move sessions, their players and comments into the history tables
+    Archive,
+    Delete,
+}
+
+impl FromStr for RetentionMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "archive" => Ok(RetentionMode::Archive),
+            "delete" => Ok(RetentionMode::Delete),
+            _ => Err(anyhow!("unknown retention mode: {s}")),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RetentionPolicy {
+    pub mode: RetentionMode,
+    // sessions that ended more than this many days ago are cleaned up
+    pub days: u32,
+    // how often the cleanup runs
+    pub interval: Duration,
+}
+
+impl RetentionPolicy {
+    // cleans up once, returns how many sessions were archived or deleted
+    pub async fn apply(&self, client: &SqliteClient) -> Result<u64, AppError> {
+        let cutoff = Utc::now() - chrono::Duration::days(self.days.into());
+        match self.mode {
+            RetentionMode::Archive => client.archive_sessions_ended_before(cutoff).await,
+            RetentionMode::Delete => client.delete_sessions_ended_before(cutoff).await,
+        }
+    }
+
+    /**
+     * Runs the policy in the background every interval, starting right away
+     */
+    pub fn spawn(self, client: SqliteClient) -> tokio::task::JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(self.interval);
+            loop {
+                ticker.tick().await;
+                match self.apply(&client).await {
+                    Ok(0) => {}
+                    Ok(n) => info!(sessions = n, mode = ?self.mode, "retention cleanup done"),
+                    Err(e) => error!("retention cleanup failed: {:?}", e),
+                }
+            }
+        })
+    }
+}
@@ -0,0 +1,64 @@
+use std::str::FromStr;
+
+use anyhow::{anyhow, Result};
+use axum::{body::Body, http::Request};
+use tracing::Span;
+use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, EnvFilter, Layer};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LogFormat {
+    // one JSON object per line, for log collectors
+    Json,
+    // human readable, for development
+    Pretty,
+}
+
+impl FromStr for LogFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "json" => Ok(LogFormat::Json),
+            "pretty" => Ok(LogFormat::Pretty),
+            _ => Err(anyhow!("unknown log format: {s}")),
+        }
+    }
+}
+
+/**
+ * Installs the global tracing subscriber. `filter` uses the RUST_LOG syntax and only applies to
+ * the logs, query metrics see every query span
+ */
+pub fn init(filter: &str, format: LogFormat) {
+    let filter = EnvFilter::try_new(filter).unwrap_or_else(|_| EnvFilter::new("info"));
+    let logs = match format {
+        LogFormat::Json => tracing_subscriber::fmt::layer()
+            .json()
+            .with_current_span(true)
+            .with_span_list(true)
+            .boxed(),
+        LogFormat::Pretty => tracing_subscriber::fmt::layer().boxed(),
+    };
+    tracing_subscriber::registry()
+        .with(logs.with_filter(filter))
+        .with(crate::metrics::query_layer())
+        .init();
+}
+
+/**
+ * Root span of every request, carrying the id set by SetRequestIdLayer so it shows up on every
+ * log line of the request, including the server function and query spans under it
+ */
+pub fn request_span(req: &Request<Body>) -> Span {
+    let request_id = req
+        .headers()
+        .get("x-request-id")
+        .and_then(|id| id.to_str().ok())
+        .unwrap_or_default();
+    tracing::info_span!(
+        "request",
+        method = %req.method(),
+        path = %req.uri().path(),
+        request_id,
+    )
+}